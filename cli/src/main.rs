@@ -0,0 +1,287 @@
+//! Render parts from MIDI files and REAPER projects without REAPER.
+//!
+//! ```text
+//! rea-score song.rpp --track Flute --bars 5-12 -o flute.pdf
+//! rea-score song.rpp --track Flute --part -o flute-part.pdf
+//! ```
+use std::{error::Error, ops::RangeInclusive, path::PathBuf};
+
+use clap::{Parser, ValueEnum};
+use rea_score::{
+    dom::{
+        dynamics::DynamicsInference, from_rpp, from_rpp_in_bars, from_smf,
+        from_smf_in_bars, Part, Score, StaffGrouping,
+    },
+    lilypond_render::{
+        preview_source, render_source, score_source,
+        set_offline_render_settings, OutputFormat, RenderSettings,
+        RendersToLilypond,
+    },
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "rea-score", version, about)]
+struct Args {
+    /// .mid or .rpp file.
+    input: PathBuf,
+    /// Output file. Format is taken from extension: ly, pdf, png or
+    /// svg.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Track name or 1-based index among tracks with notes. If
+    /// omitted, all tracks are rendered as score.
+    #[arg(short, long)]
+    track: Option<String>,
+    /// How staves of score are grouped.
+    #[arg(long, value_enum, default_value_t = Group::StaffGroup)]
+    group: Group,
+    /// Bar range, e.g. `5-12` or `5`.
+    #[arg(short, long, value_parser = parse_bars)]
+    bars: Option<RangeInclusive<u32>>,
+    /// Render on the preview paper of the given size in pixels, e.g.
+    /// `1200x400`. By default the default LilyPond paper is used.
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(u32, u32)>,
+    #[arg(long)]
+    dpi: Option<u32>,
+    /// Place dynamics, inferred from note velocities.
+    #[arg(long)]
+    infer_dynamics: bool,
+    /// With `--infer-dynamics`, write velocity ramps as hairpins.
+    #[arg(long, requires = "infer_dynamics")]
+    hairpins: bool,
+    /// Render single track as instrumental part, where empty measures
+    /// are compressed into multi-measure rests. Score is always
+    /// rendered expanded.
+    #[arg(long)]
+    part: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Group {
+    StaffGroup,
+    ChoirStaff,
+    PianoStaff,
+}
+impl From<Group> for StaffGrouping {
+    fn from(group: Group) -> Self {
+        match group {
+            Group::StaffGroup => Self::StaffGroup,
+            Group::ChoirStaff => Self::ChoirStaff,
+            Group::PianoStaff => Self::PianoStaff,
+        }
+    }
+}
+
+fn parse_bars(arg: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("Can not parse bar number: {s}"))
+    };
+    let range = match arg.split_once('-') {
+        None => parse(arg)?..=parse(arg)?,
+        Some((start, end)) => parse(start)?..=parse(end)?,
+    };
+    if *range.start() == 0 || range.start() > range.end() {
+        return Err(format!("Invalid bar range: {arg}"));
+    }
+    Ok(range)
+}
+
+fn parse_size(arg: &str) -> Result<(u32, u32), String> {
+    let (w, h) = arg
+        .split_once('x')
+        .ok_or(format!("Size should be like 1200x400, got: {arg}"))?;
+    match (w.parse(), h.parse()) {
+        (Ok(w), Ok(h)) => Ok((w, h)),
+        _ => Err(format!("Can not parse size: {arg}")),
+    }
+}
+
+fn read_parts(args: &Args) -> Result<Vec<Part>, Box<dyn Error>> {
+    let ext = args
+        .input
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let bars = args.bars.clone();
+    match (ext.as_deref(), bars) {
+        (Some("mid") | Some("midi"), None) => from_smf(&args.input),
+        (Some("mid") | Some("midi"), Some(bars)) => {
+            from_smf_in_bars(&args.input, bars)
+        }
+        (Some("rpp"), None) => from_rpp(&args.input),
+        (Some("rpp"), Some(bars)) => from_rpp_in_bars(&args.input, bars),
+        _ => Err(format!(
+            "Unsupported input file: {}. Expected .mid or .rpp",
+            args.input.display()
+        )
+        .into()),
+    }
+}
+
+fn select_part(
+    mut parts: Vec<Part>,
+    track: &str,
+) -> Result<Part, Box<dyn Error>> {
+    let idx = match parts
+        .iter()
+        .position(|part| part.name.as_deref() == Some(track))
+    {
+        Some(idx) => idx,
+        None => match track.parse::<usize>() {
+            Ok(idx) if idx >= 1 && idx <= parts.len() => idx - 1,
+            _ => {
+                let names = parts
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, part)| {
+                        format!(
+                            "{}: {}",
+                            idx + 1,
+                            part.name.clone().unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return Err(format!(
+                    "Track {track} not found. Available tracks:\n{names}"
+                )
+                .into());
+            }
+        },
+    };
+    Ok(parts.swap_remove(idx))
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let format = OutputFormat::from_path(&args.output).ok_or(format!(
+        "Unsupported output file: {}. Expected .ly, .pdf, .png or .svg",
+        args.output.display()
+    ))?;
+    if args.infer_dynamics {
+        set_offline_render_settings(RenderSettings {
+            infer_dynamics: Some(DynamicsInference {
+                hairpins: args.hairpins,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    let mut parts = read_parts(&args)?;
+    let render_part = |part: Part| match args.part {
+        true => part.render_lilypond_extracted(),
+        false => part.render_lilypond(),
+    };
+    let code = match (args.track.as_deref(), parts.len()) {
+        (_, 0) => return Err("No tracks with notes found".into()),
+        (Some(track), _) => render_part(select_part(parts, track)?),
+        (None, 1) => render_part(parts.remove(0)),
+        (None, _) => {
+            Score::from_parts(parts, args.group.into())?.render_lilypond()
+        }
+    };
+    let source = match args.size {
+        Some(size) => preview_source(code, size, args.dpi),
+        None => score_source(code),
+    };
+    render_source(source, args.output, format, args.dpi)?;
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(err) = run(args) {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{run, Args};
+
+    static PROJECT: &str = r#"<REAPER_PROJECT 0.1 "6.71/linux-x86_64" 0
+  TEMPO 60 4 4
+  <TRACK
+    NAME "Flute"
+    <ITEM
+      POSITION 0
+      LENGTH 4
+      <SOURCE MIDI
+        HASDATA 1 480 QN
+        E 0 90 3c 60
+        E 1920 80 3c 00
+      >
+    >
+  >
+>
+"#;
+
+    #[test]
+    fn test_args() {
+        let args = Args::try_parse_from([
+            "rea-score",
+            "song.rpp",
+            "-o",
+            "flute.pdf",
+            "--bars",
+            "5-12",
+            "--size",
+            "1200x400",
+        ])
+        .expect("can not parse args");
+        assert_eq!(args.bars, Some(5..=12));
+        assert_eq!(args.size, Some((1200, 400)));
+        assert!(!args.part);
+        let parse = |args: &[&str]| {
+            Args::try_parse_from(
+                ["rea-score", "song.rpp", "-o", "song.ly"]
+                    .iter()
+                    .chain(args)
+                    .copied(),
+            )
+        };
+        assert!(parse(&["--bars", "0-2"]).is_err());
+        assert!(parse(&["--bars", "3-2"]).is_err());
+        assert!(parse(&["--size", "1200"]).is_err());
+        assert!(parse(&["--hairpins"]).is_err());
+        assert!(parse(&["--infer-dynamics", "--hairpins"]).is_ok());
+    }
+
+    #[test]
+    fn test_render_to_file() {
+        let dir = tempfile::tempdir().expect("can not create temp dir");
+        let input = dir.path().join("song.rpp");
+        std::fs::write(&input, PROJECT).expect("can not write project");
+        let output = dir.path().join("song.ly");
+        let input = input.to_str().expect("bad path");
+        let args = Args::try_parse_from([
+            "rea-score",
+            input,
+            "-o",
+            output.to_str().expect("bad path"),
+        ])
+        .expect("can not parse args");
+        run(args).expect("can not render");
+        let source =
+            std::fs::read_to_string(&output).expect("can not read output");
+        // the default paper, not the preview one
+        assert!(source.starts_with("\\version \"2.24\"\n"));
+        assert!(!source.contains("set-paper-size"));
+        assert!(source.contains("c'1"));
+
+        let mp3 = dir.path().join("song.mp3");
+        let args = Args::try_parse_from([
+            "rea-score",
+            input,
+            "-o",
+            mp3.to_str().expect("bad path"),
+        ])
+        .expect("can not parse args");
+        assert!(run(args).is_err());
+    }
+}
@@ -1,13 +1,20 @@
 use std::{collections::HashMap, str::FromStr};
 
 use fraction::Fraction;
-use rea_rs::{PluginContext, Reaper, Timer};
+use rea_rs::{
+    MessageBoxType, MessageBoxValue, PluginContext, Reaper, Timer,
+};
 use rea_score::{
     dom::midi_parse::{
+        fit_tuplet_to_selected,
         notations_to_first_and_last_selected,
-        notations_to_first_selected,
+        notations_to_first_selected, notations_to_selected,
+    },
+    notation::{
+        chord_notations::{Articulation, ChordNotations, Hairpin},
+        NotationType,
     },
-    notation::NotationType,
+    primitives::GraceType,
 };
 use reaper_imgui::{
     Context, ImGui, KeyBinding, KeyCode, KeyModifier,
@@ -81,6 +88,39 @@ fn make_key_bindings() -> HashMap<KeyBinding, Box<dyn Fn()>> {
         KeyBinding::new([KeyModifier::Ctrl], KeyCode::T),
         Box::new(make_tuplet),
     );
+    kb.insert(
+        KeyBinding::new(
+            [KeyModifier::Ctrl, KeyModifier::Shift],
+            KeyCode::A,
+        ),
+        Box::new(apply_articulation),
+    );
+    kb.insert(
+        KeyBinding::new([KeyModifier::Ctrl], KeyCode::L),
+        Box::new(|| make_slur(false)),
+    );
+    kb.insert(
+        KeyBinding::new(
+            [KeyModifier::Ctrl, KeyModifier::Shift],
+            KeyCode::L,
+        ),
+        Box::new(|| make_slur(true)),
+    );
+    kb.insert(
+        KeyBinding::new([KeyModifier::Ctrl], KeyCode::H),
+        Box::new(make_hairpin),
+    );
+    kb.insert(
+        KeyBinding::new([KeyModifier::Ctrl], KeyCode::G),
+        Box::new(make_grace),
+    );
+    kb.insert(
+        KeyBinding::new(
+            [KeyModifier::Ctrl, KeyModifier::Shift],
+            KeyCode::T,
+        ),
+        Box::new(detect_tuplet),
+    );
 
     kb
 }
@@ -113,23 +153,211 @@ fn apply_dynamics() {
     }
 }
 
-fn make_tuplet() {
+/// Articulation to every selected note.
+fn apply_articulation() {
     let rpr = Reaper::get();
-    let rate_str = match rpr.get_user_inputs(
-        "Type tuplet rate in form of '3/2' for regular triplet",
-        vec!["rate"],
+    let art_str = match rpr.get_user_inputs(
+        "Type articulation name (e.g. 'staccato') or LilyPond shorthand",
+        vec!["articulation"],
         None,
     ) {
-        Ok(i) => {
-            i.get("rate").expect("should be value here").to_string()
+        Ok(i) => i
+            .get("articulation")
+            .expect("should be value here")
+            .to_string(),
+        Err(_) => return,
+    };
+    if art_str.is_empty() {
+        return;
+    }
+    let art = match Articulation::from_str(art_str.as_str()) {
+        Ok(art) => art,
+        Err(err) => {
+            return error_box(
+                "Wrong articulation",
+                format!("{}", err),
+            );
+        }
+    };
+    match notations_to_selected(vec![NotationType::Chord(
+        ChordNotations::Articulation(art),
+    )]) {
+        Ok(()) => (),
+        Err(err) => {
+            return error_box("Error!", format!("{}", err));
+        }
+    }
+}
+
+/// Slur from the first to the last selected note.
+fn make_slur(phrasing: bool) {
+    let (start, end) = match phrasing {
+        false => {
+            (ChordNotations::SlurStart, ChordNotations::SlurEnd)
         }
+        true => (
+            ChordNotations::PhrasingSlurStart,
+            ChordNotations::PhrasingSlurEnd,
+        ),
+    };
+    match notations_to_first_and_last_selected(
+        vec![NotationType::Chord(start)],
+        vec![NotationType::Chord(end)],
+    ) {
+        Ok(()) => (),
+        Err(err) => {
+            return error_box("Error!", format!("{}", err));
+        }
+    }
+}
+
+/// Hairpin from the first to the last selected note.
+///
+/// If end dynamics is typed, it is placed on the last note and
+/// terminates hairpin. Otherwise, hairpin ends explicitly.
+fn make_hairpin() {
+    let rpr = Reaper::get();
+    let inputs = match rpr.get_user_inputs(
+        "Type hairpin ('<' or '>') and optional end dynamics",
+        vec!["hairpin", "end dynamics"],
+        None,
+    ) {
+        Ok(i) => i,
         Err(_) => return,
     };
+    let hairpin_str =
+        inputs.get("hairpin").expect("should be value here");
+    if hairpin_str.is_empty() {
+        return;
+    }
+    let hairpin = match Hairpin::from_str(hairpin_str.as_str()) {
+        Ok(hairpin) => hairpin,
+        Err(err) => {
+            return error_box("Wrong hairpin", format!("{}", err));
+        }
+    };
+    let end = match inputs
+        .get("end dynamics")
+        .expect("should be value here")
+        .as_str()
+    {
+        "" => ChordNotations::HairpinEnd,
+        dynamics => ChordNotations::Dynamics(dynamics.to_string()),
+    };
+    match notations_to_first_and_last_selected(
+        vec![NotationType::Chord(ChordNotations::Hairpin(hairpin))],
+        vec![NotationType::Chord(end)],
+    ) {
+        Ok(()) => (),
+        Err(err) => {
+            return error_box("Error!", format!("{}", err));
+        }
+    }
+}
+
+/// Mark all selected notes as grace notes.
+fn make_grace() {
+    let rpr = Reaper::get();
+    let grace_str = match rpr.get_user_inputs(
+        "Type grace type: grace (default), acciaccatura, appoggiatura \
+        or after_grace",
+        vec!["grace type"],
+        None,
+    ) {
+        Ok(i) => i
+            .get("grace type")
+            .expect("should be value here")
+            .to_string(),
+        Err(_) => return,
+    };
+    let grace_type = match grace_str.as_str() {
+        "" => GraceType::Grace,
+        s => match GraceType::from_str(s) {
+            Ok(grace_type) => grace_type,
+            Err(err) => {
+                return error_box(
+                    "Wrong grace type",
+                    format!("{}", err),
+                );
+            }
+        },
+    };
+    match notations_to_selected(vec![NotationType::Chord(
+        ChordNotations::Grace(grace_type),
+    )]) {
+        Ok(()) => (),
+        Err(err) => {
+            return error_box("Error!", format!("{}", err));
+        }
+    }
+}
+
+/// Suggest tuplet, that fits selected notes, and make it, if user
+/// accepts the suggestion.
+fn detect_tuplet() {
+    let fit = match fit_tuplet_to_selected() {
+        Ok(Some(fit)) => fit,
+        Ok(None) => {
+            return error_box(
+                "No tuplet detected",
+                "Selected notes fit straight grid better.",
+            );
+        }
+        Err(err) => {
+            return error_box("Error!", format!("{}", err));
+        }
+    };
+    let answer = Reaper::get().show_message_box(
+        "Tuplet detected",
+        format!(
+            "Notes fit tuplet {} with confidence {:.0}%.\nMake tuplet?",
+            fit.rate(),
+            fit.confidence * 100.0
+        ),
+        MessageBoxType::YesNo,
+    );
+    if !matches!(answer, Ok(MessageBoxValue::Yes)) {
+        return;
+    }
+    match notations_to_first_and_last_selected(
+        vec![NotationType::Chord(ChordNotations::TupletRate(vec![
+            fit.rate(),
+        ]))],
+        vec![NotationType::Chord(ChordNotations::TupletEnd(1))],
+    ) {
+        Ok(()) => (),
+        Err(err) => {
+            return error_box("Error!", format!("{}", err));
+        }
+    }
+}
+
+/// Make tuplet from the first to the last selected note.
+///
+/// Several comma-separated rates open nested tuplets on the first
+/// note, the outer first. The last note closes the given amount of
+/// the innermost tuplets, all of them by default.
+fn make_tuplet() {
+    let rpr = Reaper::get();
+    let inputs = match rpr.get_user_inputs(
+        "Type tuplet rate in form of '3/2' for regular triplet, or \
+        '5/4,3/2' for nested tuplets",
+        vec!["rate", "tuplets to close"],
+        None,
+    ) {
+        Ok(i) => i,
+        Err(_) => return,
+    };
+    let rate_str = inputs.get("rate").expect("should be value here");
     if rate_str.is_empty() {
         return;
     }
-    let rate = match Fraction::from_str(rate_str.as_str()) {
-        Ok(rate) => rate,
+    let rates = match rate_str
+        .split(',')
+        .map(|rate| Fraction::from_str(rate.trim()))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(rates) => rates,
         Err(err) => {
             return error_box(
                 "Wrong rate string",
@@ -141,15 +369,29 @@ fn make_tuplet() {
             );
         }
     };
-    match notations_to_first_and_last_selected(vec![NotationType::Chord(
-        rea_score::notation::chord_notations::ChordNotations::TupletRate(
-            rate,
-        ),
-    )],vec![NotationType::Chord(
-        rea_score::notation::chord_notations::ChordNotations::TupletEnd)]) {
+    let levels = match inputs
+        .get("tuplets to close")
+        .expect("should be value here")
+        .as_str()
+    {
+        "" => rates.len() as u8,
+        levels => match levels.parse() {
+            Ok(levels) => levels,
+            Err(err) => {
+                return error_box(
+                    "Wrong amount of tuplets to close",
+                    format!("{}", err),
+                );
+            }
+        },
+    };
+    match notations_to_first_and_last_selected(
+        vec![NotationType::Chord(ChordNotations::TupletRate(rates))],
+        vec![NotationType::Chord(ChordNotations::TupletEnd(levels))],
+    ) {
         Ok(()) => (),
         Err(err) => {
-            return error_box("Error!", format!("{}",err));
+            return error_box("Error!", format!("{}", err));
         }
     }
 }
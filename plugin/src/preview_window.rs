@@ -9,8 +9,9 @@ use std::{
 use rea_rs::{
     ExtState, Measure, PluginContext, Position, Reaper, Timer,
 };
-use rea_score::lilypond_render::{
-    preview_string, RendersToLilypond,
+use rea_score::{
+    dom::{Score, ScoreSource, StaffGrouping},
+    lilypond_render::{preview_string, RendersToLilypond},
 };
 use reaper_imgui::{
     Context, ContextFlags, Dock, ImGui, ImageHandle, SetWidth, Size,
@@ -146,9 +147,23 @@ impl PreviewWindow {
                 Ok(value) => value,
                 Err(value) => return value,
             };
-        let code = rea_score::dom::parse_track_in_bounds(
-            track, start_pos, end_pos,
-        );
+        let extracted = Score::global_render_settings().extracted_part;
+        let code = match pr.n_selected_tracks() {
+            1 => rea_score::dom::parse_track_in_bounds(
+                track, start_pos, end_pos,
+            )
+            .map(|part| match extracted {
+                true => part.render_lilypond_extracted(),
+                false => part.render_lilypond(),
+            }),
+            _ => Score::parse_in_bounds(
+                &ScoreSource::SelectedTracks,
+                start_pos,
+                end_pos,
+                StaffGrouping::default(),
+            )
+            .map(|score| score.render_lilypond()),
+        };
         let code = match code {
             Ok(c) => c,
             Err(err) => {
@@ -162,7 +177,6 @@ impl PreviewWindow {
             .state
             .get()
             .expect("can not load Preview Window state");
-        let code = code.render_lilypond();
         println!("Lily code:\n{code}");
         state.code = code;
         self.state.set(state);
@@ -0,0 +1,368 @@
+//! Inference of dynamics from velocities of recorded notes.
+//!
+//! Velocities of note attacks are smoothed and mapped to dynamic
+//! marks. Mark is placed only, where the level changes. Dynamics,
+//! written explicitly in ReaScore notations, always win: they reset
+//! the current level and are never overwritten. Nothing is inferred
+//! inside explicit hairpins.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    notation::{
+        chord_notations::{ChordNotations, Hairpin},
+        NotationType,
+    },
+    primitives::{EventInfo, EventType},
+};
+
+use super::{Part, Voice};
+
+/// Least amount of attacks with steadily changing velocity, that
+/// makes hairpin.
+pub const MIN_HAIRPIN_ATTACKS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DynamicLevel {
+    PP,
+    P,
+    MP,
+    MF,
+    F,
+    FF,
+}
+impl DynamicLevel {
+    pub fn from_velocity(velocity: u8) -> Self {
+        match velocity {
+            0..=39 => Self::PP,
+            40..=55 => Self::P,
+            56..=71 => Self::MP,
+            72..=87 => Self::MF,
+            88..=103 => Self::F,
+            _ => Self::FF,
+        }
+    }
+    /// Name, as it is written in [ChordNotations::Dynamics].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PP => "pp",
+            Self::P => "p",
+            Self::MP => "mp",
+            Self::MF => "mf",
+            Self::F => "f",
+            Self::FF => "ff",
+        }
+    }
+    /// Level of the written dynamics, if it is one of the known.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim_start_matches('\\');
+        [Self::PP, Self::P, Self::MP, Self::MF, Self::F, Self::FF]
+            .into_iter()
+            .find(|level| level.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicsInference {
+    /// Amount of neighbour attacks, velocities are averaged over.
+    pub smoothing: usize,
+    /// Put hairpins on steady velocity ramps, instead of the every
+    /// intermediate dynamics.
+    pub hairpins: bool,
+}
+impl Default for DynamicsInference {
+    fn default() -> Self {
+        Self {
+            smoothing: 3,
+            hairpins: false,
+        }
+    }
+}
+
+/// Dynamics, already written to the attack.
+#[derive(Debug, Default)]
+struct Written {
+    dynamics: Option<String>,
+    hairpin_start: bool,
+    hairpin_end: bool,
+}
+impl Written {
+    fn from_event(event: &EventInfo) -> Self {
+        let notations = match &event.event {
+            EventType::Note(note) => note.chord_notations(),
+            EventType::Chord(chord) => chord.chord_notations(),
+            _ => return Self::default(),
+        };
+        let mut written = Self::default();
+        for notation in notations {
+            match notation {
+                ChordNotations::Dynamics(d) if d != "!" => {
+                    written.dynamics = Some(d.clone())
+                }
+                ChordNotations::Hairpin(_) => written.hairpin_start = true,
+                ChordNotations::HairpinEnd => written.hairpin_end = true,
+                _ => (),
+            }
+        }
+        written
+    }
+    fn is_empty(&self) -> bool {
+        self.dynamics.is_none() && !self.hairpin_start && !self.hairpin_end
+    }
+}
+
+/// Attacks, covered by explicit hairpins.
+///
+/// Hairpin lasts until its end or the next written dynamics, as in
+/// LilyPond. Hairpin without end lasts until the last attack.
+fn explicit_hairpins(written: &[Written]) -> Vec<bool> {
+    let mut open = false;
+    written
+        .iter()
+        .map(|attack| {
+            let covered = open || attack.hairpin_start || attack.hairpin_end;
+            if attack.hairpin_end || attack.dynamics.is_some() {
+                open = false;
+            }
+            if attack.hairpin_start {
+                open = true;
+            }
+            covered
+        })
+        .collect()
+}
+
+fn velocity(event: &EventInfo) -> Option<u8> {
+    match &event.event {
+        EventType::Note(note) => note.velocity(),
+        EventType::Chord(chord) => chord.velocity(),
+        _ => None,
+    }
+}
+
+impl Voice {
+    /// Place dynamics, inferred from velocities of notes.
+    pub fn infer_dynamics(&mut self, inference: &DynamicsInference) {
+        let mut attacks = self
+            .note_events_mut()
+            .into_iter()
+            .filter(|event| velocity(event).is_some())
+            .collect::<Vec<_>>();
+        let velocities = attacks
+            .iter()
+            .map(|ev| velocity(ev).expect("attacks have velocities"))
+            .collect::<Vec<_>>();
+        let written = attacks
+            .iter()
+            .map(|ev| Written::from_event(ev))
+            .collect::<Vec<_>>();
+        let marks = infer_marks(&velocities, &written, inference);
+        for (event, marks) in attacks.iter_mut().zip(marks) {
+            for mark in marks {
+                event
+                    .push_notation(NotationType::Chord(mark))
+                    .expect("Can not apply dynamics to attack");
+            }
+        }
+    }
+}
+
+impl Part {
+    /// Place dynamics, inferred from velocities, to every voice.
+    pub fn infer_dynamics(&mut self, inference: &DynamicsInference) {
+        self.staves
+            .iter_mut()
+            .flat_map(|staff| staff.voices.iter_mut())
+            .for_each(|voice| voice.infer_dynamics(inference));
+    }
+}
+
+/// Marks for every attack.
+fn infer_marks(
+    velocities: &[u8],
+    written: &[Written],
+    inference: &DynamicsInference,
+) -> Vec<Vec<ChordNotations>> {
+    let smoothed = smooth(velocities, inference.smoothing);
+    let levels = smoothed
+        .iter()
+        .map(|v| DynamicLevel::from_velocity(*v))
+        .collect::<Vec<_>>();
+    let dynamics =
+        |level: DynamicLevel| ChordNotations::Dynamics(level.name().into());
+    let covered = explicit_hairpins(written);
+    let blocked = written
+        .iter()
+        .zip(covered.iter())
+        .map(|(attack, covered)| *covered || !attack.is_empty())
+        .collect::<Vec<_>>();
+    let mut marks = vec![Vec::new(); velocities.len()];
+    let mut current: Option<DynamicLevel> = None;
+    let mut idx = 0;
+    while idx < velocities.len() {
+        if let Some(name) = &written[idx].dynamics {
+            current = DynamicLevel::from_name(name).or(current);
+            idx += 1;
+            continue;
+        }
+        if blocked[idx] {
+            // level after explicit hairpin is unknown.
+            current = None;
+            idx += 1;
+            continue;
+        }
+        if inference.hairpins {
+            let end = ramp_end(&smoothed, &blocked, idx);
+            if end + 1 - idx >= MIN_HAIRPIN_ATTACKS
+                && levels[end] != levels[idx]
+            {
+                if current != Some(levels[idx]) {
+                    marks[idx].push(dynamics(levels[idx]));
+                }
+                let hairpin = match smoothed[end] > smoothed[idx] {
+                    true => Hairpin::Crescendo,
+                    false => Hairpin::Decrescendo,
+                };
+                marks[idx].push(ChordNotations::Hairpin(hairpin));
+                marks[end].push(dynamics(levels[end]));
+                current = Some(levels[end]);
+                idx = end + 1;
+                continue;
+            }
+        }
+        if current != Some(levels[idx]) {
+            marks[idx].push(dynamics(levels[idx]));
+            current = Some(levels[idx]);
+        }
+        idx += 1;
+    }
+    marks
+}
+
+/// Average velocities over `window` neighbour attacks.
+fn smooth(velocities: &[u8], window: usize) -> Vec<u8> {
+    let half = window.max(1) / 2;
+    (0..velocities.len())
+        .map(|idx| {
+            let start = idx.saturating_sub(half);
+            let end = (idx + half).min(velocities.len() - 1);
+            let slice = &velocities[start..=end];
+            (slice.iter().map(|v| *v as u32).sum::<u32>() / slice.len() as u32)
+                as u8
+        })
+        .collect()
+}
+
+/// Last index of steady velocity change, starting from `start`.
+///
+/// Ramp never enters `blocked` attacks: written dynamics and explicit
+/// hairpins.
+fn ramp_end(smoothed: &[u8], blocked: &[bool], start: usize) -> usize {
+    let mut end = start;
+    let rising = match smoothed.get(start + 1) {
+        Some(next) if *next != smoothed[start] => *next > smoothed[start],
+        _ => return start,
+    };
+    while end + 1 < smoothed.len() && !blocked[end + 1] {
+        let (a, b) = (smoothed[end], smoothed[end + 1]);
+        if a == b || (b > a) != rising {
+            break;
+        }
+        end += 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use crate::{
+        dom::{Voice, VoiceRenderOptions},
+        notation::chord_notations::ChordNotations,
+        primitives::{
+            AbsolutePosition, EventInfo, EventType, Length, MeasureInfo, Note,
+            Pitch, RelativePosition, TimeMap,
+        },
+    };
+
+    use super::{infer_marks, DynamicsInference, Written};
+
+    fn voice(velocities: &[u8]) -> Voice {
+        let time_map = Arc::new(TimeMap::new(
+            vec![
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+            ],
+            AbsolutePosition::from(0.0),
+        ));
+        let mut voice = Voice::from(time_map);
+        for (idx, velocity) in velocities.iter().enumerate() {
+            let mut note = Note::new(Pitch::from_midi(60, None, None));
+            note.set_velocity(*velocity);
+            voice
+                .insert_event(EventInfo::new(
+                    RelativePosition::new(
+                        idx as u32 / 4 + 1,
+                        Fraction::new(idx as u64 % 4, 4_u64),
+                    ),
+                    Length::from(Fraction::new(1_u64, 4_u64)),
+                    EventType::Note(note),
+                ))
+                .unwrap();
+        }
+        voice
+    }
+
+    #[test]
+    fn test_infer_dynamics() {
+        let velocities = [50, 50, 60, 75, 90, 100, 100, 100];
+        let mut inference = DynamicsInference {
+            smoothing: 1,
+            hairpins: false,
+        };
+        let mut plain = voice(&velocities);
+        plain.infer_dynamics(&inference);
+        let rendered = plain.render_measures(VoiceRenderOptions::default());
+        assert!(rendered.contains(r"c'4\p c'4 c'4\mp c'4\mf |"));
+        assert!(rendered.contains(r"c'4\f c'4 c'4 c'4 |"));
+
+        inference.hairpins = true;
+        let mut ramp = voice(&velocities);
+        ramp.infer_dynamics(&inference);
+        let rendered = ramp.render_measures(VoiceRenderOptions::default());
+        assert!(rendered.contains(r"c'4\p c'4\< c'4 c'4 |"));
+        assert!(rendered.contains(r"c'4 c'4\f c'4 c'4 |"));
+    }
+    #[test]
+    fn test_explicit_hairpin_span() {
+        let velocities = [50, 50, 60, 75, 90, 100, 100, 100];
+        let mut written = (0..velocities.len())
+            .map(|_| Written::default())
+            .collect::<Vec<_>>();
+        written[2].hairpin_start = true;
+        written[5].hairpin_end = true;
+        let inference = DynamicsInference {
+            smoothing: 1,
+            hairpins: true,
+        };
+        let marks = infer_marks(&velocities, &written, &inference);
+        let dynamics =
+            |name: &str| vec![ChordNotations::Dynamics(name.into())];
+        assert_eq!(
+            marks,
+            vec![
+                dynamics("p"),
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                dynamics("f"),
+                vec![],
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Lifting grace notes out of the timeline.
+//!
+//! Grace notes are either marked by `grace` notation, or detected by
+//! their length, if [RenderSettings::grace_threshold] is set: very
+//! short note, immediately followed by another note of the same voice
+//! on the beat, becomes acciaccatura. Otherwise, it would take a tiny
+//! fragment of the measure, which can not be rendered.
+//!
+//! [RenderSettings::grace_threshold]:
+//! crate::lilypond_render::RenderSettings::grace_threshold
+use fraction::Fraction;
+
+use crate::primitives::{
+    Chord, EventType, GraceGroup, GraceType, Length, Measure,
+    RelativePosition, TimeMap,
+};
+
+use super::midi_parse::ParsedEvent;
+
+/// Remove grace notes from events and attach them to the main
+/// events of the same channel: the following one, or the preceding
+/// one for after-grace.
+///
+/// Notes, shorter than `threshold`, are considered as grace notes,
+/// if the next note starts on the beat, not later than `threshold`
+/// after their end. Grace notes without main event stay in the
+/// timeline.
+pub fn lift_grace_notes(
+    mut events: Vec<ParsedEvent>,
+    threshold: Option<Fraction>,
+    time_map: &TimeMap,
+) -> Result<Vec<ParsedEvent>, String> {
+    let mut grace_types = events
+        .iter_mut()
+        .map(|ev| ev.event.event.take_grace_notation())
+        .collect::<Vec<_>>();
+    let starts = events
+        .iter()
+        .map(|ev| {
+            time_map
+                .pos_absolute_from_relative(&ev.event.position)
+                .get()
+        })
+        .collect::<Vec<_>>();
+    let ends = events
+        .iter()
+        .zip(starts.iter())
+        .map(|(ev, start)| *start + ev.event.length.get())
+        .collect::<Vec<_>>();
+    if let Some(threshold) = threshold {
+        for idx in 0..events.len() {
+            if grace_types[idx].is_some()
+                || events[idx].event.length.get() >= threshold
+            {
+                continue;
+            }
+            let latest = ends[idx] + threshold;
+            if (0..events.len()).any(|other| {
+                events[other].channel == events[idx].channel
+                    && starts[other] > starts[idx]
+                    && starts[other] <= latest
+                    && is_on_beat(&events[other].event.position, time_map)
+            }) {
+                grace_types[idx] = Some(GraceType::Acciaccatura);
+            }
+        }
+    }
+
+    // (main event, grace type, grace events)
+    let mut groups: Vec<(usize, GraceType, Vec<usize>)> = Vec::new();
+    for (idx, grace_type) in grace_types.iter().enumerate() {
+        let grace_type = match grace_type {
+            Some(grace_type) => *grace_type,
+            None => continue,
+        };
+        let candidates = (0..events.len()).filter(|main| {
+            grace_types[*main].is_none()
+                && events[*main].channel == events[idx].channel
+        });
+        let main = match grace_type.is_after() {
+            false => candidates
+                .filter(|main| starts[*main] > starts[idx])
+                .min_by_key(|main| starts[*main]),
+            true => candidates
+                .filter(|main| starts[*main] < starts[idx])
+                .max_by_key(|main| starts[*main]),
+        };
+        let main = match main {
+            Some(main) => main,
+            None => continue,
+        };
+        match groups
+            .iter_mut()
+            .find(|(m, tp, _)| *m == main && *tp == grace_type)
+        {
+            Some((_, _, graces)) => graces.push(idx),
+            None => groups.push((main, grace_type, vec![idx])),
+        }
+    }
+
+    let mut lifted = Vec::new();
+    for (main, grace_type, graces) in groups.iter() {
+        if !grace_type.is_after() {
+            // the previous note sounds until the main one, instead of
+            // leaving a tiny rest after it.
+            let (first, main_start) = (starts[graces[0]], starts[*main]);
+            for prev in 0..events.len() {
+                if grace_types[prev].is_none()
+                    && events[prev].channel == events[*main].channel
+                    && ends[prev] >= first
+                    && ends[prev] < main_start
+                {
+                    events[prev].event.length =
+                        Length::from(main_start - starts[prev]);
+                }
+            }
+        }
+        let mut grace_events: Vec<(Fraction, EventType)> = Vec::new();
+        for idx in graces {
+            let event = events[*idx].event.event.clone();
+            match grace_events.last_mut() {
+                Some((start, chord)) if *start == starts[*idx] => {
+                    *chord = EventType::Chord(
+                        Chord::new().push(chord.clone())?.push(event)?,
+                    )
+                }
+                _ => grace_events.push((starts[*idx], event)),
+            }
+        }
+        let group = GraceGroup::new(
+            *grace_type,
+            grace_events.into_iter().map(|(_, ev)| ev).collect(),
+        );
+        events[*main].event.event.push_grace(group)?;
+        lifted.extend(graces.iter().copied());
+    }
+    Ok(events
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| !lifted.contains(idx))
+        .map(|(_, ev)| ev)
+        .collect())
+}
+
+/// If position is the start of beat (or beat group) of its measure.
+fn is_on_beat(position: &RelativePosition, time_map: &TimeMap) -> bool {
+    let info = time_map.get_measure_info(position.get_measure_index());
+    let position = position.position_quantized();
+    Measure::from(&info)
+        .beats()
+        .iter()
+        .any(|(start, _)| *start == position)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use crate::{
+        dom::{midi_parse::ParsedEvent, part_from_events},
+        lilypond_render::RendersToLilypond,
+        notation::{chord_notations::ChordNotations, NotationType},
+        primitives::{
+            AbsolutePosition, EventInfo, EventType, GraceType, Length,
+            MeasureInfo, Note, Pitch, RelativePosition, TimeMap,
+        },
+    };
+
+    use super::lift_grace_notes;
+
+    fn note(
+        midi: u8,
+        position: Fraction,
+        length: Fraction,
+        notations: Vec<NotationType>,
+    ) -> ParsedEvent {
+        ParsedEvent::new(
+            1,
+            midi,
+            EventInfo::new(
+                RelativePosition::new(1, position),
+                Length::from(length),
+                EventType::Note(Note::new(Pitch::from_midi(midi, None, None))),
+            ),
+            notations,
+        )
+        .apply_single_notations()
+    }
+
+    #[test]
+    fn test_lift_grace_notes() {
+        let time_map = Arc::new(TimeMap::new(
+            vec![MeasureInfo::new(1, TimeSignature::new(4, 4))],
+            AbsolutePosition::from(0.0),
+        ));
+        let frac = |n: u64, d: u64| Fraction::new(n, d);
+        let after_grace = vec![NotationType::Chord(ChordNotations::Grace(
+            GraceType::AfterGrace,
+        ))];
+        let events = vec![
+            note(60, frac(0, 1), frac(31, 128), vec![]),
+            note(62, frac(31, 128), frac(1, 128), vec![]),
+            note(64, frac(1, 4), frac(1, 2), vec![]),
+            note(65, frac(3, 4), frac(1, 8), after_grace.clone()),
+            note(67, frac(7, 8), frac(1, 8), after_grace),
+        ];
+        let threshold = Some(frac(1, 64));
+        let events = lift_grace_notes(events, threshold, &time_map).unwrap();
+        let part =
+            part_from_events(events.into_iter(), time_map.clone()).unwrap();
+        assert!(part.render_lilypond().contains(
+            r"c'4 \acciaccatura { d'8 } \afterGrace e'2 { f'16 g'16 } r4 |"
+        ));
+
+        // short note before the off-beat one stays in the timeline.
+        let events = vec![
+            note(60, frac(0, 1), frac(1, 8), vec![]),
+            note(62, frac(1, 8), frac(1, 128), vec![]),
+            note(64, frac(17, 128), frac(1, 8), vec![]),
+        ];
+        assert_eq!(
+            lift_grace_notes(events, threshold, &time_map)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
@@ -1,14 +1,18 @@
 use rea_rs::{
     errors::ReaperError, MidiEvent, MidiMessage, NotationMessage,
-    NoteOnMessage, Position, ProbablyMutable, RawMidiMessage, Take,
+    NoteOnMessage, Position, ProbablyMutable, RawMidiMessage, Reaper, Take,
 };
 
 use crate::{
-    dom::get_edited_midi,
+    dom::{
+        get_edited_midi,
+        tuplets::{beat_divisions, fit_tuplet, units, TupletFit},
+        with_edited_take,
+    },
     notation::{message::MidiFuncs, NotationType},
     primitives::{
-        position::Distance, AbsolutePosition, EventInfo, EventType,
-        Note, Pitch, RelativePosition,
+        position::Distance, AbsolutePosition, EventInfo, EventType, Measure,
+        Note, Pitch, RelativePosition, TimeMap,
     },
 };
 
@@ -45,12 +49,13 @@ pub fn parse_events<'a, T: ProbablyMutable>(
                 Position::from_ppq(note.end_in_ppq, take),
             ));
         let length = position.get_distance_as_length(&end_pos, None);
+        let mut parsed_note =
+            Note::new(Pitch::from_midi(note.note, None, None));
+        parsed_note.set_velocity(note.velocity);
         let ev = EventInfo::new(
             position,
             length,
-            EventType::Note(Note::new(Pitch::from_midi(
-                note.note, None, None,
-            ))),
+            EventType::Note(parsed_note),
         );
         let not_n = notations.clone().into_iter().filter(|not| {
             if not.ppq_position() != note.start_in_ppq {
@@ -223,6 +228,25 @@ pub fn notations_to_first_selected(
         notations_to_note_events(notations, note_events, events);
     set_edited_midi(events)
 }
+/// Apply the same notations to every selected note.
+pub fn notations_to_selected(
+    notations: Vec<NotationType>,
+) -> Result<(), ReaperError> {
+    let events = get_edited_midi()?;
+    let note_events: Vec<_> = events
+        .clone()
+        .filter_note_on()
+        .filter(|ev| ev.selected())
+        .collect();
+    if note_events.is_empty() {
+        return Err(ReaperError::UnsuccessfulOperation(
+            "No selected notes.",
+        ));
+    }
+    let events =
+        notations_to_note_events(notations, note_events, events);
+    set_edited_midi(events)
+}
 pub fn notations_to_first_and_last_selected(
     notations_to_first: Vec<NotationType>,
     notations_to_last: Vec<NotationType>,
@@ -259,6 +283,104 @@ pub fn notations_to_first_and_last_selected(
     set_edited_midi(events)
 }
 
+/// Fit onsets of selected notes to tuplet grids.
+///
+/// Selection is considered as the whole tuplet: from the start of the
+/// beat of the first onset to the end of the beat of the last onset.
+/// Selected notes should start in one measure. Beats are taken from
+/// the measure of project TimeMap, so beat grouping, meter and
+/// partial measures are respected.
+pub fn fit_tuplet_to_selected(
+) -> Result<Option<TupletFit>, ReaperError> {
+    with_edited_take(|take| {
+        let mut onsets: Vec<_> = take
+            .iter_midi(None)?
+            .filter_note_on()
+            .filter(|ev| ev.selected())
+            .map(|ev| {
+                RelativePosition::from(Position::from_ppq(
+                    ev.ppq_position(),
+                    take,
+                ))
+            })
+            .collect();
+        onsets.sort_by_key(|onset| {
+            (onset.get_measure_index(), onset.position())
+        });
+        onsets.dedup();
+        let (first, last) = match (onsets.first(), onsets.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => {
+                return Err(ReaperError::UnsuccessfulOperation(
+                    "No selected notes.",
+                ))
+            }
+        };
+        if first.get_measure_index() != last.get_measure_index() {
+            return Err(ReaperError::UnsuccessfulOperation(
+                "Selected notes should start in one measure.",
+            ));
+        }
+        let index = first.get_measure_index();
+        let project = Reaper::get().current_project();
+        let time_map = TimeMap::build_from_bounds(
+            rea_rs::Measure::from_index(index, &project).start,
+            rea_rs::Measure::from_index(index + 1, &project).start,
+        );
+        let info = time_map.get_measure_info(index);
+        let denominator = info.time_signature.denominator as u64;
+        let onsets = onsets
+            .iter()
+            .map(|onset| time_map.notated_from_timeline(onset))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|err| {
+                log::warn!("{err}");
+                ReaperError::UnsuccessfulOperation(
+                    "Selected notes are in the cut-off head of measure.",
+                )
+            })?;
+        let beats = Measure::from(&info).beats();
+        let beat_of = |onset: &RelativePosition| {
+            beats
+                .iter()
+                .position(|(start, length)| {
+                    onset.position() < *start + *length
+                })
+                .or_else(|| beats.len().checked_sub(1))
+                .ok_or(ReaperError::UnsuccessfulOperation(
+                    "Measure has no beats.",
+                ))
+        };
+        let (first_beat, last_beat) = (
+            beat_of(&onsets[0])?,
+            beat_of(&onsets[onsets.len() - 1])?,
+        );
+        let start = beats[first_beat].0;
+        let span = beats[last_beat].0 + beats[last_beat].1 - start;
+        let amount = (last_beat - first_beat + 1) as u64;
+        let beat_units = units(beats[first_beat].1, denominator);
+        let mut divisions = beat_divisions(beat_units)
+            .iter()
+            .map(|division| division * amount)
+            .collect::<Vec<_>>();
+        // quarter-note triplets over two simple beats
+        if beat_units % 3 != 0 && amount % 2 == 0 {
+            divisions.push(3 * amount / 2);
+        }
+        divisions.sort();
+        let onsets = onsets
+            .iter()
+            .map(|onset| onset.position() - start)
+            .collect::<Vec<_>>();
+        Ok(fit_tuplet(
+            &onsets,
+            span,
+            units(span, denominator),
+            &divisions,
+        ))
+    })
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct ParsedEvent {
     pub channel: u8,
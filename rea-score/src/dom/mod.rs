@@ -1,18 +1,73 @@
 use crate::{
-    lilypond_render::RendersToLilypond,
-    primitives::{EventInfo, Measure, TimeMap},
+    lilypond_render::{RenderError, RendersToLilypond},
+    musicxml_render::{
+        join_with_backup, partwise_document, MusicXmlContext,
+        RendersToMusicXml, DIVISIONS,
+    },
+    notation::{
+        measure_notations::MeasureNotations, message::MidiFuncs,
+        track_notations::TrackNotations, NotationType,
+    },
+    primitives::{
+        event::EventTupletType, AbsolutePosition, Clef, EventInfo, EventType,
+        KeySignature, Measure, Quantization, RelativePosition, TimeMap,
+    },
 };
+use fraction::Fraction;
 use itertools::Itertools;
 use rea_rs::{
     errors::ReaperError, Immutable, MidiEvent, MidiEventBuilder,
-    MidiEventConsumer, MidiMessage, NoteOffMessage, Position, RawMidiMessage,
-    Reaper, Track,
+    MidiEventConsumer, MidiMessage, NotationMessage, NoteOffMessage, Position,
+    RawMidiMessage, Reaper, Take, Track,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    sync::Arc,
 };
-use std::{collections::HashMap, error::Error, sync::Arc};
 
-use self::midi_parse::{parse_events, ParsedEvent};
+use self::{
+    grace::lift_grace_notes,
+    midi_parse::{parse_events, ParsedEvent},
+    pedal::{pedal_changes, PedalEvent},
+    repeats::{render_repeats, repeat_bounds},
+    rests::RenderedMeasure,
+    tuplets::{suggest_event_tuplets, TupletSuggestion},
+};
 
+pub mod dynamics;
+pub mod grace;
 pub mod midi_parse;
+pub mod pedal;
+pub mod repeats;
+pub mod rests;
+pub mod rpp;
+pub mod score;
+pub mod smf;
+pub mod spacer;
+pub mod tuplets;
+
+pub use rpp::{from_rpp, from_rpp_in_bars};
+pub use score::{Score, ScoreSource, StaffGrouping};
+pub use smf::{from_smf, from_smf_in_bars};
+
+/// How [Voice::render_measures] writes measures.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VoiceRenderOptions<'a> {
+    /// Measures without notes are rendered as invisible rests, which
+    /// is handy for secondary voices.
+    pub spacer_empty: bool,
+    /// Clefs, placed at the beginning of measures with the given
+    /// indexes.
+    pub clefs: &'a [(u32, Clef)],
+    /// Render rehearsal marks, barlines, navigation marks and breaks
+    /// of measures, which is needed only once per staff.
+    pub marks: bool,
+    /// Indexes of measures, that are empty in every voice of the
+    /// staff. Consecutive ones are joined into multi-measure rests
+    /// (see [rests]), as in extracted parts.
+    pub compress_rests: Option<&'a [u32]>,
+}
 
 #[derive(Debug)]
 pub struct Voice {
@@ -33,6 +88,7 @@ impl Voice {
             event.position.set_measure_index(index);
             event.position.set_position(0.0.into());
         }
+        index = self.move_to_open_tuplet(&mut event).unwrap_or(index);
         let head = self
             .measures
             .get_mut((index - self.begin_measure) as usize)
@@ -46,12 +102,63 @@ impl Voice {
             Some(head) => self.insert_event(head),
         }
     }
+    /// If tuplet, started in one of the previous measures, is still open,
+    /// event belongs to it: event position is moved to that measure.
+    ///
+    /// Tuplet is split at barlines, when it is closed.
+    fn move_to_open_tuplet(&self, event: &mut EventInfo) -> Option<u32> {
+        let index = event.position.get_measure_index();
+        let mut offset = Fraction::from(0);
+        for measure in self.measures.iter().rev() {
+            if measure.index() >= index {
+                continue;
+            }
+            offset += measure.length().get();
+            if measure.has_open_tuplets() {
+                event.position.set_measure_index(measure.index());
+                event
+                    .position
+                    .set_position(event.position.position() + offset);
+                return Some(measure.index());
+            }
+        }
+        None
+    }
     pub fn get_measure(&self, index: u32) -> Option<&Measure> {
         self.measures.get((index - self.begin_measure) as usize)
     }
     pub fn get_measure_mut(&mut self, index: u32) -> Option<&mut Measure> {
         self.measures.get_mut((index - self.begin_measure) as usize)
     }
+    /// Indexes of measures without notes.
+    pub fn empty_measures(&self) -> Vec<u32> {
+        self.measures
+            .iter()
+            .filter(|measure| !measure.has_notes())
+            .map(|measure| measure.index())
+            .collect()
+    }
+    /// Notes and chords of all measures, including ones inside
+    /// tuplets.
+    pub(crate) fn note_events_mut(&mut self) -> Vec<&mut EventInfo> {
+        self.measures
+            .iter_mut()
+            .flat_map(|measure| note_events(measure.events_mut()))
+            .collect()
+    }
+}
+fn note_events(events: &mut VecDeque<EventInfo>) -> Vec<&mut EventInfo> {
+    let mut notes = Vec::new();
+    for event in events.iter_mut() {
+        match event.event {
+            EventType::Tuplet(ref mut tuplet) => {
+                notes.extend(note_events(tuplet.container_mut().events_mut()))
+            }
+            EventType::Rest => (),
+            _ => notes.push(event),
+        }
+    }
+    notes
 }
 impl From<Arc<TimeMap>> for Voice {
     fn from(time_map: Arc<TimeMap>) -> Self {
@@ -68,39 +175,209 @@ impl From<Arc<TimeMap>> for Voice {
         }
     }
 }
-impl RendersToLilypond for Voice {
-    fn render_lilypond(&self) -> String {
-        self.measures
+impl Voice {
+    /// Render all measures of the voice, as `options` tell.
+    ///
+    /// Tempo marks are written on the spacer voice (see
+    /// [spacer::render_tempo]). Repeats and alternative endings are
+    /// written in every voice.
+    pub fn render_measures(&self, options: VoiceRenderOptions) -> String {
+        let VoiceRenderOptions {
+            spacer_empty,
+            clefs,
+            marks,
+            compress_rests,
+        } = options;
+        let global_settings = Self::global_render_settings();
+        let rendered = self
+            .measures
             .iter()
             .map(|measure| {
-                let ts = match measure.index() {
-                    x if x == self.begin_measure => {
-                        measure.time_signature().render_lilypond()
-                    }
-                    x => match self
+                let previous = match measure.index() {
+                    x if x == self.begin_measure => None,
+                    x => self
                         .measures
-                        .get((x - self.begin_measure - 1) as usize)
+                        .get((x - self.begin_measure - 1) as usize),
+                };
+                let ts = match previous {
+                    Some(m)
+                        if m.time_signature() == measure.time_signature() =>
+                    {
+                        "".to_string()
+                    }
+                    _ => measure.time_signature().render_lilypond(),
+                };
+                // `\time` resets beat structure to the default one
+                let ts = match measure.beat_structure_lilypond() {
+                    Some(beats)
+                        if !ts.is_empty()
+                            || previous.and_then(|m| m.beat_grouping())
+                                != measure.beat_grouping() =>
+                    {
+                        format!("{ts} {beats}").trim_start().to_string()
+                    }
+                    _ => ts,
+                };
+                let mut settings = global_settings;
+                let ts = match measure.key() {
+                    None => ts,
+                    Some(key) => {
+                        settings.key = key.key();
+                        match previous.and_then(|m| m.key()) == Some(key) {
+                            true => ts,
+                            false => format!("{ts} {}", key.render_lilypond())
+                                .trim_start()
+                                .to_string(),
+                        }
+                    }
+                };
+                let ts = match clefs
+                    .iter()
+                    .find(|(index, _)| *index == measure.index())
+                {
+                    None => ts,
+                    Some((_, clef)) => {
+                        format!("{} {ts}", clef.render_lilypond())
+                            .trim_end()
+                            .to_string()
+                    }
+                };
+                // `\partial` goes after `\time`
+                let ts = match measure.partial_lilypond() {
+                    None => ts,
+                    Some(partial) => {
+                        format!("{ts} {partial}").trim_start().to_string()
+                    }
+                };
+                // LilyPond does not count pickup, REAPER does
+                let ts = match previous {
+                    Some(m)
+                        if m.is_partial()
+                            && m.index() == self.begin_measure =>
                     {
-                        None => measure.time_signature().render_lilypond(),
-                        Some(m) => match m.time_signature()
-                            == measure.time_signature()
-                        {
-                            true => "".to_string(),
-                            false => {
-                                measure.time_signature().render_lilypond()
+                        format!(
+                            r"\set Score.currentBarNumber = #{} {ts}",
+                            measure.index()
+                        )
+                        .trim_end()
+                        .to_string()
+                    }
+                    _ => ts,
+                };
+                let ts = match marks {
+                    false => ts,
+                    true => format!(
+                        "{} {ts}",
+                        measure
+                            .marks()
+                            .lilypond(global_settings.rehearsal_marks)
+                    )
+                    .trim()
+                    .to_string(),
+                };
+                // tempo marks of the spacer voice would be hidden inside
+                // multi-measure rest
+                let rest = match compress_rests
+                    .map_or(false, |empty| empty.contains(&measure.index()))
+                    && !spacer_empty
+                    && !measure.is_partial()
+                    && measure.tempo().is_empty()
+                {
+                    true => measure.full_rest_duration(),
+                    false => None,
+                };
+                let events = match spacer_empty && !measure.has_notes() {
+                    true if measure.is_partial() => {
+                        let length = measure.length().get_quantized();
+                        format!(
+                            "s1*{}/{}",
+                            length.numer().expect("bad fraction"),
+                            length.denom().expect("bad fraction")
+                        )
+                    }
+                    true => {
+                        let ts = measure.time_signature();
+                        format!("s1*{}/{}", ts.numerator, ts.denominator)
+                    }
+                    false => match &rest {
+                        Some(duration) => format!("R{duration}"),
+                        None => {
+                            match measure.render_events_lilypond(&settings) {
+                                Ok(events) => events,
+                                // measure is kept in time by the skip
+                                Err(err) => {
+                                    log::error!(
+                                        "Can not render bar {}: {err}",
+                                        measure.index()
+                                    );
+                                    format!(
+                                        "% error: {err}\n{}",
+                                        spacer::skip(
+                                            measure.length().get_quantized()
+                                        )
+                                    )
+                                }
                             }
-                        },
+                        }
                     },
                 };
-                let events = measure
-                    .get_events_normalized()
-                    .expect("Can not get normalized events")
-                    .iter()
-                    .map(|ev| ev.render_lilypond())
-                    .join(" ");
-                format!("% bar{}\n{ts} {events} |", measure.index())
+                let end = match marks {
+                    true => measure.marks().end_lilypond(),
+                    false => String::new(),
+                };
+                RenderedMeasure {
+                    index: measure.index(),
+                    prefix: ts,
+                    events: format!("{events} {end}").trim_end().to_string(),
+                    // navigation marks end the multi-measure rest
+                    rest: rest.filter(|_| end.is_empty()),
+                }
             })
-            .join(" ")
+            .collect::<Vec<_>>();
+        let marks = self
+            .measures
+            .iter()
+            .map(|measure| measure.marks())
+            .collect::<Vec<_>>();
+        let rendered = match compress_rests {
+            Some(_) => rests::compress_rests(rendered, &repeat_bounds(&marks)),
+            None => rendered.iter().map(RenderedMeasure::render).collect(),
+        };
+        render_repeats(&marks, rendered)
+    }
+
+    /// Command, setting stem directions, based on voice index.
+    ///
+    /// Voices 1-4 (5-8 and so on for the next staves) become
+    /// `\voiceOne`-`\voiceFour`.
+    pub fn direction_command(&self) -> &'static str {
+        match self.index.saturating_sub(1) % 4 {
+            0 => r"\voiceOne",
+            1 => r"\voiceTwo",
+            2 => r"\voiceThree",
+            _ => r"\voiceFour",
+        }
+    }
+}
+impl RendersToLilypond for Voice {
+    fn render_lilypond(&self) -> String {
+        self.render_measures(VoiceRenderOptions {
+            marks: true,
+            ..Default::default()
+        })
+    }
+}
+
+impl RendersToMusicXml for Voice {
+    /// Renders only measure, currently written by Part.
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        match self.get_measure(ctx.measure) {
+            None => Ok(String::new()),
+            Some(measure) => measure.render_musicxml(ctx),
+        }
     }
 }
 
@@ -109,6 +386,9 @@ pub struct Staff {
     pub time_map: Arc<TimeMap>,
     pub index: u8,
     pub voices: Vec<Voice>,
+    pub clef: Clef,
+    /// Change clef by pitch range of measures, starting from `clef`.
+    pub auto_clef: bool,
 }
 impl Staff {
     pub fn new(time_map: Arc<TimeMap>, index: u8, voices: Vec<Voice>) -> Self {
@@ -116,16 +396,152 @@ impl Staff {
             time_map,
             index,
             voices,
+            clef: Clef::default(),
+            auto_clef: false,
         }
     }
+
+    /// Clef changes as `(measure index, clef)`, where the first item
+    /// is the clef of the first measure.
+    pub fn clefs(&self) -> Vec<(u32, Clef)> {
+        if !self.auto_clef {
+            return vec![(self.time_map.begin_measure(), self.clef)];
+        }
+        let ranges = self
+            .time_map
+            .get()
+            .iter()
+            .map(|info| (info.index, self.pitch_range(info.index)));
+        Clef::auto_clefs(self.clef, ranges)
+    }
+
+    /// Lowest and highest pitches of the measure in all voices.
+    fn pitch_range(&self, index: u32) -> Option<(u8, u8)> {
+        self.voices
+            .iter()
+            .filter_map(|voice| voice.get_measure(index)?.pitch_range())
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+    }
+
+    /// Indexes of measures, that are empty in every voice.
+    pub fn empty_measures(&self) -> Vec<u32> {
+        let voices = self
+            .voices
+            .iter()
+            .map(|voice| voice.empty_measures())
+            .collect::<Vec<_>>();
+        self.time_map
+            .get()
+            .iter()
+            .map(|info| info.index)
+            .filter(|index| voices.iter().all(|empty| empty.contains(index)))
+            .collect()
+    }
+
+    /// Render voices of the staff. If `compress_rests`, measures, that
+    /// are empty in every voice, are joined into multi-measure rests of
+    /// the first voice, as in extracted parts. Empty measures of the
+    /// other voices are hidden then. If `tempo`, tempo marks are
+    /// written on the spacer voice of the staff.
+    pub fn render_voices(&self, compress_rests: bool, tempo: bool) -> String {
+        let mut clefs = self.clefs();
+        // treble clef is the LilyPond default
+        if clefs.first().map(|(_, clef)| *clef) == Some(Clef::Treble) {
+            clefs.remove(0);
+        }
+        let settings = Self::global_render_settings();
+        // pedal style is set only for staves, that need it.
+        let pedal_style = match self.has_pedals() {
+            true => format!("{} ", settings.pedal_style.lilypond()),
+            false => String::new(),
+        };
+        let empty = match compress_rests {
+            true => Some(self.empty_measures()),
+            false => None,
+        };
+        let music = match self.voices.len() {
+            1 => format!(
+                "{pedal_style}{}",
+                self.voices[0].render_measures(VoiceRenderOptions {
+                    spacer_empty: false,
+                    clefs: &clefs,
+                    marks: true,
+                    compress_rests: empty.as_deref(),
+                })
+            ),
+            _ => self.render_several_voices(
+                &clefs,
+                &pedal_style,
+                empty.as_deref(),
+            ),
+        };
+        let tempo = match tempo {
+            true => spacer::render_tempo(&self.time_map),
+            false => None,
+        };
+        match tempo {
+            None => music,
+            Some(tempo) => {
+                format!(r"<< {{ {music} }} \new Voice {{ {tempo} }} >>")
+            }
+        }
+    }
+
+    fn render_several_voices(
+        &self,
+        clefs: &[(u32, Clef)],
+        pedal_style: &str,
+        compress_rests: Option<&[u32]>,
+    ) -> String {
+        let settings = Self::global_render_settings();
+        // multi-measure rests are written by the first voice only
+        let hide_rests =
+            settings.hide_empty_voice_rests || compress_rests.is_some();
+        let voices = self
+            .voices
+            .iter()
+            .sorted_by_key(|voice| voice.index)
+            .enumerate()
+            .map(|(idx, voice)| {
+                let measures = voice.render_measures(VoiceRenderOptions {
+                    spacer_empty: idx > 0 && hide_rests,
+                    clefs: match idx {
+                        0 => clefs,
+                        _ => &[],
+                    },
+                    marks: idx == 0,
+                    compress_rests: compress_rests.filter(|_| idx == 0),
+                });
+                let measures = match idx {
+                    0 => format!("{pedal_style}{measures}"),
+                    _ => measures,
+                };
+                format!("{{ {} {measures} }}", voice.direction_command())
+            })
+            .join(r" \\ ");
+        format!("<< {voices} >>")
+    }
 }
 impl RendersToLilypond for Staff {
     fn render_lilypond(&self) -> String {
-        if self.voices.len() == 1 {
-            self.voices[0].render_lilypond()
-        } else {
-            todo!()
-        }
+        self.render_voices(false, true)
+    }
+}
+
+impl RendersToMusicXml for Staff {
+    /// Renders all voices of measure, currently written by Part.
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        join_with_backup(
+            ctx,
+            self.voices.iter().enumerate(),
+            |ctx, (idx, voice)| {
+                ctx.voice = (ctx.staff - 1) * 4 + idx as u8 + 1;
+                voice.render_musicxml(ctx)
+            },
+        )
     }
 }
 
@@ -133,26 +549,271 @@ impl RendersToLilypond for Staff {
 pub struct Part {
     pub time_map: Arc<TimeMap>,
     pub staves: Vec<Staff>,
+    /// Usually, name of the track, part is built from.
+    pub name: Option<String>,
 }
 impl Part {
     pub fn new(time_map: Arc<TimeMap>, staves: Vec<Staff>) -> Self {
-        Self { time_map, staves }
+        Self {
+            time_map,
+            staves,
+            name: None,
+        }
+    }
+
+    /// Apply notations to measures of every voice, starting from the
+    /// given measure index.
+    ///
+    /// Unlike notations of TimeMap, these are local to the part. Key,
+    /// beat grouping and meter changes of TimeMap are merged with
+    /// them by position.
+    pub fn apply_measure_notations(
+        &mut self,
+        notations: impl IntoIterator<Item = (u32, MeasureNotations)>,
+    ) {
+        // changes of TimeMap go first, so track notation wins at the
+        // same measure, and every change holds until the next one of
+        // either source.
+        let mut notations = self
+            .time_map
+            .get()
+            .iter()
+            .flat_map(|info| {
+                info.changes
+                    .iter()
+                    .map(|notation| (info.index, notation.clone()))
+            })
+            .chain(notations)
+            .collect::<Vec<_>>();
+        notations.sort_by_key(|(index, _)| *index);
+        let voices =
+            self.staves.iter_mut().flat_map(|st| st.voices.iter_mut());
+        for voice in voices {
+            for (index, notation) in notations.iter() {
+                voice
+                    .measures
+                    .iter_mut()
+                    .filter(|measure| match notation.is_global() {
+                        true => measure.index() == *index,
+                        false => measure.index() >= *index,
+                    })
+                    .for_each(|measure| measure.apply_notation(notation));
+            }
+        }
+    }
+
+    /// Apply notations, found in track notation events.
+    ///
+    /// Measure notations are applied from the measure with the given
+    /// index, and track notations — to the whole part.
+    pub fn apply_track_notations(
+        &mut self,
+        notations: impl IntoIterator<Item = (u32, NotationType)>,
+    ) {
+        let mut measure_notations = Vec::new();
+        for (index, notation) in notations {
+            match notation {
+                NotationType::Measure(notation) => {
+                    measure_notations.push((index, notation))
+                }
+                NotationType::Track(notation) => {
+                    self.apply_track_notation(notation)
+                }
+                _ => (),
+            }
+        }
+        self.apply_measure_notations(measure_notations);
+    }
+    fn apply_track_notation(&mut self, notation: TrackNotations) {
+        let staves = self.staves.iter_mut();
+        match notation {
+            TrackNotations::Clef(index, clef) => staves
+                .filter(|st| st.index == index)
+                .for_each(|st| st.clef = clef),
+            TrackNotations::AutoClef(index) => staves
+                .filter(|st| st.index == index)
+                .for_each(|st| st.auto_clef = true),
+        }
+    }
+
+    /// Key in force at the measure, as it was set by notations or by
+    /// TimeMap.
+    pub fn measure_key(&self, index: u32) -> Option<KeySignature> {
+        self.staves
+            .iter()
+            .flat_map(|st| st.voices.iter())
+            .find_map(|voice| voice.get_measure(index))
+            .map_or(self.time_map.get_measure_info(index).key, |measure| {
+                measure.key()
+            })
+    }
+
+    /// Render part as `\new Staff` (or `\new PianoStaff` if part has
+    /// several staves), named by the part name.
+    ///
+    /// If `tempo`, tempo marks are written on the top staff. In score
+    /// only the top part needs them.
+    pub fn render_lilypond_staves(&self, tempo: bool) -> String {
+        let with = match &self.name {
+            None => String::new(),
+            Some(name) => format!(
+                r#"\with {{ instrumentName = "{}" }} "#,
+                name.replace('\\', r"\\").replace('"', r#"\""#)
+            ),
+        };
+        if self.staves.len() == 1 {
+            return format!(
+                r"\new Staff {with}{{ {} }}",
+                self.staves[0].render_voices(false, tempo)
+            );
+        }
+        format!(
+            r"\new PianoStaff {with}<< {} >>",
+            self.staves
+                .iter()
+                .enumerate()
+                .map(|(idx, staff)| {
+                    format!(
+                        r"\new Staff {{ {} }}",
+                        staff.render_voices(false, tempo && idx == 0)
+                    )
+                })
+                .join(" ")
+        )
     }
 }
-impl RendersToLilypond for Part {
-    fn render_lilypond(&self) -> String {
+impl Part {
+    /// Render part for extraction: consecutive empty measures are
+    /// written as multi-measure rests, compressed by
+    /// `\compressMMRests`.
+    pub fn render_lilypond_extracted(&self) -> String {
+        format!(r"\compressMMRests {{ {} }}", self.render_staves(true))
+    }
+
+    fn render_staves(&self, compress_rests: bool) -> String {
         if self.staves.len() == 1 {
-            return self.staves[0].render_lilypond();
+            return self.staves[0].render_voices(compress_rests, true);
         }
         format!(
             "<< {} >>",
             self.staves
                 .iter()
-                .map(|staff| staff.render_lilypond())
+                .enumerate()
+                .map(|(idx, staff)| {
+                    staff.render_voices(compress_rests, idx == 0)
+                })
                 .join(" ")
         )
     }
 }
+impl RendersToLilypond for Part {
+    fn render_lilypond(&self) -> String {
+        self.render_staves(false)
+    }
+}
+
+impl Part {
+    fn musicxml_attributes(
+        &self,
+        measure_index: u32,
+        ctx: &mut MusicXmlContext,
+        clefs: &[Vec<(u32, Clef)>],
+    ) -> Result<String, RenderError> {
+        let info = self.time_map.get_measure_info(measure_index);
+        let first = measure_index == self.time_map.begin_measure();
+        let previous = match first {
+            true => None,
+            false => Some(self.time_map.get_measure_info(measure_index - 1)),
+        };
+        let ts_changed = previous
+            .as_ref()
+            .map_or(true, |prev| prev.time_signature != info.time_signature);
+        let key = self.measure_key(measure_index);
+        let key_changed = match previous {
+            None => key.is_some(),
+            Some(prev) => self.measure_key(prev.index) != key,
+        };
+        let clefs = clefs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, clefs)| {
+                let (_, clef) =
+                    clefs.iter().find(|(index, _)| *index == measure_index)?;
+                Some(clef.musicxml(idx + 1))
+            })
+            .collect::<Vec<_>>();
+        if !ts_changed && !key_changed && clefs.is_empty() {
+            return Ok(String::new());
+        }
+        let mut attributes = Vec::new();
+        if first {
+            attributes.push(format!("<divisions>{DIVISIONS}</divisions>"));
+        }
+        if key_changed {
+            if let Some(key) = key {
+                attributes.push(key.render_musicxml(ctx)?);
+            }
+        }
+        if ts_changed {
+            attributes.push(info.time_signature.render_musicxml(ctx)?);
+        }
+        if first && self.staves.len() > 1 {
+            attributes.push(format!("<staves>{}</staves>", self.staves.len()));
+        }
+        attributes.extend(clefs);
+        Ok(format!(
+            "<attributes>{}</attributes>\n",
+            attributes.join("")
+        ))
+    }
+}
+impl RendersToMusicXml for Part {
+    /// Renders the whole partwise document.
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let base_key = ctx.settings.key;
+        let clefs = self.staves.iter().map(|st| st.clefs()).collect_vec();
+        let measures = self
+            .time_map
+            .get()
+            .iter()
+            .map(|info| {
+                ctx.measure = info.index;
+                ctx.settings.key = self
+                    .measure_key(info.index)
+                    .map_or(base_key, |key| key.key());
+                let attributes =
+                    self.musicxml_attributes(info.index, ctx, &clefs)?;
+                let staves = join_with_backup(
+                    ctx,
+                    self.staves.iter().enumerate(),
+                    |ctx, (idx, staff)| {
+                        ctx.staff = idx as u8 + 1;
+                        staff.render_musicxml(ctx)
+                    },
+                )?;
+                // pickup is not counted as a measure
+                let implicit = match info.is_partial()
+                    && info.index == self.time_map.begin_measure()
+                {
+                    true => " implicit=\"yes\"",
+                    false => "",
+                };
+                Ok(format!(
+                    "<measure number=\"{}\"{implicit}>\n{attributes}{staves}\n</measure>",
+                    info.index
+                ))
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?
+            .join("\n");
+        Ok(partwise_document(
+            self.name.clone().unwrap_or("Part".to_string()),
+            measures,
+        ))
+    }
+}
 
 pub fn parse_track_in_bounds(
     track: Track<Immutable>,
@@ -160,17 +821,161 @@ pub fn parse_track_in_bounds(
     end_pos: impl Into<Position>,
 ) -> Result<Part, Box<dyn Error>> {
     let (start_pos, end_pos) = (start_pos.into(), end_pos.into());
+    let time_map = Arc::new(time_map_for_tracks(
+        std::slice::from_ref(&track),
+        start_pos,
+        end_pos,
+    )?);
+    parse_track_with_time_map(track, start_pos, end_pos, time_map)
+}
+
+/// Build TimeMap from bounds, with partial measures and tempo names,
+/// set by track notations of the given tracks.
+pub(crate) fn time_map_for_tracks(
+    tracks: &[Track<Immutable>],
+    start_pos: Position,
+    end_pos: Position,
+) -> Result<TimeMap, ReaperError> {
+    let mut time_map = TimeMap::build_from_bounds(start_pos, end_pos);
+    for track in tracks {
+        time_map.apply_measure_notations(time_map_notations(
+            &get_track_notations(track)?,
+        ));
+    }
+    Ok(time_map)
+}
+
+/// Track notations, that belong to the TimeMap (partial measures,
+/// tempo names). They should be applied to it before events are
+/// parsed.
+pub(crate) fn time_map_notations<'a>(
+    notations: impl IntoIterator<Item = &'a (u32, NotationType)>,
+) -> Vec<(u32, MeasureNotations)> {
+    notations
+        .into_iter()
+        .filter_map(|(index, notation)| match notation {
+            NotationType::Measure(notation) if notation.is_global() => {
+                Some((*index, notation.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The same as [parse_track_in_bounds], but TimeMap is given, so it
+/// can be shared between Parts of one Score.
+pub fn parse_track_with_time_map(
+    track: Track<Immutable>,
+    start_pos: impl Into<Position>,
+    end_pos: impl Into<Position>,
+    time_map: Arc<TimeMap>,
+) -> Result<Part, Box<dyn Error>> {
+    let (start_pos, end_pos) = (start_pos.into(), end_pos.into());
+    let name = track.name()?;
+    let track_notations = get_track_notations(&track)?;
+    let pedals = get_track_pedals_in_bounds(&track, start_pos, end_pos)?;
     let events = get_track_midi_in_bounds(track, start_pos, end_pos)?
         .into_iter()
-        .map(|ev| ev.apply_single_notations());
+        .map(|mut ev| {
+            ev.event.position =
+                time_map.notated_from_timeline(&ev.event.position)?;
+            Ok(ev.apply_single_notations())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
     // println!("events: {:?}", events.clone().collect_vec());
-    let time_map = Arc::new(TimeMap::build_from_bounds(start_pos, end_pos));
-    let voices = voices_from_events(events, time_map.clone())?;
+    let mut part = part_from_events(events.into_iter(), time_map)?;
+    part.apply_track_notations(track_notations);
+    part.apply_pedals(pedals)?;
+    if let Some(inference) = Part::global_render_settings().infer_dynamics {
+        part.infer_dynamics(&inference);
+    }
+    if !name.is_empty() {
+        part.name = Some(name);
+    }
+    Ok(part)
+}
+
+/// Build Part from events, that already have notations applied.
+pub(crate) fn part_from_events(
+    events: impl Iterator<Item = ParsedEvent>,
+    time_map: Arc<TimeMap>,
+) -> Result<Part, Box<dyn Error>> {
+    let settings = Part::global_render_settings();
+    let threshold = settings
+        .grace_threshold
+        .map(|denom| Fraction::new(1_u64, denom));
+    let mut events =
+        lift_grace_notes(events.collect(), threshold, &time_map)?;
+    let tuplets = match settings.detect_tuplets {
+        Some(min_confidence) => {
+            suggest_event_tuplets(&events, &time_map, min_confidence)
+        }
+        None => Vec::new(),
+    };
+    quantize_events(&mut events, &settings.quantization, &tuplets, &time_map);
+    let voices = voices_from_events(events.into_iter(), time_map.clone())?;
     // println!("voices: {:?}", voices);
     let staves = staves_from_voices(voices, time_map.clone());
     // println!("staves: {:?}", staves);
-    Ok(Part::new(time_map.clone(), staves))
+    let mut part = Part::new(time_map, staves);
+    part.apply_tuplets(&tuplets)?;
+    Ok(part)
 }
+/// Snap events to the grid of settings.
+///
+/// Events of tuplets are snapped to the grid in the tuplet time, so
+/// tuplets are built from them, not from the straight grid. These are
+/// tagged tuplets and `detected` ones, see
+/// [tuplets::suggest_event_tuplets].
+fn quantize_events(
+    events: &mut [ParsedEvent],
+    quantization: &Quantization,
+    detected: &[(u8, TupletSuggestion)],
+    time_map: &TimeMap,
+) {
+    let mut open: HashMap<u8, Vec<(RelativePosition, Fraction)>> =
+        HashMap::new();
+    for ev in events.iter_mut() {
+        let tuplets = open.entry(ev.channel).or_default();
+        let suggestion = detected.iter().find(|(channel, suggestion)| {
+            let start = &suggestion.position;
+            let position = &ev.event.position;
+            *channel == ev.channel
+                && position.get_measure_index() == start.get_measure_index()
+                && position.position() >= start.position()
+                && position.position()
+                    < start.position() + suggestion.length.get()
+        });
+        match (tuplets.last(), suggestion) {
+            (Some((start, _)), _) => {
+                let rate = tuplets
+                    .iter()
+                    .fold(Fraction::from(1), |rate, (_, r)| rate * r);
+                ev.event.quantize_from(
+                    start,
+                    quantization.step() / rate,
+                    time_map,
+                )
+            }
+            (None, Some((_, suggestion))) => ev.event.quantize_from(
+                &suggestion.position,
+                suggestion.length.get()
+                    / Fraction::from(suggestion.fit.division),
+                time_map,
+            ),
+            (None, None) => ev.event.quantize(quantization, time_map),
+        }
+        match &ev.event.tuplet_type {
+            EventTupletType::TupletStart(rates) => tuplets.extend(
+                rates.iter().map(|rate| (ev.event.position.clone(), *rate)),
+            ),
+            EventTupletType::TupletEnd(levels) => tuplets
+                .truncate(tuplets.len().saturating_sub(*levels as usize)),
+            EventTupletType::NonTuplet => (),
+        }
+    }
+}
+
 fn staves_from_voices(
     voices: Vec<Voice>,
     time_map: Arc<TimeMap>,
@@ -260,18 +1065,111 @@ fn get_track_midi_in_bounds(
     Ok(events)
 }
 
+/// Pedal changes from controllers of all items up to `end_pos`.
+fn get_track_pedals_in_bounds(
+    track: &Track<Immutable>,
+    start_pos: Position,
+    end_pos: Position,
+) -> Result<Vec<PedalEvent>, ReaperError> {
+    let mut controllers = Vec::new();
+    for idx in 0..track.n_items() {
+        let item = track.get_item(idx).expect("Should be item here");
+        if item.position() > end_pos {
+            continue;
+        }
+        let take = item.active_take();
+        for event in take.iter_midi(None)? {
+            let position = Position::from_ppq(event.ppq_position(), &take);
+            let raw = event.message().get_raw();
+            if position > end_pos || raw.len() != 3 || raw[0] & 0xF0 != 0xB0 {
+                continue;
+            }
+            let channel = (raw[0] & 0x0F) + 1;
+            controllers.push((
+                AbsolutePosition::from(position),
+                channel,
+                raw[1],
+                raw[2],
+            ));
+        }
+    }
+    controllers.sort_by(|a, b| {
+        a.0.partial_cmp(&b.0)
+            .expect("can not compare event positions")
+    });
+    Ok(pedal_changes(
+        controllers,
+        &AbsolutePosition::from(start_pos),
+    ))
+}
+
+/// Measure and track notations from track notation events of all
+/// items, with indexes of measures they are placed in.
+fn get_track_notations(
+    track: &Track<Immutable>,
+) -> Result<Vec<(u32, NotationType)>, ReaperError> {
+    let project = Reaper::get().current_project();
+    let mut notations = Vec::new();
+    for idx in 0..track.n_items() {
+        let item = track.get_item(idx).expect("Should be item here");
+        let take = item.active_take();
+        let events = match take.iter_midi(None) {
+            Ok(events) => events,
+            Err(err) => {
+                log::warn!(
+                    "skipping notations of item {idx} on track {}: {err}",
+                    track.name().unwrap_or_default()
+                );
+                continue;
+            }
+        };
+        for event in events {
+            let msg =
+                match NotationMessage::from_raw(event.message().get_raw()) {
+                    Some(msg) => msg,
+                    None => continue,
+                };
+            let index = rea_rs::Measure::from_position(
+                Position::from_ppq(event.ppq_position(), &take),
+                &project,
+            )
+            .index;
+            notations.extend(
+                MidiFuncs::parse_notations(msg)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|notation| {
+                        matches!(
+                            notation,
+                            NotationType::Measure(_) | NotationType::Track(_)
+                        )
+                    })
+                    .map(|notation| (index, notation)),
+            );
+        }
+    }
+    Ok(notations)
+}
+
 pub fn get_edited_midi() -> Result<MidiEventBuilder, ReaperError> {
+    with_edited_take(|take| take.iter_midi(None))
+}
+
+/// Call `f` with the take of the opened MIDI editor, or else of the
+/// first selected item.
+pub fn with_edited_take<R>(
+    f: impl FnOnce(&Take<Immutable>) -> Result<R, ReaperError>,
+) -> Result<R, ReaperError> {
     let rpr = Reaper::get();
     let mut pr = rpr.current_project();
     match rpr.active_midi_editor() {
-        Some(mut e) => e.item_mut(&pr).active_take().iter_midi(None),
-        None => pr
+        Some(mut e) => f(&e.item_mut(&pr).active_take()),
+        None => f(&pr
             .get_selected_item_mut(0)
             .ok_or(ReaperError::InvalidObject(
                 "No opened editor and no selected item found.",
             ))?
-            .active_take()
-            .iter_midi(None),
+            .active_take()),
     }
 }
 
@@ -314,12 +1212,18 @@ mod tests {
     use std::sync::Arc;
 
     use crate::primitives::{
-        AbsolutePosition, EventInfo, EventType, Length, Measure, MeasureInfo,
-        Note, Pitch, RelativePosition, TimeMap, TimeMapMeasures,
+        AbsolutePosition, Barline, Clef, EventInfo, EventType, GraceGroup,
+        GraceType, Length, Measure, MeasureInfo, Note, Pitch,
+        RelativePosition, TimeMap, TimeMapMeasures,
     };
     static TIME_MAP: OnceCell<Arc<TimeMap>> = OnceCell::new();
 
-    use super::Voice;
+    use crate::{
+        lilypond_render::RendersToLilypond,
+        notation::measure_notations::MeasureNotations,
+    };
+
+    use super::{Part, Staff, Voice, VoiceRenderOptions};
     fn get_time_map() -> Arc<TimeMap> {
         match TIME_MAP.get() {
             None => {
@@ -379,4 +1283,260 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_staff_voices() -> Result<(), String> {
+        let time_map = Arc::new(TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(3, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        ));
+        let mut voice_1 = Voice::from(time_map.clone());
+        voice_1.index = 1;
+        let mut voice_2 = Voice::from(time_map.clone());
+        voice_2.index = 2;
+        voice_1.insert_event(EventInfo::new(
+            RelativePosition::new(2, Fraction::from(0.0)),
+            Length::from(Fraction::new(3_u64, 4_u64)),
+            EventType::Note(Note::new(Pitch::from_midi(67, None, None))),
+        ))?;
+        voice_2.insert_event(EventInfo::new(
+            RelativePosition::new(1, Fraction::from(0.0)),
+            Length::from(Fraction::new(1_u64, 1_u64)),
+            EventType::Note(Note::new(Pitch::from_midi(60, None, None))),
+        ))?;
+        let staff = Staff::new(time_map, 1, vec![voice_2, voice_1]);
+        let rendered = staff.render_lilypond();
+        assert!(rendered.starts_with(r"<< { \voiceOne % bar1"));
+        assert!(rendered.contains(r"} \\ { \voiceTwo % bar1"));
+        assert!(rendered.ends_with("% bar2\n\\time 3/4 s1*3/4 | } >>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_measure_key() {
+        let mut time_map = TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+                MeasureInfo::new(3, TimeSignature::new(4, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        );
+        let key = "Eb-major".parse().unwrap();
+        time_map.apply_measure_notations([(2, MeasureNotations::Key(key))]);
+        let voice = Voice::from(Arc::new(time_map));
+        let rendered = voice.render_measures(VoiceRenderOptions::default());
+        assert!(rendered.starts_with("% bar1\n\\time 4/4 r1 |"));
+        assert!(rendered.contains("% bar2\n\\key es \\major r1 |"));
+        assert!(rendered.ends_with("% bar3\n r1 |"));
+    }
+
+    #[test]
+    fn test_track_and_marker_keys() {
+        let mut time_map = TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+                MeasureInfo::new(3, TimeSignature::new(4, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        );
+        let key = "Eb-major".parse().unwrap();
+        time_map.apply_measure_notations([(3, MeasureNotations::Key(key))]);
+        let time_map = Arc::new(time_map);
+        let voice = Voice::from(time_map.clone());
+        let staff = Staff::new(time_map.clone(), 1, vec![voice]);
+        let mut part = Part::new(time_map, vec![staff]);
+        let key = "D-major".parse().unwrap();
+        part.apply_measure_notations([(2, MeasureNotations::Key(key))]);
+        let rendered = part.staves[0].voices[0]
+            .render_measures(VoiceRenderOptions::default());
+        assert!(rendered.contains("% bar2\n\\key d \\major r1 |"));
+        assert!(rendered.ends_with("% bar3\n\\key es \\major r1 |"));
+    }
+
+    #[test]
+    fn test_beat_grouping() -> Result<(), String> {
+        let mut time_map = TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(7, 8)),
+                MeasureInfo::new(2, TimeSignature::new(7, 8)),
+                MeasureInfo::new(3, TimeSignature::new(4, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        );
+        time_map.apply_measure_notations([(
+            1,
+            MeasureNotations::BeatGrouping(vec![2, 2, 3]),
+        )]);
+        assert_eq!(time_map.get_measure_info(3).beat_grouping, None);
+        let mut voice = Voice::from(Arc::new(time_map));
+        let eighth = Fraction::new(1_u64, 8_u64);
+        let notes = [
+            (60, 0, 1),
+            (62, 1, 2),
+            (64, 3, 1),
+            (65, 4, 1),
+            (67, 5, 1),
+            (69, 6, 1),
+        ];
+        let note = |midi| {
+            EventType::Note(Note::new(Pitch::from_midi(midi, None, None)))
+        };
+        for (midi, position, length) in notes {
+            let mut event = note(midi);
+            // beam is attached to the main note, not to the grace group
+            if midi == 60 {
+                event.push_grace(GraceGroup::new(
+                    GraceType::AfterGrace,
+                    vec![note(71)],
+                ))?;
+            }
+            voice.insert_event(EventInfo::new(
+                RelativePosition::new(1, eighth * position),
+                Length::from(eighth * length),
+                event,
+            ))?;
+        }
+        let rendered = voice.render_measures(VoiceRenderOptions::default());
+        assert!(rendered.starts_with(
+            "% bar1\n\\time 7/8 \\set Timing.beatStructure = 2,2,3 \
+            \\afterGrace c'8[ { b'8 } d'8]~ d'8[ e'8] f'8[ g'8 a'8] |"
+        ));
+        // the same grouping is not repeated
+        assert!(rendered.contains("% bar2\n r"));
+        assert!(rendered.ends_with("% bar3\n\\time 4/4 r1 |"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pickup_measure() -> Result<(), String> {
+        let mut time_map = TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+                MeasureInfo::new(3, TimeSignature::new(4, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        );
+        let quarter = Fraction::new(1_u64, 4_u64);
+        time_map.apply_measure_notations([(
+            1,
+            MeasureNotations::Partial(quarter),
+        )]);
+        let mut voice = Voice::from(Arc::new(time_map));
+        voice.insert_event(EventInfo::new(
+            RelativePosition::new(1, Fraction::from(0)),
+            Length::from(quarter),
+            EventType::Note(Note::new(Pitch::from_midi(60, None, None))),
+        ))?;
+        voice.insert_event(EventInfo::new(
+            RelativePosition::new(2, Fraction::from(0)),
+            Length::from(1.0),
+            EventType::Note(Note::new(Pitch::from_midi(62, None, None))),
+        ))?;
+        assert_eq!(
+            voice.render_measures(VoiceRenderOptions::default()),
+            "% bar1\n\\time 4/4 \\partial 4 c'4 | \
+            % bar2\n\\set Score.currentBarNumber = #2 d'1 | \
+            % bar3\n r1 |"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_measure_rests() {
+        let mut time_map = TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+                MeasureInfo::new(3, TimeSignature::new(4, 4)),
+                MeasureInfo::new(4, TimeSignature::new(3, 4)),
+                MeasureInfo::new(5, TimeSignature::new(3, 4)),
+                MeasureInfo::new(6, TimeSignature::new(3, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        );
+        time_map.apply_measure_notations([(
+            6,
+            MeasureNotations::Barline(Barline::Double),
+        )]);
+        let voice = Voice::from(Arc::new(time_map));
+        let empty = voice.empty_measures();
+        let mut options = VoiceRenderOptions {
+            marks: true,
+            compress_rests: Some(&empty),
+            ..Default::default()
+        };
+        assert_eq!(
+            voice.render_measures(options),
+            "% bar1\n\\time 4/4 R1*3 | \
+            % bar4\n\\time 3/4 R2.*2 | \
+            % bar6\n\\bar \"||\" R2. |"
+        );
+        // score view keeps measures expanded
+        options.compress_rests = None;
+        assert!(voice
+            .render_measures(options)
+            .starts_with("% bar1\n\\time 4/4 r1 | % bar2\n r1 |"));
+    }
+
+    #[test]
+    fn test_staff_multi_measure_rests() -> Result<(), String> {
+        let time_map = Arc::new(TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+                MeasureInfo::new(3, TimeSignature::new(4, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        ));
+        let mut first = Voice::from(time_map.clone());
+        first.index = 1;
+        let mut second = Voice::from(time_map.clone());
+        second.index = 2;
+        second.insert_event(EventInfo::new(
+            RelativePosition::new(3, Fraction::from(0.0)),
+            Length::from(Fraction::new(1_u64, 1_u64)),
+            EventType::Note(Note::new(Pitch::from_midi(60, None, None))),
+        ))?;
+        let staff = Staff::new(time_map, 1, vec![first, second]);
+        assert_eq!(staff.empty_measures(), vec![1, 2]);
+        let rendered = staff.render_voices(true, false);
+        // the first voice is not compressed, where the second has notes
+        assert!(rendered.contains("% bar1\n\\time 4/4 R1*2 | % bar3\n r1 |"));
+        assert!(rendered.contains("% bar1\n\\time 4/4 s1*4/4 |"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_staff_clef() -> Result<(), String> {
+        let time_map = Arc::new(TimeMap::new(
+            TimeMapMeasures::from([
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+            ]),
+            AbsolutePosition::from(0.0),
+        ));
+        let mut voice = Voice::from(time_map.clone());
+        voice.index = 1;
+        voice.insert_event(EventInfo::new(
+            RelativePosition::new(2, Fraction::from(0.0)),
+            Length::from(Fraction::new(1_u64, 1_u64)),
+            EventType::Note(Note::new(Pitch::from_midi(43, None, None))),
+        ))?;
+        let mut staff = Staff::new(time_map, 1, vec![voice]);
+        assert!(staff.render_lilypond().starts_with("% bar1\n\\time 4/4"));
+        staff.auto_clef = true;
+        assert!(staff.render_lilypond().contains("% bar2\n\\clef bass "));
+        staff.clef = Clef::Treble8;
+        staff.auto_clef = false;
+        assert!(staff
+            .render_lilypond()
+            .starts_with("% bar1\n\\clef \"treble_8\" \\time 4/4"));
+        Ok(())
+    }
 }
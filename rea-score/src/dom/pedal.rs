@@ -0,0 +1,273 @@
+//! Piano pedals from MIDI controllers (CC64, CC66, CC67).
+//!
+//! Controller values are reduced to pedal state changes, which are
+//! attached as [ChordNotations] to the nearest note attack of the
+//! voice, pedal is recorded in.
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    notation::{
+        chord_notations::{ChordNotations, Pedal},
+        NotationError, NotationType,
+    },
+    primitives::{AbsolutePosition, EventInfo, EventType},
+};
+
+use super::{Part, Staff, Voice};
+
+/// Controller values from this one mean pressed pedal.
+pub const PEDAL_DOWN_THRESHOLD: u8 = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PedalEvent {
+    /// 1-based, the same as voice index.
+    pub channel: u8,
+    pub position: AbsolutePosition,
+    pub notation: ChordNotations,
+}
+impl PedalEvent {
+    pub fn new(
+        channel: u8,
+        position: AbsolutePosition,
+        pedal: Pedal,
+        pressed: bool,
+    ) -> Self {
+        let notation = match pressed {
+            true => ChordNotations::PedalOn(pedal),
+            false => ChordNotations::PedalOff(pedal),
+        };
+        Self {
+            channel,
+            position,
+            notation,
+        }
+    }
+}
+
+/// Reduce controller messages to pedal state changes.
+///
+/// Controllers are `(position, channel, controller, value)`, sorted
+/// by position. Not pedal controllers are ignored. Changes before
+/// `start` are not returned, but pedals, held at `start`, are
+/// pressed at it.
+pub fn pedal_changes(
+    controllers: impl IntoIterator<Item = (AbsolutePosition, u8, u8, u8)>,
+    start: &AbsolutePosition,
+) -> Vec<PedalEvent> {
+    let mut state: HashMap<(u8, Pedal), bool> = HashMap::new();
+    let mut changes = Vec::new();
+    let mut started = false;
+    let held = |state: &HashMap<(u8, Pedal), bool>| {
+        state
+            .iter()
+            .filter(|(_, pressed)| **pressed)
+            .map(|((channel, pedal), _)| (*channel, *pedal))
+            .collect::<Vec<_>>()
+    };
+    for (position, channel, controller, value) in controllers {
+        let pedal = match Pedal::from_controller(controller) {
+            Some(pedal) => pedal,
+            None => continue,
+        };
+        if !started && position >= *start {
+            started = true;
+            let mut held = held(&state);
+            held.sort();
+            changes.extend(held.into_iter().map(|(channel, pedal)| {
+                PedalEvent::new(channel, start.clone(), pedal, true)
+            }));
+        }
+        let pressed = value >= PEDAL_DOWN_THRESHOLD;
+        if state.insert((channel, pedal), pressed).unwrap_or(false) == pressed
+        {
+            continue;
+        }
+        if started {
+            changes.push(PedalEvent::new(channel, position, pedal, pressed));
+        }
+    }
+    if !started {
+        let mut held = held(&state);
+        held.sort();
+        changes.extend(held.into_iter().map(|(channel, pedal)| {
+            PedalEvent::new(channel, start.clone(), pedal, true)
+        }));
+    }
+    changes
+}
+
+impl Voice {
+    /// Attach pedal mark to the note or chord, which attack is the
+    /// nearest to the position.
+    ///
+    /// Voice without notes and mark outside of TimeMap are ignored.
+    pub fn apply_pedal(
+        &mut self,
+        position: &AbsolutePosition,
+        notation: ChordNotations,
+    ) -> Result<(), NotationError> {
+        let time_map = self.time_map.clone();
+        // compare notated positions, as events are placed by them.
+        let target = match time_map.pos_relative_from_absolute(position) {
+            Some(relative) => time_map.pos_absolute_from_relative(&relative),
+            None => return Ok(()),
+        }
+        .get();
+        let distance = |event: &EventInfo| {
+            let pos =
+                time_map.pos_absolute_from_relative(&event.position).get();
+            match pos > target {
+                true => pos - target,
+                false => target - pos,
+            }
+        };
+        match self
+            .note_events_mut()
+            .into_iter()
+            .min_by_key(|event| distance(event))
+        {
+            None => Ok(()),
+            Some(event) => event.push_notation(NotationType::Chord(notation)),
+        }
+    }
+}
+
+impl Part {
+    /// Attach pedal marks to voices of the same channel.
+    ///
+    /// If there is no such voice, the first voice of the part gets
+    /// the mark.
+    pub fn apply_pedals(
+        &mut self,
+        pedals: impl IntoIterator<Item = PedalEvent>,
+    ) -> Result<(), NotationError> {
+        for pedal in pedals {
+            let has_voice = self
+                .staves
+                .iter()
+                .flat_map(|staff| staff.voices.iter())
+                .any(|voice| voice.index == pedal.channel);
+            let voice = self
+                .staves
+                .iter_mut()
+                .flat_map(|staff| staff.voices.iter_mut())
+                .find(|voice| !has_voice || voice.index == pedal.channel);
+            if let Some(voice) = voice {
+                voice.apply_pedal(&pedal.position, pedal.notation)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Staff {
+    /// If any voice of the staff has pedal marks.
+    pub fn has_pedals(&self) -> bool {
+        self.voices.iter().any(|voice| {
+            voice
+                .measures
+                .iter()
+                .any(|measure| has_pedal_marks(measure.events()))
+        })
+    }
+}
+
+fn has_pedal_marks(events: &VecDeque<EventInfo>) -> bool {
+    events.iter().any(|event| {
+        let notations = match &event.event {
+            EventType::Note(note) => note.chord_notations(),
+            EventType::Chord(chord) => chord.chord_notations(),
+            EventType::Tuplet(tuplet) => {
+                return has_pedal_marks(tuplet.container().events())
+            }
+            _ => return false,
+        };
+        notations.iter().any(|notation| {
+            matches!(
+                notation,
+                ChordNotations::PedalOn(_) | ChordNotations::PedalOff(_)
+            )
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use crate::{
+        dom::{Voice, VoiceRenderOptions},
+        notation::chord_notations::Pedal,
+        primitives::{
+            AbsolutePosition, EventInfo, EventType, Length, MeasureInfo, Note,
+            Pitch, RelativePosition, TimeMap,
+        },
+    };
+
+    use super::{pedal_changes, PedalEvent};
+
+    #[test]
+    fn test_pedal_changes() {
+        let pos = |position: f64| AbsolutePosition::from(position);
+        let controllers = vec![
+            (pos(0.0), 1, 64, 127),
+            (pos(0.5), 1, 1, 30),
+            (pos(1.0), 1, 64, 100),
+            (pos(3.5), 1, 64, 0),
+            (pos(3.75), 1, 64, 127),
+            (pos(5.0), 2, 67, 127),
+        ];
+        assert_eq!(
+            pedal_changes(controllers.clone(), &pos(0.0)),
+            vec![
+                PedalEvent::new(1, pos(0.0), Pedal::Sustain, true),
+                PedalEvent::new(1, pos(3.5), Pedal::Sustain, false),
+                PedalEvent::new(1, pos(3.75), Pedal::Sustain, true),
+                PedalEvent::new(2, pos(5.0), Pedal::UnaCorda, true),
+            ]
+        );
+        assert_eq!(
+            pedal_changes(controllers, &pos(2.0)),
+            vec![
+                PedalEvent::new(1, pos(2.0), Pedal::Sustain, true),
+                PedalEvent::new(1, pos(3.5), Pedal::Sustain, false),
+                PedalEvent::new(1, pos(3.75), Pedal::Sustain, true),
+                PedalEvent::new(2, pos(5.0), Pedal::UnaCorda, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_pedal() {
+        let time_map = Arc::new(TimeMap::new(
+            vec![MeasureInfo::new(1, TimeSignature::new(4, 4))],
+            AbsolutePosition::from(0.0),
+        ));
+        let mut voice = Voice::from(time_map);
+        for idx in 0..4 {
+            voice
+                .insert_event(EventInfo::new(
+                    RelativePosition::new(1, Fraction::new(idx, 4_u64)),
+                    Length::from(Fraction::new(1_u64, 4_u64)),
+                    EventType::Note(Note::new(Pitch::from_midi(
+                        60, None, None,
+                    ))),
+                ))
+                .unwrap();
+        }
+        // pedal changes between attacks go to the nearest ones.
+        for event in [
+            PedalEvent::new(1, 0.0.into(), Pedal::Sustain, true),
+            PedalEvent::new(1, 0.45.into(), Pedal::Sustain, false),
+            PedalEvent::new(1, 0.55.into(), Pedal::Sustain, true),
+        ] {
+            voice.apply_pedal(&event.position, event.notation).unwrap();
+        }
+        assert!(voice
+            .render_measures(VoiceRenderOptions::default())
+            .contains(r"c'4\sustainOn c'4 c'4\sustainOff\sustainOn c'4 |"));
+    }
+}
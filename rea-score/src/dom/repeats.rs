@@ -0,0 +1,241 @@
+//! Repeated sections and alternative endings, written as
+//! `\repeat volta` and `\alternative` around rendered measures.
+use itertools::Itertools;
+
+use crate::primitives::MeasureMarks;
+
+/// Repeated section. Indexes are of measures in the rendered range.
+#[derive(Debug, PartialEq)]
+struct RepeatSection {
+    start: usize,
+    /// The first measure after the repeated body.
+    body_end: usize,
+    times: u8,
+    /// Volta number, the first and the last measure of every
+    /// alternative ending.
+    alternatives: Vec<(u8, usize, usize)>,
+}
+impl RepeatSection {
+    /// The last measure of section, including alternative endings.
+    fn last(&self) -> usize {
+        match self.alternatives.last() {
+            Some((_, _, last)) => *last,
+            None => self.body_end - 1,
+        }
+    }
+}
+
+/// Find repeated sections and starts of repeats, that are not closed
+/// inside the range.
+///
+/// Repeat, that ends without start, is repeated from the end of the
+/// previous section or from the first measure, which is also the
+/// case, when range starts inside the repeated section.
+///
+/// The first alternative ending lasts its own length, but never ends
+/// before the end of repeat.
+fn repeat_sections(
+    marks: &[&MeasureMarks],
+) -> (Vec<RepeatSection>, Vec<usize>) {
+    let mut sections: Vec<RepeatSection> = Vec::new();
+    let mut unclosed = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut idx = 0;
+    while idx < marks.len() {
+        if marks[idx].repeat_start {
+            unclosed.extend(open.replace(idx));
+        }
+        let times = match marks[idx].repeat_end {
+            Some(times) => times,
+            None => {
+                idx += 1;
+                continue;
+            }
+        };
+        let start = match open.take() {
+            Some(start) => start,
+            None => sections.last().map_or(0, |section| section.last() + 1),
+        };
+        let mut section = RepeatSection {
+            start,
+            body_end: idx + 1,
+            times,
+            alternatives: Vec::new(),
+        };
+        let volta = (start + 1..=idx)
+            .find_map(|idx| marks[idx].volta.map(|volta| (idx, volta)));
+        if let Some((first, (number, length))) = volta {
+            let end = first + length as usize;
+            if end <= idx {
+                log::warn!(
+                    "volta {number} ends before the end of repeat, \
+                    extended to it"
+                );
+            }
+            let last = end.max(idx + 1).min(marks.len()) - 1;
+            section.body_end = first;
+            section.alternatives.push((number, first, last));
+            let mut next = last + 1;
+            while let Some(Some((number, length))) =
+                marks.get(next).map(|measure| measure.volta)
+            {
+                let last = (next + length as usize).min(marks.len()) - 1;
+                section.alternatives.push((number, next, last));
+                next = last + 1;
+            }
+        }
+        idx = section.last() + 1;
+        sections.push(section);
+    }
+    unclosed.extend(open);
+    (sections, unclosed)
+}
+
+/// Indexes of measures, at which repeated sections, their bodies and
+/// alternative endings start or end, so nothing can be joined across
+/// them.
+pub fn repeat_bounds(marks: &[&MeasureMarks]) -> Vec<usize> {
+    let (sections, unclosed) = repeat_sections(marks);
+    sections
+        .iter()
+        .flat_map(|section| {
+            let alternatives =
+                section.alternatives.iter().map(|(_, first, _)| *first);
+            [section.start, section.body_end, section.last() + 1]
+                .into_iter()
+                .chain(alternatives)
+        })
+        .chain(unclosed)
+        .collect()
+}
+
+/// Wrap rendered measures into `\repeat volta` and `\alternative`.
+///
+/// Every alternative ending is marked by its volta number.
+///
+/// `marks` and `measures` are of the same measures. Empty measures
+/// are skipped, as they are joined to the previous ones (see
+/// [super::rests]). Repeat, that is not closed inside the range, is
+/// written by start barline only.
+pub fn render_repeats(
+    marks: &[&MeasureMarks],
+    mut measures: Vec<String>,
+) -> String {
+    let (sections, unclosed) = repeat_sections(marks);
+    for idx in unclosed {
+        measures[idx] = with_prefix(&measures[idx], r#"\bar ".|:""#);
+    }
+    let mut rendered = Vec::new();
+    let mut idx = 0;
+    for section in sections {
+        rendered.push(join(&measures[idx..section.start]));
+        let body = join(&measures[section.start..section.body_end]);
+        let mut repeat =
+            format!(r"\repeat volta {} {{ {body} }}", section.times);
+        if !section.alternatives.is_empty() {
+            let alternatives = section
+                .alternatives
+                .iter()
+                .map(|(number, first, last)| {
+                    format!(
+                        r"\volta {number} {{ {} }}",
+                        join(&measures[*first..=*last])
+                    )
+                })
+                .join(" ");
+            repeat = format!(r"{repeat} \alternative {{ {alternatives} }}");
+        }
+        rendered.push(repeat);
+        idx = section.last() + 1;
+    }
+    rendered.push(join(&measures[idx..]));
+    join(&rendered)
+}
+
+fn join(measures: &[String]) -> String {
+    measures
+        .iter()
+        .filter(|measure| !measure.is_empty())
+        .join(" ")
+}
+
+/// Insert command after the `% bar` comment of rendered measure.
+fn with_prefix(measure: &str, prefix: &str) -> String {
+    match measure.split_once('\n') {
+        Some((comment, rest)) => {
+            format!("{comment}\n{prefix} {}", rest.trim_start())
+        }
+        None => format!("{prefix} {measure}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::primitives::MeasureMarks;
+
+    use super::render_repeats;
+
+    fn measures(amount: usize) -> Vec<String> {
+        (1..=amount)
+            .map(|idx| format!("% bar{idx}\nc1 |"))
+            .collect()
+    }
+
+    fn render(marks: &[MeasureMarks]) -> String {
+        let marks = marks.iter().collect::<Vec<_>>();
+        render_repeats(&marks, measures(marks.len())).replace("\n", " ")
+    }
+
+    #[test]
+    fn test_repeats() {
+        let mut marks = vec![MeasureMarks::default(); 4];
+        assert_eq!(render(&marks), measures(4).join(" ").replace("\n", " "));
+        marks[1].repeat_start = true;
+        marks[2].repeat_end = Some(2);
+        assert_eq!(
+            render(&marks),
+            concat!(
+                r"% bar1 c1 | \repeat volta 2 { % bar2 c1 | % bar3 c1 | } ",
+                "% bar4 c1 |"
+            )
+        );
+        // range starts inside the repeated section
+        marks[1].repeat_start = false;
+        assert!(render(&marks).starts_with(r"\repeat volta 2 { % bar1"));
+        // range ends inside the repeated section
+        marks[1].repeat_start = true;
+        marks[2].repeat_end = None;
+        assert_eq!(
+            render(&marks),
+            r#"% bar1 c1 | % bar2 \bar ".|:" c1 | % bar3 c1 | % bar4 c1 |"#
+        );
+    }
+
+    #[test]
+    fn test_alternatives() {
+        let mut marks = vec![MeasureMarks::default(); 6];
+        marks[0].repeat_start = true;
+        marks[1].volta = Some((1, 2));
+        marks[2].repeat_end = Some(2);
+        marks[3].volta = Some((2, 1));
+        assert_eq!(
+            render(&marks),
+            concat!(
+                r"\repeat volta 2 { % bar1 c1 | } ",
+                r"\alternative { \volta 1 { % bar2 c1 | % bar3 c1 | } ",
+                r"\volta 2 { % bar4 c1 | } } % bar5 c1 | % bar6 c1 |"
+            )
+        );
+        // the first ending lasts beyond the end of repeat
+        marks[2].repeat_end = None;
+        marks[1].repeat_end = Some(2);
+        assert_eq!(
+            render(&marks),
+            concat!(
+                r"\repeat volta 2 { % bar1 c1 | } ",
+                r"\alternative { \volta 1 { % bar2 c1 | % bar3 c1 | } ",
+                r"\volta 2 { % bar4 c1 | } } % bar5 c1 | % bar6 c1 |"
+            )
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Multi-measure rests of extracted parts.
+//!
+//! Consecutive measures, that hold only rests, are written as a single
+//! `R1*N`, which LilyPond compresses inside `\compressMMRests`. In the
+//! score view measures are kept expanded.
+
+/// Measure, rendered before joining rests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedMeasure {
+    pub index: u32,
+    /// Commands before events: time signature, key, clef, marks etc.
+    pub prefix: String,
+    pub events: String,
+    /// Duration of the whole-measure rest, if measure holds only it.
+    pub rest: Option<String>,
+}
+impl RenderedMeasure {
+    pub fn render(&self) -> String {
+        format!("% bar{}\n{} {} |", self.index, self.prefix, self.events)
+    }
+}
+
+/// Join consecutive whole-measure rests into multi-measure rests.
+///
+/// Multi-measure rest is continued only by measures of the same
+/// duration without prefix, so time signature changes, marks and
+/// `bounds` (see [super::repeats::repeat_bounds]) start the new one.
+/// Joined measures become empty strings, so the result is indexed as
+/// `measures` are.
+pub fn compress_rests(
+    measures: Vec<RenderedMeasure>,
+    bounds: &[usize],
+) -> Vec<String> {
+    let mut rendered = vec![String::new(); measures.len()];
+    let mut idx = 0;
+    while idx < measures.len() {
+        let mut measure = measures[idx].clone();
+        let duration = match &measure.rest {
+            None => {
+                rendered[idx] = measure.render();
+                idx += 1;
+                continue;
+            }
+            Some(duration) => duration,
+        };
+        let amount = measures[idx + 1..]
+            .iter()
+            .enumerate()
+            .take_while(|(offset, next)| {
+                next.rest.as_ref() == Some(duration)
+                    && next.prefix.is_empty()
+                    && !bounds.contains(&(idx + 1 + offset))
+            })
+            .count()
+            + 1;
+        if amount > 1 {
+            measure.events = format!("R{duration}*{amount}");
+        }
+        rendered[idx] = measure.render();
+        idx += amount;
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress_rests, RenderedMeasure};
+
+    fn rest(index: u32, prefix: &str, duration: &str) -> RenderedMeasure {
+        RenderedMeasure {
+            index,
+            prefix: prefix.to_string(),
+            events: format!("R{duration}"),
+            rest: Some(duration.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_compress_rests() {
+        let measures = vec![
+            rest(1, r"\time 4/4", "1"),
+            rest(2, "", "1"),
+            rest(3, "", "1"),
+            rest(4, r"\time 3/4", "2."),
+            rest(5, "", "2."),
+            RenderedMeasure {
+                index: 6,
+                prefix: String::new(),
+                events: "c'2.".to_string(),
+                rest: None,
+            },
+            rest(7, "", "2."),
+        ];
+        assert_eq!(
+            compress_rests(measures.clone(), &[]),
+            vec![
+                "% bar1\n\\time 4/4 R1*3 |",
+                "",
+                "",
+                "% bar4\n\\time 3/4 R2.*2 |",
+                "",
+                "% bar6\n c'2. |",
+                "% bar7\n R2. |",
+            ]
+        );
+        // repeated section starts at the third measure
+        assert_eq!(
+            compress_rests(measures[..3].to_vec(), &[2]),
+            vec!["% bar1\n\\time 4/4 R1*2 |", "", "% bar3\n R1 |"]
+        );
+    }
+}
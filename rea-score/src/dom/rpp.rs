@@ -0,0 +1,486 @@
+//! Offline parsing of REAPER project files (.rpp).
+//!
+//! Only the things, needed for building Parts are read: tracks names,
+//! MIDI items with their notes and notation events, project tempo and
+//! time signatures from tempo envelope, project markers and regions.
+//!
+//! Limitations:
+//! - only the first MIDI take of item is read;
+//! - item looping, take offsets and playrate are ignored;
+//! - positions are computed as if tempo ramps were instant tempo
+//!   changes, though ramps are written as rit. or accel.
+use std::{
+    collections::HashSet, error::Error, ops::RangeInclusive, path::Path,
+};
+
+use base64::Engine;
+
+use super::{
+    smf::{check_time_signature, parts_from_tracks, SmfTrack},
+    Part,
+};
+
+/// All item positions are converted to this resolution.
+const PPQ: u64 = 960;
+
+/// Read REAPER project and build Part for every track, that holds
+/// notes.
+///
+/// Part name is taken from the track name.
+pub fn from_rpp(path: impl AsRef<Path>) -> Result<Vec<Part>, Box<dyn Error>> {
+    let tracks = read_rpp(path)?;
+    parts_from_tracks(tracks, PPQ, None)
+}
+
+/// The same as [from_rpp], but Parts hold only the given measures
+/// (1-based, inclusive).
+pub fn from_rpp_in_bars(
+    path: impl AsRef<Path>,
+    bars: RangeInclusive<u32>,
+) -> Result<Vec<Part>, Box<dyn Error>> {
+    let tracks = read_rpp(path)?;
+    parts_from_tracks(tracks, PPQ, Some(bars))
+}
+
+fn read_rpp(path: impl AsRef<Path>) -> Result<Vec<SmfTrack>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    parse_rpp(&text)
+}
+
+/// Tempo point: position in seconds, bpm, ramp to the next point.
+#[derive(Debug, Clone, PartialEq)]
+struct TempoPoint {
+    seconds: f64,
+    bpm: f64,
+    ramp: bool,
+}
+
+/// Converts seconds to ticks of [PPQ] resolution.
+#[derive(Debug, Clone, PartialEq)]
+struct TempoMap {
+    points: Vec<TempoPoint>,
+}
+impl TempoMap {
+    fn ticks(&self, seconds: f64) -> u64 {
+        let mut quarters = 0.0;
+        for (idx, point) in self.points.iter().enumerate() {
+            if point.seconds >= seconds {
+                break;
+            }
+            let end = match self.points.get(idx + 1) {
+                Some(next) if next.seconds < seconds => next.seconds,
+                _ => seconds,
+            };
+            quarters += (end - point.seconds) * point.bpm / 60.0;
+        }
+        (quarters * PPQ as f64).round() as u64
+    }
+}
+
+/// Line of project file, split to tokens, respecting quotes.
+fn tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    for ch in line.trim().chars() {
+        match quote {
+            Some(q) if ch == q => {
+                quote = None;
+                // fields are positional, so empty string is kept
+                if current.is_empty() {
+                    tokens.push(String::new());
+                }
+            }
+            Some(_) => current.push(ch),
+            None => match ch {
+                '"' | '\'' | '`' => quote = Some(ch),
+                ch if ch.is_whitespace() => {
+                    if !current.is_empty() {
+                        tokens.push(std::mem::take(&mut current));
+                    }
+                }
+                ch => current.push(ch),
+            },
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_token<T: std::str::FromStr>(
+    tokens: &[String],
+    idx: usize,
+) -> Result<T, String> {
+    tokens
+        .get(idx)
+        .and_then(|tok| tok.parse().ok())
+        .ok_or(format!("Can not parse line: {}", tokens.join(" ")))
+}
+
+fn parse_hex(tokens: &[String], idx: usize) -> Result<u8, String> {
+    tokens
+        .get(idx)
+        .and_then(|tok| u8::from_str_radix(tok, 16).ok())
+        .ok_or(format!("Can not parse MIDI event: {}", tokens.join(" ")))
+}
+
+/// MIDI item, which is currently parsed.
+#[derive(Debug, Default)]
+struct ItemState {
+    position: f64,
+    /// resolution of the source
+    ppq: u64,
+    /// current tick of the source
+    tick: u64,
+    in_source: bool,
+    source_read: bool,
+    /// base64 lines of the current notation event.
+    notation: Option<String>,
+}
+
+/// Events are stored, until tempo map is fully known.
+enum ItemEvent {
+    /// channel, note, velocity
+    NoteOn(u8, u8, u8),
+    NoteOff(u8, u8),
+    /// channel, controller, value
+    Controller(u8, u8, u8),
+    Notation(Vec<u8>),
+}
+
+/// Position of item in seconds, ppq of source, tick in source, event.
+type ItemEvents = Vec<(f64, u64, u64, ItemEvent)>;
+
+fn parse_rpp(text: &str) -> Result<Vec<SmfTrack>, Box<dyn Error>> {
+    let mut tempo = TempoMap {
+        points: vec![TempoPoint {
+            seconds: 0.0,
+            bpm: 120.0,
+            ramp: false,
+        }],
+    };
+    // seconds, numerator, denominator
+    let mut time_signatures: Vec<(f64, u32, u32)> = Vec::new();
+    let mut tracks: Vec<(Option<String>, ItemEvents)> = Vec::new();
+    // seconds, name, is region
+    let mut markers: Vec<(f64, String, bool)> = Vec::new();
+    let mut regions: HashSet<u32> = HashSet::new();
+
+    let mut path: Vec<String> = Vec::new();
+    let mut item = ItemState::default();
+    for line in text.lines() {
+        let tokens = tokens(line);
+        let first = match tokens.first() {
+            Some(tok) => tok.as_str(),
+            None => continue,
+        };
+        if let Some(notation) = &mut item.notation {
+            if first == ">" {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(notation.as_bytes())?;
+                item.notation = None;
+                path.pop();
+                if let Some((_, events)) = tracks.last_mut() {
+                    events.push((
+                        item.position,
+                        item.ppq,
+                        item.tick,
+                        ItemEvent::Notation(data),
+                    ));
+                }
+            } else {
+                notation.push_str(first);
+            }
+            continue;
+        }
+        if first == ">" {
+            if path.pop().as_deref() == Some("SOURCE") && item.in_source {
+                item.in_source = false;
+                item.source_read = true;
+            }
+            continue;
+        }
+        if let Some(block) = first.strip_prefix('<') {
+            path.push(block.to_string());
+            match block {
+                "TRACK" => tracks.push((None, Vec::new())),
+                "ITEM" => item = ItemState::default(),
+                "SOURCE"
+                    if !item.source_read
+                        && matches!(
+                            tokens.get(1).map(|t| t.as_str()),
+                            Some("MIDI") | Some("MIDIPOOL")
+                        ) =>
+                {
+                    item.in_source = true;
+                    item.tick = 0;
+                    item.ppq = PPQ;
+                }
+                "X" | "x" if item.in_source => {
+                    item.tick += parse_token::<u64>(&tokens, 1)?;
+                    item.notation = Some(String::new());
+                }
+                _ => (),
+            }
+            continue;
+        }
+        let parent = path.last().map(|p| p.as_str());
+        match (parent, first) {
+            (Some("REAPER_PROJECT"), "TEMPO") => {
+                tempo.points[0].bpm = parse_token(&tokens, 1)?;
+                let (num, denom) =
+                    (parse_token(&tokens, 2)?, parse_token(&tokens, 3)?);
+                check_time_signature(num, denom).map_err(|err| {
+                    format!("{err}: {}", tokens.join(" "))
+                })?;
+                time_signatures.push((0.0, num, denom));
+            }
+            (Some("TEMPOENVEX"), "PT") => {
+                let seconds: f64 = parse_token(&tokens, 1)?;
+                let bpm: f64 = parse_token(&tokens, 2)?;
+                // shape 0 is linear, the others are square
+                let ramp = parse_token::<i32>(&tokens, 3) == Ok(0);
+                match tempo
+                    .points
+                    .iter_mut()
+                    .find(|point| point.seconds == seconds)
+                {
+                    Some(point) => {
+                        point.bpm = bpm;
+                        point.ramp = ramp;
+                    }
+                    None => {
+                        tempo.points.push(TempoPoint { seconds, bpm, ramp })
+                    }
+                }
+                // time signature is packed as numerator + (denom << 16)
+                if let Ok(packed) = parse_token::<i64>(&tokens, 4) {
+                    if packed > 0 {
+                        let num = (packed & 0xFFFF) as u32;
+                        let denom = (packed >> 16) as u32;
+                        check_time_signature(num, denom).map_err(|err| {
+                            format!("{err}: {}", tokens.join(" "))
+                        })?;
+                        time_signatures.push((seconds, num, denom));
+                    }
+                }
+            }
+            (Some("REAPER_PROJECT"), "MARKER") => {
+                let index: u32 = parse_token(&tokens, 1)?;
+                let seconds: f64 = parse_token(&tokens, 2)?;
+                let name = tokens.get(3).cloned().unwrap_or_default();
+                let is_region = parse_token::<u32>(&tokens, 4)
+                    .map_or(false, |flags| flags & 1 != 0);
+                // region is written twice: at its start and at its end
+                if is_region && !regions.insert(index) {
+                    continue;
+                }
+                markers.push((seconds, name, is_region));
+            }
+            (Some("TRACK"), "NAME") => {
+                if let Some((name, _)) = tracks.last_mut() {
+                    *name = tokens.get(1).filter(|n| !n.is_empty()).cloned();
+                }
+            }
+            (Some("ITEM"), "POSITION") => {
+                item.position = parse_token(&tokens, 1)?;
+            }
+            (Some("SOURCE"), "HASDATA") if item.in_source => {
+                item.ppq = parse_token(&tokens, 2)?;
+                if item.ppq == 0 {
+                    return Err(format!(
+                        "Zero resolution of MIDI source: {}",
+                        tokens.join(" ")
+                    )
+                    .into());
+                }
+            }
+            (Some("SOURCE"), "E") | (Some("SOURCE"), "e")
+                if item.in_source =>
+            {
+                item.tick += parse_token::<u64>(&tokens, 1)?;
+                let status = parse_hex(&tokens, 2)?;
+                let channel = (status & 0x0F) + 1;
+                let note = parse_hex(&tokens, 3)?;
+                let velocity = parse_hex(&tokens, 4)?;
+                let event = match status & 0xF0 {
+                    0x90 if velocity > 0 => {
+                        ItemEvent::NoteOn(channel, note, velocity)
+                    }
+                    0x90 | 0x80 => ItemEvent::NoteOff(channel, note),
+                    0xB0 => ItemEvent::Controller(channel, note, velocity),
+                    _ => continue,
+                };
+                if let Some((_, events)) = tracks.last_mut() {
+                    events.push((item.position, item.ppq, item.tick, event));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    tempo.points.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+    let mut conductor = SmfTrack::default();
+    for point in tempo.points.iter() {
+        conductor.tempo(tempo.ticks(point.seconds), point.bpm, point.ramp);
+    }
+    for (seconds, name, is_region) in markers {
+        conductor.marker(tempo.ticks(seconds), name, is_region);
+    }
+    for (seconds, num, denom) in time_signatures {
+        conductor.time_signature(tempo.ticks(seconds), num, denom);
+    }
+    let mut parsed = vec![conductor];
+    for (name, events) in tracks {
+        let mut track = SmfTrack::default();
+        track.name = name;
+        for (position, ppq, tick, event) in events {
+            let tick = tempo.ticks(position) + tick * PPQ / ppq;
+            match event {
+                ItemEvent::NoteOn(channel, note, velocity) => {
+                    track.note_on(tick, channel, note, velocity)
+                }
+                ItemEvent::NoteOff(channel, note) => {
+                    track.note_off(tick, channel, note)
+                }
+                ItemEvent::Controller(channel, controller, value) => {
+                    track.controller(tick, channel, controller, value)
+                }
+                ItemEvent::Notation(raw) => track.notation(tick, raw),
+            }
+        }
+        track.finish();
+        parsed.push(track);
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lilypond_render::RendersToLilypond;
+
+    use super::{parse_rpp, parts_from_tracks, PPQ};
+
+    static PROJECT: &str = r#"<REAPER_PROJECT 0.1 "6.71/linux-x86_64" 1672000000
+  TEMPO 60 3 4
+  <TRACK {3B2C5B4E-1D1A-4F44-9A57-F1C1D0F0A5E5}
+    NAME "Flute"
+    <ITEM
+      POSITION 1
+      LENGTH 3
+      <SOURCE MIDI
+        HASDATA 1 480 QN
+        E 0 90 3c 60
+        E 480 80 3c 00
+        E 0 90 3e 60
+        E 960 80 3e 00
+      >
+    >
+  >
+>
+"#;
+
+    #[test]
+    fn test_parse_rpp() {
+        let tracks = parse_rpp(PROJECT).expect("can not parse project");
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[1].name, Some("Flute".to_string()));
+        let parts =
+            parts_from_tracks(tracks, PPQ, None).expect("can not build parts");
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].time_map.end_measure(), 2);
+        // item starts at 1 second, which is one quarter at 60 bpm.
+        let rendered = parts[0].render_lilypond();
+        assert!(rendered.starts_with("<< { % bar1\n\\time 3/4 r4 c'4"));
+        assert!(
+            rendered.contains("\\new Voice { % bar1\n\\tempo 4 = 60 s1*3/4 |")
+        );
+    }
+
+    #[test]
+    fn test_zero_ppq() {
+        let project =
+            PROJECT.replace("HASDATA 1 480 QN", "HASDATA 1 0 QN");
+        assert!(parse_rpp(&project).is_err());
+    }
+
+    #[test]
+    fn test_invalid_time_signature() {
+        let project = PROJECT.replace("TEMPO 60 3 4", "TEMPO 60 4 0");
+        assert!(parse_rpp(&project).is_err());
+        let project = PROJECT.replace("TEMPO 60 3 4", "TEMPO 60 0 4");
+        assert!(parse_rpp(&project).is_err());
+        let project = PROJECT.replace(
+            "  TEMPO 60 3 4\n",
+            "  TEMPO 60 3 4\n  <TEMPOENVEX\n    PT 2 60 1 4\n  >\n",
+        );
+        assert!(parse_rpp(&project).is_err());
+    }
+
+    #[test]
+    fn test_tempo_ramp() {
+        let project = PROJECT.replace(
+            "  <TRACK",
+            "  <TEMPOENVEX\n    PT 0 60 0\n    PT 3 90 1\n  >\n  <TRACK",
+        );
+        let tracks = parse_rpp(&project).expect("can not parse project");
+        let parts =
+            parts_from_tracks(tracks, PPQ, None).expect("can not build parts");
+        let rendered = parts[0].render_lilypond();
+        assert!(rendered.starts_with("<< { % bar1\n\\time 3/4 r4 c'4"));
+        assert!(rendered.contains(concat!(
+            "\\new Voice { % bar1\n\\tempo 4 = 60 ",
+            r#"\override TextSpanner.bound-details.left.text = "accel." "#,
+            r"s1*3/4\startTextSpan |"
+        )));
+        assert!(
+            rendered.contains("% bar2\n\\tempo 4 = 90 s1*3/4\\stopTextSpan")
+        );
+    }
+
+    #[test]
+    fn test_markers() {
+        let project = PROJECT.replace(
+            "  <TRACK",
+            concat!(
+                "  MARKER 1 3 Verse 0\n",
+                "  MARKER 2 3 \"ReaScore|double_bar|break\" 0\n",
+                "  MARKER 3 0 \"Intro part\" 1\n",
+                "  MARKER 3 3 \"\" 1\n",
+                "  <TRACK"
+            ),
+        );
+        let tracks = parse_rpp(&project).expect("can not parse project");
+        let parts =
+            parts_from_tracks(tracks, PPQ, None).expect("can not build parts");
+        let time_map = &parts[0].time_map;
+        let first = time_map.get_measure_info(1).marks;
+        assert_eq!(first.section, Some("Intro part".to_string()));
+        let second = time_map.get_measure_info(2).marks;
+        assert_eq!(second.rehearsal, Some("Verse".to_string()));
+        assert_eq!(second.section, None);
+        assert!(parts[0]
+            .render_lilypond()
+            .contains("% bar2\n\\bar \"||\" \\break "));
+    }
+
+    #[test]
+    fn test_repeats() {
+        let project = PROJECT.replace(
+            "  <TRACK",
+            concat!(
+                "  MARKER 1 0 \"ReaScore|repeat:start\" 0\n",
+                "  MARKER 2 3 \"ReaScore|repeat:end:3|jump:fine\" 0\n",
+                "  <TRACK"
+            ),
+        );
+        let tracks = parse_rpp(&project).expect("can not parse project");
+        let parts =
+            parts_from_tracks(tracks, PPQ, None).expect("can not build parts");
+        let rendered = parts[0].render_lilypond();
+        assert!(rendered.starts_with("<< { \\repeat volta 3 { % bar1\n"));
+        assert!(rendered.contains("\\fine | }"));
+    }
+}
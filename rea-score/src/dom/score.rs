@@ -0,0 +1,217 @@
+//! Score is a set of Parts, sharing the same TimeMap.
+use std::{error::Error, sync::Arc};
+
+use itertools::Itertools;
+use rea_rs::{Immutable, Position, Project, Reaper, Track};
+use serde::{Deserialize, Serialize};
+
+use crate::{lilypond_render::RendersToLilypond, primitives::TimeMap};
+
+use super::{parse_track_with_time_map, time_map_for_tracks, Part};
+
+/// LilyPond context, holding all staves of the Score.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum StaffGrouping {
+    /// Staves are joined by bracket and barlines.
+    #[default]
+    StaffGroup,
+    /// Staves are joined by bracket, barlines are separate.
+    ChoirStaff,
+    /// Staves are joined by brace.
+    PianoStaff,
+}
+impl StaffGrouping {
+    pub fn context(&self) -> &'static str {
+        match self {
+            Self::StaffGroup => "StaffGroup",
+            Self::ChoirStaff => "ChoirStaff",
+            Self::PianoStaff => "PianoStaff",
+        }
+    }
+}
+
+/// Tracks, Score is built from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScoreSource {
+    SelectedTracks,
+    /// All tracks inside the folder with the given name.
+    Folder(String),
+    /// Tracks with the given names, in the given order.
+    Tracks(Vec<String>),
+}
+impl ScoreSource {
+    /// Find tracks of the project, matching source.
+    pub fn tracks<'a>(
+        &self,
+        project: &'a Project,
+    ) -> Result<Vec<Track<'a, Immutable>>, Box<dyn Error>> {
+        let all_tracks = || {
+            (0..project.n_tracks()).filter_map(|idx| project.get_track(idx))
+        };
+        let tracks = match self {
+            Self::SelectedTracks => (0..project.n_selected_tracks())
+                .filter_map(|idx| project.get_selected_track(idx))
+                .collect(),
+            Self::Folder(folder) => all_tracks()
+                .filter(|track| {
+                    let mut parent = track.parent_track();
+                    while let Some(tr) = parent {
+                        if tr.name().ok().as_ref() == Some(folder) {
+                            return true;
+                        }
+                        parent = tr.parent_track();
+                    }
+                    false
+                })
+                .collect(),
+            Self::Tracks(names) => names
+                .iter()
+                .map(|name| {
+                    all_tracks()
+                        .find(|track| track.name().ok().as_ref() == Some(name))
+                        .ok_or(format!("Can not find track: {name}"))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        };
+        Ok(tracks)
+    }
+}
+
+#[derive(Debug)]
+pub struct Score {
+    pub time_map: Arc<TimeMap>,
+    pub parts: Vec<Part>,
+    pub grouping: StaffGrouping,
+}
+impl Score {
+    pub fn new(
+        time_map: Arc<TimeMap>,
+        parts: Vec<Part>,
+        grouping: StaffGrouping,
+    ) -> Self {
+        Self {
+            time_map,
+            parts,
+            grouping,
+        }
+    }
+
+    /// Build Score from already parsed Parts (e.g. from MIDI file).
+    ///
+    /// All parts should share the same TimeMap.
+    pub fn from_parts(
+        parts: Vec<Part>,
+        grouping: StaffGrouping,
+    ) -> Result<Self, String> {
+        let time_map = parts
+            .first()
+            .ok_or("Can not build Score without parts")?
+            .time_map
+            .clone();
+        if parts
+            .iter()
+            .any(|part| !Arc::ptr_eq(&part.time_map, &time_map))
+        {
+            return Err("All parts of Score should share TimeMap".into());
+        }
+        Ok(Self::new(time_map, parts, grouping))
+    }
+
+    /// Parse tracks of the current project in the given bounds.
+    pub fn parse_in_bounds(
+        source: &ScoreSource,
+        start_pos: impl Into<Position>,
+        end_pos: impl Into<Position>,
+        grouping: StaffGrouping,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (start_pos, end_pos) = (start_pos.into(), end_pos.into());
+        let project = Reaper::get().current_project();
+        let tracks = source.tracks(&project)?;
+        let time_map =
+            Arc::new(time_map_for_tracks(&tracks, start_pos, end_pos)?);
+        let parts = tracks
+            .into_iter()
+            .map(|track| {
+                parse_track_with_time_map(
+                    track,
+                    start_pos,
+                    end_pos,
+                    time_map.clone(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(time_map, parts, grouping))
+    }
+}
+impl RendersToLilypond for Score {
+    fn render_lilypond(&self) -> String {
+        format!(
+            r"\new {} << {} >>",
+            self.grouping.context(),
+            self.parts
+                .iter()
+                .enumerate()
+                .map(|(idx, part)| part.render_lilypond_staves(idx == 0))
+                .join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rea_rs::TimeSignature;
+
+    use crate::{
+        dom::{Part, Staff, Voice},
+        lilypond_render::RendersToLilypond,
+        primitives::{AbsolutePosition, MeasureInfo, TimeMap},
+    };
+
+    use super::{Score, StaffGrouping};
+
+    fn part(time_map: &Arc<TimeMap>, name: &str) -> Part {
+        let mut voice = Voice::from(time_map.clone());
+        voice.index = 1;
+        let mut part = Part::new(
+            time_map.clone(),
+            vec![Staff::new(time_map.clone(), 1, vec![voice])],
+        );
+        part.name = Some(name.to_string());
+        part
+    }
+
+    #[test]
+    fn test_score() {
+        let time_map = Arc::new(TimeMap::new(
+            vec![MeasureInfo::new(1, TimeSignature::new(4, 4))],
+            AbsolutePosition::from(0.0),
+        ));
+        let score = Score::from_parts(
+            vec![part(&time_map, "Soprano"), part(&time_map, "Alto")],
+            StaffGrouping::ChoirStaff,
+        )
+        .expect("can not build score");
+        assert_eq!(
+            score.render_lilypond(),
+            r#"\new ChoirStaff << "#.to_string()
+                + r#"\new Staff \with { instrumentName = "Soprano" } "#
+                + "{ % bar1\n\\time 4/4 r1 | } "
+                + r#"\new Staff \with { instrumentName = "Alto" } "#
+                + "{ % bar1\n\\time 4/4 r1 | } >>"
+        );
+
+        let other_map = Arc::new(TimeMap::new(
+            vec![MeasureInfo::new(1, TimeSignature::new(4, 4))],
+            AbsolutePosition::from(0.0),
+        ));
+        assert!(Score::from_parts(
+            vec![part(&time_map, "Soprano"), part(&other_map, "Alto")],
+            StaffGrouping::ChoirStaff,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,679 @@
+//! Offline parsing of Standard MIDI Files.
+//!
+//! Nothing here calls REAPER API: positions are converted by the
+//! [TimeMap], built from time-signature meta events of the file.
+use std::{
+    collections::HashMap, error::Error, ops::RangeInclusive, path::Path,
+    sync::Arc,
+};
+
+use fraction::Fraction;
+use midly::{Format, MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use rea_rs::{MidiMessage as _, Notation, NotationMessage, TimeSignature};
+
+use crate::{
+    lilypond_render::RendersToLilypond,
+    notation::{
+        measure_notations::{marker_notations_from_string, MeasureNotations},
+        message::MidiFuncs,
+        NotationType,
+    },
+    primitives::{
+        AbsolutePosition, EventInfo, EventType, KeyMode, KeySignature, Length,
+        MeasureInfo, Note, Pitch, TempoPoint, TimeMap,
+    },
+};
+
+use super::{
+    midi_parse::ParsedEvent,
+    part_from_events,
+    pedal::{pedal_changes, PedalEvent},
+    time_map_notations, Part,
+};
+
+/// REAPER writes notation events as meta events of this type.
+const NOTATION_META: u8 = 0x0F;
+
+/// The largest time signature denominator, accepted from files.
+pub(super) const MAX_DENOMINATOR: u32 = 64;
+
+/// Err, if time signature, read from file, can not be notated.
+pub(super) fn check_time_signature(
+    num: u32,
+    denom: u32,
+) -> Result<(), String> {
+    if num == 0 {
+        return Err("Time signature numerator is zero".to_string());
+    }
+    if !denom.is_power_of_two() || denom > MAX_DENOMINATOR {
+        return Err(format!("Invalid time signature denominator: {denom}"));
+    }
+    Ok(())
+}
+
+/// Read Standard MIDI File (format 0 or 1) and build Part for every
+/// track, that holds notes.
+///
+/// Tracks without notes (e.g. conductor track of format 1) are
+/// skipped. Part name is taken from the track name meta event.
+pub fn from_smf(path: impl AsRef<Path>) -> Result<Vec<Part>, Box<dyn Error>> {
+    let (tracks, ppq) = read_smf(path)?;
+    parts_from_tracks(tracks, ppq, None)
+}
+
+/// The same as [from_smf], but Parts hold only the given measures
+/// (1-based, inclusive).
+///
+/// Notes, crossing range bounds, are truncated.
+pub fn from_smf_in_bars(
+    path: impl AsRef<Path>,
+    bars: RangeInclusive<u32>,
+) -> Result<Vec<Part>, Box<dyn Error>> {
+    let (tracks, ppq) = read_smf(path)?;
+    parts_from_tracks(tracks, ppq, Some(bars))
+}
+
+fn read_smf(
+    path: impl AsRef<Path>,
+) -> Result<(Vec<SmfTrack>, u64), Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+    let smf = Smf::parse(&data)?;
+    if smf.header.format == Format::Sequential {
+        return Err(
+            "Sequential MIDI files (format 2) are not supported".into()
+        );
+    }
+    let ppq = match smf.header.timing {
+        Timing::Metrical(ppq) if ppq.as_int() == 0 => {
+            return Err("MIDI file has zero resolution".into())
+        }
+        Timing::Metrical(ppq) => ppq.as_int() as u64,
+        Timing::Timecode(..) => {
+            return Err(
+                "MIDI files with timecode timing are not supported".into()
+            )
+        }
+    };
+    let tracks = smf
+        .tracks
+        .iter()
+        .map(|track| SmfTrack::parse(track))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((tracks, ppq))
+}
+
+/// Build Parts from tracks, sharing the same TimeMap.
+///
+/// Tracks without notes are skipped.
+pub(super) fn parts_from_tracks(
+    tracks: Vec<SmfTrack>,
+    ppq: u64,
+    bars: Option<RangeInclusive<u32>>,
+) -> Result<Vec<Part>, Box<dyn Error>> {
+    let mut time_map = time_map_from_tracks(&tracks, ppq)?;
+    let track_notations = tracks
+        .iter()
+        .map(|track| track.track_notations(ppq, &time_map))
+        .collect::<Vec<_>>();
+    time_map.apply_measure_notations(time_map_notations(
+        track_notations.iter().flatten(),
+    ));
+    let (mut time_map, bounds) = match bars {
+        None => (time_map, (0, u64::MAX)),
+        Some(bars) => time_map_in_bars(&time_map, bars, ppq)?,
+    };
+    time_map.apply_tempo(tracks.iter().flat_map(|track| {
+        track.tempos.iter().map(|(tick, bpm, ramp)| {
+            TempoPoint::new(ticks_to_position(*tick, ppq), *bpm, *ramp)
+        })
+    }));
+    let time_map = Arc::new(time_map);
+    tracks
+        .into_iter()
+        .zip(track_notations)
+        .filter(|(track, _)| !track.notes.is_empty())
+        .map(|(track, notations)| -> Result<Part, Box<dyn Error>> {
+            let name = track.name.clone();
+            let events = track.parsed_events(ppq, &time_map, bounds)?;
+            let mut part = part_from_events(
+                events.into_iter().map(|ev| ev.apply_single_notations()),
+                time_map.clone(),
+            )?;
+            part.apply_track_notations(notations);
+            part.apply_pedals(track.pedals(ppq, bounds))?;
+            if let Some(inference) =
+                Part::global_render_settings().infer_dynamics
+            {
+                part.infer_dynamics(&inference);
+            }
+            part.name = name;
+            Ok(part)
+        })
+        .collect()
+}
+
+/// Note, built from note-on/note-off pair, positions in ticks.
+#[derive(Debug, Clone)]
+struct SmfNote {
+    channel: u8,
+    note: u8,
+    velocity: u8,
+    start: u64,
+    end: u64,
+}
+
+/// Track contents, independent of the file format, it was read from.
+#[derive(Debug, Default)]
+pub(super) struct SmfTrack {
+    pub name: Option<String>,
+    notes: Vec<SmfNote>,
+    /// tick, numerator, denominator
+    time_signatures: Vec<(u64, u32, u32)>,
+    /// tick, notation message
+    notations: Vec<(u64, NotationMessage)>,
+    key_signatures: Vec<(u64, KeySignature)>,
+    /// tick, quarter notes per minute, ramp to the next tempo
+    tempos: Vec<(u64, f64, bool)>,
+    /// tick, name, is region
+    markers: Vec<(u64, String, bool)>,
+    /// tick, channel, controller, value
+    controllers: Vec<(u64, u8, u8, u8)>,
+    /// starts and velocities of notes, that are not closed yet.
+    opened: HashMap<(u8, u8), Vec<(u64, u8)>>,
+}
+impl SmfTrack {
+    /// Err, if the track has invalid time signature (see
+    /// [check_time_signature]).
+    fn parse(track: &midly::Track) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut tick = 0_u64;
+        for event in track.iter() {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Midi { channel, message } => {
+                    // channels are 1-based in the rest of the crate.
+                    let channel = channel.as_int() + 1;
+                    match message {
+                        MidiMessage::NoteOn { key, vel }
+                            if vel.as_int() > 0 =>
+                        {
+                            parsed.note_on(
+                                tick,
+                                channel,
+                                key.as_int(),
+                                vel.as_int(),
+                            )
+                        }
+                        MidiMessage::NoteOn { key, .. }
+                        | MidiMessage::NoteOff { key, .. } => {
+                            parsed.note_off(tick, channel, key.as_int())
+                        }
+                        MidiMessage::Controller { controller, value } => {
+                            parsed.controller(
+                                tick,
+                                channel,
+                                controller.as_int(),
+                                value.as_int(),
+                            )
+                        }
+                        _ => (),
+                    }
+                }
+                TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+                    parsed.name =
+                        Some(String::from_utf8_lossy(name).to_string());
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(
+                    num,
+                    denom_pow,
+                    ..,
+                )) => {
+                    // overflowing power is rejected as zero.
+                    let denom =
+                        2_u32.checked_pow(denom_pow as u32).unwrap_or(0);
+                    check_time_signature(num as u32, denom)
+                        .map_err(|err| format!("{err} at tick {tick}"))?;
+                    parsed.time_signature(tick, num as u32, denom)
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(microseconds)) => {
+                    parsed.tempo(
+                        tick,
+                        60_000_000.0 / microseconds.as_int() as f64,
+                        false,
+                    )
+                }
+                TrackEventKind::Meta(MetaMessage::Marker(name)) => parsed
+                    .marker(
+                        tick,
+                        String::from_utf8_lossy(name).to_string(),
+                        false,
+                    ),
+                TrackEventKind::Meta(MetaMessage::KeySignature(
+                    fifths,
+                    minor,
+                )) => {
+                    let mode = match minor {
+                        true => KeyMode::Minor,
+                        false => KeyMode::Major,
+                    };
+                    parsed.key_signature(
+                        tick,
+                        KeySignature::from_fifths(fifths, mode),
+                    )
+                }
+                TrackEventKind::Meta(MetaMessage::Unknown(
+                    NOTATION_META,
+                    data,
+                )) => {
+                    let mut raw = vec![0xFF, NOTATION_META];
+                    raw.extend_from_slice(data);
+                    parsed.notation(tick, raw);
+                }
+                _ => (),
+            }
+        }
+        parsed.finish();
+        Ok(parsed)
+    }
+
+    /// channel is 1-based.
+    pub fn note_on(&mut self, tick: u64, channel: u8, note: u8, velocity: u8) {
+        self.opened
+            .entry((channel, note))
+            .or_default()
+            .push((tick, velocity));
+    }
+
+    /// channel is 1-based. Note-off without note-on is ignored.
+    pub fn note_off(&mut self, tick: u64, channel: u8, note: u8) {
+        let (start, velocity) = match self.opened.get_mut(&(channel, note)) {
+            Some(starts) if !starts.is_empty() => starts.remove(0),
+            _ => return,
+        };
+        self.notes.push(SmfNote {
+            channel,
+            note,
+            velocity,
+            start,
+            end: tick,
+        });
+    }
+
+    pub fn time_signature(&mut self, tick: u64, num: u32, denom: u32) {
+        self.time_signatures.push((tick, num, denom));
+    }
+
+    pub fn key_signature(&mut self, tick: u64, key: KeySignature) {
+        self.key_signatures.push((tick, key));
+    }
+
+    /// If `ramp`, tempo changes gradually to the next one.
+    pub fn tempo(&mut self, tick: u64, bpm: f64, ramp: bool) {
+        self.tempos.push((tick, bpm, ramp));
+    }
+
+    /// Project marker, or start of region.
+    pub fn marker(&mut self, tick: u64, name: String, is_region: bool) {
+        self.markers.push((tick, name, is_region));
+    }
+
+    /// channel is 1-based.
+    pub fn controller(
+        &mut self,
+        tick: u64,
+        channel: u8,
+        controller: u8,
+        value: u8,
+    ) {
+        self.controllers.push((tick, channel, controller, value));
+    }
+
+    /// Raw message should start from `0xFF 0x0F`, otherwise ignored.
+    pub fn notation(&mut self, tick: u64, raw: Vec<u8>) {
+        if let Some(msg) = NotationMessage::from_raw(raw) {
+            self.notations.push((tick, msg));
+        }
+    }
+
+    /// Should be called, when everything is pushed to the track.
+    pub fn finish(&mut self) {
+        self.opened.clear();
+        self.notes.sort_by_key(|note| note.start);
+        self.controllers.sort_by_key(|(tick, ..)| *tick);
+    }
+
+    fn parsed_events(
+        &self,
+        ppq: u64,
+        time_map: &TimeMap,
+        bounds: (u64, u64),
+    ) -> Result<Vec<ParsedEvent>, String> {
+        self.notes
+            .iter()
+            .filter(|note| note.end > bounds.0 && note.start < bounds.1)
+            .map(|note| {
+                let (start, end) =
+                    (note.start.max(bounds.0), note.end.min(bounds.1));
+                let position = time_map
+                    .pos_relative_from_absolute(&ticks_to_position(start, ppq))
+                    .ok_or(format!("Note is out of time map: {:?}", note))?;
+                let length = Length::from(Fraction::new(end - start, ppq * 4));
+                let mut parsed_note =
+                    Note::new(Pitch::from_midi(note.note, None, None));
+                // note, clipped by bounds, has no attack in them.
+                if note.start >= bounds.0 {
+                    parsed_note.set_velocity(note.velocity);
+                }
+                let event = EventInfo::new(
+                    position,
+                    length,
+                    EventType::Note(parsed_note),
+                );
+                Ok(ParsedEvent::new(
+                    note.channel,
+                    note.note,
+                    event,
+                    self.note_notations(note),
+                ))
+            })
+            .collect()
+    }
+
+    /// Pedal changes inside bounds.
+    fn pedals(&self, ppq: u64, bounds: (u64, u64)) -> Vec<PedalEvent> {
+        let controllers = self
+            .controllers
+            .iter()
+            .filter(|(tick, ..)| *tick < bounds.1)
+            .map(|(tick, channel, controller, value)| {
+                (ticks_to_position(*tick, ppq), *channel, *controller, *value)
+            });
+        pedal_changes(controllers, &ticks_to_position(bounds.0, ppq))
+    }
+
+    /// Measure and track notations of track notation events, with
+    /// indexes of measures they are placed in.
+    fn track_notations(
+        &self,
+        ppq: u64,
+        time_map: &TimeMap,
+    ) -> Vec<(u32, NotationType)> {
+        self.notations
+            .iter()
+            .filter_map(|(tick, msg)| {
+                let index = time_map
+                    .pos_relative_from_absolute(&ticks_to_position(
+                        *tick, ppq,
+                    ))?
+                    .get_measure_index();
+                let notations = MidiFuncs::parse_notations(msg.clone())?;
+                Some(notations.into_iter().filter_map(move |nt| match nt {
+                    NotationType::Measure(_) | NotationType::Track(_) => {
+                        Some((index, nt))
+                    }
+                    _ => None,
+                }))
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn note_notations(&self, note: &SmfNote) -> Vec<NotationType> {
+        self.notations
+            .iter()
+            .filter(|(tick, msg)| {
+                if *tick != note.start {
+                    return false;
+                }
+                match msg.notation() {
+                    Notation::Note {
+                        channel,
+                        note: nt,
+                        tokens: _,
+                    } => channel == note.channel && nt == note.note,
+                    _ => false,
+                }
+            })
+            .filter_map(|(_, msg)| MidiFuncs::parse_notations(msg.clone()))
+            .flatten()
+            .collect()
+    }
+}
+
+fn ticks_to_position(ticks: u64, ppq: u64) -> AbsolutePosition {
+    AbsolutePosition::from(Fraction::new(ticks, ppq * 4))
+}
+
+fn position_to_ticks(position: &AbsolutePosition, ppq: u64) -> u64 {
+    let ticks = position.get_quantized() * Fraction::from(ppq * 4);
+    let (num, denom) = (
+        *ticks.numer().expect("can not get numerator"),
+        *ticks.denom().expect("can not get denominator"),
+    );
+    num / denom
+}
+
+/// Cut TimeMap to the given measures.
+///
+/// # Returns
+/// TimeMap and its bounds in ticks.
+fn time_map_in_bars(
+    time_map: &TimeMap,
+    bars: RangeInclusive<u32>,
+    ppq: u64,
+) -> Result<(TimeMap, (u64, u64)), String> {
+    let measures = time_map
+        .get()
+        .iter()
+        .filter(|measure| bars.contains(&measure.index))
+        .cloned()
+        .collect::<Vec<_>>();
+    if measures.is_empty() {
+        return Err(format!(
+            "Bars {}-{} are out of file, which has {} bars",
+            bars.start(),
+            bars.end(),
+            time_map.end_measure()
+        ));
+    }
+    let start = time_map.timeline_position_of_measure(measures[0].index);
+    let mut end = start.clone();
+    for measure in measures.iter() {
+        end += measure.timeline_length();
+    }
+    let bounds =
+        (position_to_ticks(&start, ppq), position_to_ticks(&end, ppq));
+    Ok((TimeMap::new(measures, start), bounds))
+}
+
+/// Build TimeMap, starting from the file start, and covering every
+/// note of every track.
+///
+/// Err, if a measure is shorter than a tick.
+fn time_map_from_tracks(
+    tracks: &[SmfTrack],
+    ppq: u64,
+) -> Result<TimeMap, String> {
+    let mut time_signatures = tracks
+        .iter()
+        .flat_map(|track| track.time_signatures.iter().cloned())
+        .collect::<Vec<_>>();
+    time_signatures.sort_by_key(|(tick, _, _)| *tick);
+    let end = tracks
+        .iter()
+        .flat_map(|track| track.notes.iter().map(|note| note.end))
+        .max()
+        .unwrap_or(0);
+
+    let mut measures = Vec::new();
+    let mut time_signature = TimeSignature::new(4, 4);
+    let mut tick = 0_u64;
+    let mut index = 1_u32;
+    loop {
+        for (ts_tick, num, denom) in time_signatures.iter() {
+            if *ts_tick <= tick {
+                time_signature = TimeSignature::new(*num, *denom);
+            }
+        }
+        measures.push(MeasureInfo::new(index, time_signature.clone()));
+        let length = ppq * 4 * time_signature.numerator as u64
+            / time_signature.denominator as u64;
+        if length == 0 {
+            return Err(format!(
+                "Measure {index} of {}/{} is shorter than a tick",
+                time_signature.numerator, time_signature.denominator
+            ));
+        }
+        tick += length;
+        index += 1;
+        if tick >= end {
+            break;
+        }
+    }
+    let mut time_map = TimeMap::new(measures, AbsolutePosition::from(0.0));
+    let keys = tracks
+        .iter()
+        .flat_map(|track| track.key_signatures.iter())
+        .filter_map(|(tick, key)| {
+            let position = ticks_to_position(*tick, ppq);
+            let index = time_map
+                .pos_relative_from_absolute(&position)?
+                .get_measure_index();
+            Some((index, MeasureNotations::Key(*key)))
+        })
+        .collect::<Vec<_>>();
+    let markers = tracks
+        .iter()
+        .flat_map(|track| track.markers.iter())
+        .filter_map(|(tick, name, is_region)| {
+            let position = ticks_to_position(*tick, ppq);
+            let index = time_map
+                .pos_relative_from_absolute(&position)?
+                .get_measure_index();
+            Some(
+                marker_notations_from_string(name, *is_region)
+                    .into_iter()
+                    .map(move |notation| (index, notation)),
+            )
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    time_map.apply_measure_notations(keys.into_iter().chain(markers));
+    Ok(time_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::{
+        Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent,
+        TrackEventKind,
+    };
+    use rea_rs::TimeSignature;
+
+    use crate::lilypond_render::RendersToLilypond;
+
+    use super::from_smf;
+
+    fn note(delta: u32, key: u8, on: bool) -> TrackEvent<'static> {
+        let message = match on {
+            true => MidiMessage::NoteOn {
+                key: key.into(),
+                vel: 100.into(),
+            },
+            false => MidiMessage::NoteOff {
+                key: key.into(),
+                vel: 0.into(),
+            },
+        };
+        TrackEvent {
+            delta: delta.into(),
+            kind: TrackEventKind::Midi {
+                channel: 0.into(),
+                message,
+            },
+        }
+    }
+
+    #[test]
+    fn test_from_smf() {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(960.into()),
+        ));
+        smf.tracks.push(vec![
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TrackName(b"Flute")),
+            },
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::TimeSignature(
+                    3, 2, 24, 8,
+                )),
+            },
+            note(0, 60, true),
+            note(960, 60, false),
+            note(960, 62, true),
+            note(960 * 2, 62, false),
+            TrackEvent {
+                delta: 0.into(),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ]);
+        let dir = tempfile::tempdir().expect("can not create temp dir");
+        let path = dir.path().join("from_smf.mid");
+        smf.save(&path).expect("can not save midi file");
+
+        let parts = from_smf(&path).expect("can not parse midi file");
+        assert_eq!(parts.len(), 1);
+        let part = &parts[0];
+        assert_eq!(part.name, Some("Flute".to_string()));
+        assert_eq!(part.time_map.begin_measure(), 1);
+        assert_eq!(part.time_map.end_measure(), 2);
+        assert_eq!(
+            part.time_map.get_measure_info(2).time_signature,
+            TimeSignature::new(3, 4)
+        );
+        let rendered = part.render_lilypond();
+        assert!(rendered.starts_with("% bar1\n\\time 3/4 c'4 r4"));
+        assert!(rendered.contains("% bar2\n"));
+    }
+
+    #[test]
+    fn test_invalid_time_signature() {
+        let dir = tempfile::tempdir().expect("can not create temp dir");
+        // numerator, power of denominator, ppq
+        let cases = [
+            (3_u8, 40_u8, 960_u16),
+            (3, 20, 960),
+            (0, 2, 960),
+            (4, 2, 0),
+            // 1/64 measure is shorter than a tick
+            (1, 6, 1),
+        ];
+        for (idx, (num, denom_pow, ppq)) in cases.into_iter().enumerate() {
+            let mut smf = Smf::new(Header::new(
+                Format::SingleTrack,
+                Timing::Metrical(ppq.into()),
+            ));
+            smf.tracks.push(vec![
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(MetaMessage::TimeSignature(
+                        num, denom_pow, 24, 8,
+                    )),
+                },
+                note(0, 60, true),
+                note(960, 60, false),
+                TrackEvent {
+                    delta: 0.into(),
+                    kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                },
+            ]);
+            let path = dir.path().join(format!("invalid_{idx}.mid"));
+            smf.save(&path).expect("can not save midi file");
+
+            assert!(from_smf(&path).is_err());
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Spacer voices, that hold marks at exact positions.
+//!
+//! Marks, that do not need an event attack (e.g. tempo),
+//! are attached to skips of a voice, parallel to the music. Measures
+//! of TimeMap are split into skips only at positions of marks.
+use fraction::Fraction;
+use itertools::Itertools;
+
+use crate::primitives::{RelativePosition, TimeMap};
+
+/// Commands, attached to the skip at the position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpacerMark {
+    pub position: RelativePosition,
+    /// Written before the skip, e.g. `\tempo`.
+    pub before: String,
+    /// Post-event of the skip, e.g. `\sustainOn`.
+    pub after: String,
+}
+impl SpacerMark {
+    pub fn before(position: RelativePosition, before: String) -> Self {
+        Self {
+            position,
+            before,
+            after: String::new(),
+        }
+    }
+    pub fn after(position: RelativePosition, after: String) -> Self {
+        Self {
+            position,
+            before: String::new(),
+            after,
+        }
+    }
+}
+
+/// Skip of the given length in whole notes.
+pub fn skip(length: Fraction) -> String {
+    match length == Fraction::from(1) {
+        true => "s1".to_string(),
+        false => format!(
+            "s1*{}/{}",
+            length.numer().expect("bad fraction"),
+            length.denom().expect("bad fraction")
+        ),
+    }
+}
+
+/// Render every measure of TimeMap as skips, split at the marks.
+///
+/// Marks of the same position are written in the given order. Mark
+/// at the measure end or later is joined to the last mark of the
+/// measure.
+pub fn render_spacer(time_map: &TimeMap, marks: &[SpacerMark]) -> String {
+    time_map
+        .get()
+        .iter()
+        .map(|info| {
+            let length = info.length.get_quantized();
+            let zero = Fraction::from(0);
+            let mut groups: Vec<(Fraction, Vec<&str>, String)> = Vec::new();
+            for mark in marks
+                .iter()
+                .filter(|mark| mark.position.get_measure_index() == info.index)
+                .sorted_by_key(|mark| mark.position.position_quantized())
+            {
+                let mut position = mark.position.position_quantized();
+                if position >= length {
+                    position = groups.last().map_or(zero, |g| g.0);
+                }
+                if groups.last().map(|g| g.0) != Some(position) {
+                    groups.push((position, Vec::new(), String::new()));
+                }
+                let group = groups.last_mut().expect("group is pushed");
+                if !mark.before.is_empty() {
+                    group.1.push(&mark.before);
+                }
+                group.2.push_str(&mark.after);
+            }
+            if groups.first().map(|g| g.0) != Some(zero) {
+                groups.insert(0, (zero, Vec::new(), String::new()));
+            }
+            let ends = groups
+                .iter()
+                .skip(1)
+                .map(|g| g.0)
+                .chain([length])
+                .collect::<Vec<_>>();
+            let skips = groups
+                .iter()
+                .zip(ends)
+                .map(|((start, before, after), end)| {
+                    let skip = format!("{}{after}", skip(end - start));
+                    before.iter().chain([&skip.as_str()]).join(" ")
+                })
+                .join(" ");
+            format!("% bar{}\n{skips} |", info.index)
+        })
+        .join("\n")
+}
+
+/// Spacer voice with tempo marks and rit./accel. spanners of TimeMap,
+/// if it has any.
+///
+/// Tempo is the same for the whole score, so it is rendered only once,
+/// on the top staff.
+pub fn render_tempo(time_map: &TimeMap) -> Option<String> {
+    let marks = time_map
+        .get()
+        .iter()
+        .flat_map(|info| {
+            info.tempo.iter().map(|mark| SpacerMark {
+                position: RelativePosition::new(info.index, mark.position),
+                before: mark.lilypond_prefix(&info.time_signature),
+                after: mark.lilypond_suffix(),
+            })
+        })
+        .collect::<Vec<_>>();
+    match marks.is_empty() {
+        true => None,
+        false => Some(render_spacer(time_map, &marks)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use crate::primitives::{
+        AbsolutePosition, MeasureInfo, RelativePosition, TimeMap,
+    };
+
+    use super::{render_spacer, SpacerMark};
+
+    #[test]
+    fn test_render_spacer() {
+        let time_map = TimeMap::new(
+            vec![
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(3, 4)),
+            ],
+            AbsolutePosition::from(0.0),
+        );
+        let pos = |index: u32, position: u64| {
+            RelativePosition::new(index, Fraction::new(position, 8_u64))
+        };
+        let marks = vec![
+            SpacerMark::after(pos(1, 3), r"\sustainOff".to_string()),
+            SpacerMark::after(pos(1, 0), r"\sustainOn".to_string()),
+            SpacerMark::after(pos(1, 3), r"\sustainOn".to_string()),
+            SpacerMark::before(pos(2, 2), r"\tempo 4 = 90".to_string()),
+        ];
+        assert_eq!(
+            render_spacer(&time_map, &marks),
+            [
+                "% bar1\ns1*3/8\\sustainOn s1*5/8\\sustainOff\\sustainOn |",
+                "% bar2\ns1*1/4 \\tempo 4 = 90 s1*1/2 |",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,622 @@
+//! Detection of tuplets in unquantized MIDI.
+//!
+//! Onsets of every beat are fitted against the straight grid and
+//! against grids of tuplets: triplets, quintuplets and sextuplets in
+//! simple beats, duplets and quadruplets in compound ones (e.g. dotted
+//! quarter of 6/8). If tuplet grid fits better, notes of the beat are
+//! snapped to it and wrapped into [EventType::Tuplet]. Quarter-note
+//! triplets are looked for in spans of two simple beats.
+//!
+//! Tuplets of parsed MIDI are found before quantization, which would
+//! move onsets to the straight grid.
+use std::collections::{HashMap, VecDeque};
+
+use fraction::Fraction;
+use itertools::Itertools;
+
+use crate::primitives::{
+    event::{EventTupletType, Tuplet},
+    quantization::snap,
+    EventInfo, EventType, Length, Measure, RelativePosition, TimeMap,
+};
+
+use super::{midi_parse::ParsedEvent, Part, Voice};
+
+/// Amounts of tuplet notes per simple beat, that are tried.
+pub const BEAT_DIVISIONS: [u64; 3] = [3, 5, 6];
+/// Amounts of tuplet notes per compound beat, that are tried.
+pub const COMPOUND_BEAT_DIVISIONS: [u64; 2] = [2, 4];
+/// Straight grid, tuplet grids compete with: 1/32.
+pub const STRAIGHT_GRID: u64 = 32;
+
+/// How well onsets fit tuplet grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TupletFit {
+    /// Amount of tuplet grid steps in the span.
+    pub division: u64,
+    /// Amount of straight notes, tuplet notes replace.
+    pub normal: u64,
+    /// From 0 to 1: how much closer onsets are to the tuplet grid,
+    /// than to the straight one.
+    pub confidence: f64,
+}
+impl TupletFit {
+    /// Rate of [Tuplet]: e.g. 3/2 for triplet, 2/3 for duplet.
+    pub fn rate(&self) -> Fraction {
+        Fraction::new(self.division, self.normal)
+    }
+}
+
+/// Span of measure, that fits tuplet grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TupletSuggestion {
+    pub position: RelativePosition,
+    pub length: Length,
+    pub fit: TupletFit,
+}
+
+/// Amount of time signature denominators in length.
+pub fn units(length: Fraction, denominator: u64) -> u64 {
+    (length * Fraction::from(denominator))
+        .floor()
+        .numer()
+        .copied()
+        .unwrap_or(1)
+}
+
+/// Tuplet divisions, tried for the beat of `units` time signature
+/// denominators: beat of three units is compound.
+pub fn beat_divisions(units: u64) -> &'static [u64] {
+    match units % 3 == 0 {
+        true => &COMPOUND_BEAT_DIVISIONS,
+        false => &BEAT_DIVISIONS,
+    }
+}
+
+/// Amount of straight notes, `division` tuplet notes replace in the
+/// span of `units` time signature denominators.
+///
+/// It is `units`, doubled while it stays less than half of the
+/// division: triplet replaces 2 notes, quintuplet replaces 4,
+/// quadruplet in 6/8 beat replaces 3.
+fn normal(division: u64, units: u64) -> u64 {
+    let mut normal = units.max(1);
+    while normal * 2 < division {
+        normal *= 2;
+    }
+    normal
+}
+
+/// Find the tuplet grid, that fits onsets better, than the straight
+/// one.
+///
+/// Onsets are measured from the span start, span is of `units` time
+/// signature denominators. None, if there are less than two onsets,
+/// or straight grid fits not worse, or several onsets fall on the
+/// same point of tuplet grid.
+pub fn fit_tuplet(
+    onsets: &[Fraction],
+    span: Fraction,
+    units: u64,
+    divisions: &[u64],
+) -> Option<TupletFit> {
+    if onsets.len() < 2 {
+        return None;
+    }
+    let straight = grid_error(onsets, Fraction::new(1_u64, STRAIGHT_GRID));
+    if straight == 0.0 {
+        return None;
+    }
+    divisions
+        .iter()
+        .filter_map(|division| {
+            let step = span / Fraction::from(*division);
+            let mut points = onsets
+                .iter()
+                .map(|onset| snap(*onset, step))
+                .collect::<Vec<_>>();
+            points.dedup();
+            match points.len() == onsets.len() {
+                true => Some((*division, grid_error(onsets, step))),
+                false => None,
+            }
+        })
+        // the coarser grid wins on equal error
+        .fold(
+            None,
+            |best: Option<(u64, f64)>, (division, error)| match best {
+                Some((_, best_error)) if best_error <= error => best,
+                _ => Some((division, error)),
+            },
+        )
+        .filter(|(_, error)| *error < straight)
+        .map(|(division, error)| TupletFit {
+            division,
+            normal: normal(division, units),
+            confidence: 1.0 - error / straight,
+        })
+}
+
+fn to_f64(value: Fraction) -> f64 {
+    match (value.numer(), value.denom()) {
+        (Some(numer), Some(denom)) => *numer as f64 / *denom as f64,
+        _ => 0.0,
+    }
+}
+
+/// Mean distance of onsets to the grid, in halves of the grid step.
+fn grid_error(onsets: &[Fraction], step: Fraction) -> f64 {
+    let half = step / Fraction::from(2);
+    onsets
+        .iter()
+        .map(|onset| {
+            let nearest = snap(*onset, step);
+            let distance = match nearest > *onset {
+                true => nearest - *onset,
+                false => *onset - nearest,
+            };
+            to_f64(distance / half)
+        })
+        .sum::<f64>()
+        / onsets.len() as f64
+}
+
+fn end(event: &EventInfo) -> Fraction {
+    event.position.position() + event.length.get()
+}
+
+/// Find spans of measure, which onsets fit tuplet grids.
+///
+/// `events` are events of measure `index`, sorted by position,
+/// `beats` are as [Measure::beats] returns. Spans do not overlap.
+fn suggest_tuplets(
+    index: u32,
+    denominator: u64,
+    beats: &[(Fraction, Fraction)],
+    events: &[&EventInfo],
+) -> Vec<TupletSuggestion> {
+    let mut suggestions = Vec::new();
+    let mut idx = 0;
+    while idx < beats.len() {
+        let (start, length) = beats[idx];
+        let beat_units = units(length, denominator);
+        let pair = match beats.get(idx + 1) {
+            Some((_, next)) if *next == length && beat_units % 3 != 0 => {
+                suggest_in_span(
+                    index,
+                    start,
+                    length * Fraction::from(2),
+                    beat_units * 2,
+                    &[3],
+                    events,
+                )
+            }
+            _ => None,
+        };
+        if let Some(suggestion) = pair {
+            suggestions.push(suggestion);
+            idx += 2;
+            continue;
+        }
+        if let Some(suggestion) = suggest_in_span(
+            index,
+            start,
+            length,
+            beat_units,
+            beat_divisions(beat_units),
+            events,
+        ) {
+            suggestions.push(suggestion);
+        }
+        idx += 1;
+    }
+    suggestions
+}
+
+fn suggest_in_span(
+    index: u32,
+    start: Fraction,
+    span: Fraction,
+    units: u64,
+    divisions: &[u64],
+    events: &[&EventInfo],
+) -> Option<TupletSuggestion> {
+    let span_end = start + span;
+    let mut onsets = Vec::new();
+    for event in events {
+        let position = event.position.position();
+        if position < start || position >= span_end {
+            continue;
+        }
+        match event.event {
+            EventType::Rest => (),
+            EventType::Tuplet(_) => return None,
+            _ => onsets.push(position - start),
+        }
+    }
+    onsets.dedup();
+    let fit = fit_tuplet(&onsets, span, units, divisions)?;
+    let suggestion = TupletSuggestion {
+        position: RelativePosition::new(index, start),
+        length: Length::from(span),
+        fit,
+    };
+    tuplet_events(&suggestion, events).map(|_| suggestion)
+}
+
+/// Events of the span, snapped to the tuplet grid.
+///
+/// None, if notes are held over the span bounds longer, than
+/// half of the grid step.
+fn tuplet_events(
+    suggestion: &TupletSuggestion,
+    events: &[&EventInfo],
+) -> Option<Vec<EventInfo>> {
+    let start = suggestion.position.position();
+    let span_end = start + suggestion.length.get();
+    let step =
+        suggestion.length.get() / Fraction::from(suggestion.fit.division);
+    let tolerance = step / Fraction::from(2);
+    let mut notes: Vec<EventInfo> = Vec::new();
+    let mut ends = Vec::new();
+    for event in events {
+        let (position, event_end) = (event.position.position(), end(event));
+        if event_end <= start || position >= span_end {
+            continue;
+        }
+        match event.event {
+            EventType::Rest => continue,
+            EventType::Tuplet(_) => return None,
+            _ => (),
+        }
+        if (position < start && event_end - start >= tolerance)
+            || event_end - span_end >= tolerance
+        {
+            return None;
+        }
+        // tail of the previous note, it will be trimmed.
+        if position < start {
+            continue;
+        }
+        let position = start + snap(position - start, step);
+        if position >= span_end {
+            return None;
+        }
+        let mut note = (*event).clone();
+        note.position.set_position(position);
+        notes.push(note);
+        ends.push(start + snap(event_end - start, step));
+    }
+    for idx in 0..notes.len() {
+        let next = match notes.get(idx + 1) {
+            Some(next) => next.position.position(),
+            None => span_end,
+        };
+        let position = notes[idx].position.position();
+        let note_end = match ends[idx] {
+            x if x > next => next,
+            x if x < position + step => position + step,
+            x => x,
+        };
+        notes[idx].length = Length::from(note_end - position);
+    }
+    Some(notes)
+}
+
+/// Tuplets of unquantized events of every channel.
+///
+/// Events of tagged tuplets are not fitted: spans with them are
+/// skipped.
+pub(crate) fn suggest_event_tuplets(
+    events: &[ParsedEvent],
+    time_map: &TimeMap,
+    min_confidence: f64,
+) -> Vec<(u8, TupletSuggestion)> {
+    let mut depth: HashMap<u8, usize> = HashMap::new();
+    // the outer tagged tuplet, open in channel: (measure, index)
+    let mut tagged: HashMap<u8, (u32, usize)> = HashMap::new();
+    let mut by_measure: HashMap<(u8, u32), Vec<EventInfo>> = HashMap::new();
+    for ev in events {
+        let open = depth.entry(ev.channel).or_default();
+        let index = ev.event.position.get_measure_index();
+        match &ev.event.tuplet_type {
+            EventTupletType::TupletStart(rates) => {
+                if let (0, Some(rate)) = (*open, rates.first()) {
+                    // stands for the whole tagged tuplet
+                    let measure =
+                        by_measure.entry((ev.channel, index)).or_default();
+                    tagged.insert(ev.channel, (index, measure.len()));
+                    measure.push(ev.event.clone().convert_to_tuplet(*rate));
+                }
+                *open += rates.len();
+            }
+            EventTupletType::TupletEnd(levels) => {
+                *open = open.saturating_sub(*levels as usize);
+                if *open > 0 {
+                    continue;
+                }
+                let (start_index, idx) = match tagged.remove(&ev.channel) {
+                    Some(tagged) => tagged,
+                    None => continue,
+                };
+                let tuplet = &mut by_measure
+                    .get_mut(&(ev.channel, start_index))
+                    .expect("tagged tuplet is pushed")[idx];
+                let tuplet_end = match start_index == index {
+                    true => end(&ev.event),
+                    false => {
+                        time_map.get_measure_info(start_index).length.get()
+                    }
+                };
+                tuplet.length =
+                    Length::from(tuplet_end - tuplet.position.position());
+            }
+            EventTupletType::NonTuplet if *open > 0 => (),
+            EventTupletType::NonTuplet => by_measure
+                .entry((ev.channel, index))
+                .or_default()
+                .push(ev.event.clone()),
+        }
+    }
+    by_measure
+        .into_iter()
+        .sorted_by_key(|(key, _)| *key)
+        .flat_map(|((channel, index), events)| {
+            let measure = Measure::from(&time_map.get_measure_info(index));
+            let events = events
+                .iter()
+                .sorted_by_key(|ev| ev.position.position())
+                .collect::<Vec<_>>();
+            suggest_tuplets(
+                index,
+                measure.time_signature().denominator as u64,
+                &measure.beats(),
+                &events,
+            )
+            .into_iter()
+            .filter(|suggestion| suggestion.fit.confidence >= min_confidence)
+            .map(move |suggestion| (channel, suggestion))
+            .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+impl Measure {
+    /// Find spans of the measure, which onsets fit tuplet grids.
+    ///
+    /// Spans do not overlap and measure is not changed.
+    pub fn suggest_tuplets(&self) -> Vec<TupletSuggestion> {
+        suggest_tuplets(
+            self.index(),
+            self.time_signature().denominator as u64,
+            &self.beats(),
+            &self.events().iter().collect::<Vec<_>>(),
+        )
+    }
+
+    /// Replace events of the span by the tuplet.
+    ///
+    /// Rests are cut by the span bounds, notes, slightly crossing
+    /// them, are trimmed.
+    pub fn apply_tuplet(
+        &mut self,
+        suggestion: &TupletSuggestion,
+    ) -> Result<(), String> {
+        let events = self.events().iter().collect::<Vec<_>>();
+        let mut notes = tuplet_events(suggestion, &events)
+            .ok_or(format!("Tuplet does not fit measure: {:?}", suggestion))?;
+        let start = suggestion.position.position();
+        let span_end = start + suggestion.length.get();
+        let first = match notes.first() {
+            Some(note) if note.position.position() == start => notes.remove(0),
+            _ => EventInfo::new(
+                suggestion.position.clone(),
+                Length::from(
+                    suggestion.length.get()
+                        / Fraction::from(suggestion.fit.division),
+                ),
+                EventType::Rest,
+            ),
+        };
+        let mut tuplet = Tuplet::new(suggestion.fit.rate(), vec![first]);
+        for note in notes {
+            tuplet.push(note)?;
+        }
+        if tuplet.end_position().position() < span_end {
+            let mut end_position = suggestion.position.clone();
+            end_position.set_position(span_end);
+            tuplet.set_end_position(end_position);
+        }
+
+        let index = self.index();
+        let mut events = Vec::new();
+        for mut event in self.events_mut().drain(..) {
+            let (position, event_end) =
+                (event.position.position(), end(&event));
+            if event_end <= start || position >= span_end {
+                events.push(event);
+                continue;
+            }
+            if position < start {
+                event.length = Length::from(start - position);
+                events.push(event);
+            }
+            // rest, or the trimmed part of note
+            if event_end > span_end {
+                events.push(EventInfo::new(
+                    RelativePosition::new(index, span_end),
+                    Length::from(event_end - span_end),
+                    EventType::Rest,
+                ));
+            }
+        }
+        events.push(EventInfo::new(
+            suggestion.position.clone(),
+            suggestion.length.clone(),
+            EventType::Tuplet(tuplet),
+        ));
+        events
+            .sort_by(|a, b| a.position.position().cmp(&b.position.position()));
+        *self.events_mut() = VecDeque::from(events);
+        Ok(())
+    }
+}
+
+impl Voice {
+    /// Wrap into tuplets spans, which fit tuplet grid with at least
+    /// the given confidence.
+    ///
+    /// Returns applied suggestions.
+    pub fn detect_tuplets(
+        &mut self,
+        min_confidence: f64,
+    ) -> Result<Vec<TupletSuggestion>, String> {
+        let mut applied = Vec::new();
+        for measure in self.measures.iter_mut() {
+            for suggestion in measure.suggest_tuplets() {
+                if suggestion.fit.confidence < min_confidence {
+                    continue;
+                }
+                measure.apply_tuplet(&suggestion)?;
+                applied.push(suggestion);
+            }
+        }
+        Ok(applied)
+    }
+}
+
+impl Part {
+    /// Apply tuplets, found by [suggest_event_tuplets], to voices of
+    /// their channels.
+    pub(crate) fn apply_tuplets(
+        &mut self,
+        suggestions: &[(u8, TupletSuggestion)],
+    ) -> Result<(), String> {
+        for (channel, suggestion) in suggestions {
+            let index = suggestion.position.get_measure_index();
+            let measure = self
+                .staves
+                .iter_mut()
+                .flat_map(|staff| staff.voices.iter_mut())
+                .filter(|voice| voice.index == *channel)
+                .flat_map(|voice| voice.measures.iter_mut())
+                .find(|measure| measure.index() == index);
+            if let Some(measure) = measure {
+                measure.apply_tuplet(suggestion)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Detect tuplets in every voice.
+    pub fn detect_tuplets(
+        &mut self,
+        min_confidence: f64,
+    ) -> Result<Vec<TupletSuggestion>, String> {
+        let mut applied = Vec::new();
+        for voice in self
+            .staves
+            .iter_mut()
+            .flat_map(|staff| staff.voices.iter_mut())
+        {
+            applied.extend(voice.detect_tuplets(min_confidence)?);
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use crate::{
+        dom::{Voice, VoiceRenderOptions},
+        primitives::{
+            AbsolutePosition, EventInfo, EventType, Length, MeasureInfo, Note,
+            Pitch, RelativePosition, TimeMap,
+        },
+    };
+
+    use super::fit_tuplet;
+
+    #[test]
+    fn test_fit_tuplet() {
+        let frac = |n: u64, d: u64| Fraction::new(n, d);
+        let quarter = frac(1, 4);
+        let triplet = [frac(0, 1), frac(11, 128), frac(21, 128)];
+        let fit = fit_tuplet(&triplet, quarter, 1, &[3, 5, 6]).unwrap();
+        assert_eq!(fit.division, 3);
+        assert_eq!(fit.rate(), frac(3, 2));
+        assert!(fit.confidence > 0.8);
+        let quintuplet = [
+            frac(0, 1),
+            frac(6, 128),
+            frac(13, 128),
+            frac(19, 128),
+            frac(26, 128),
+        ];
+        let fit = fit_tuplet(&quintuplet, quarter, 1, &[3, 5, 6]).unwrap();
+        assert_eq!(fit.rate(), frac(5, 4));
+        let straight = [frac(0, 1), frac(1, 16), frac(3, 16)];
+        assert_eq!(fit_tuplet(&straight, quarter, 1, &[3, 5, 6]), None);
+        let duplet = [frac(0, 1), frac(25, 128)];
+        let fit = fit_tuplet(&duplet, frac(3, 8), 3, &[2, 4]).unwrap();
+        assert_eq!(fit.rate(), frac(2, 3));
+    }
+
+    fn voice(
+        time_signature: TimeSignature,
+        notes: &[(u8, u64, u64)],
+    ) -> Voice {
+        let time_map = Arc::new(TimeMap::new(
+            vec![MeasureInfo::new(1, time_signature)],
+            AbsolutePosition::from(0.0),
+        ));
+        let mut voice = Voice::from(time_map);
+        for (midi, position, length) in notes {
+            voice
+                .insert_event(EventInfo::new(
+                    RelativePosition::new(
+                        1,
+                        Fraction::new(*position, 128_u64),
+                    ),
+                    Length::from(Fraction::new(*length, 128_u64)),
+                    EventType::Note(Note::new(Pitch::from_midi(
+                        *midi, None, None,
+                    ))),
+                ))
+                .unwrap();
+        }
+        voice
+    }
+
+    #[test]
+    fn test_detect_tuplets() {
+        let notes = [
+            (60, 0, 10),
+            (62, 11, 10),
+            (64, 21, 10),
+            (65, 32, 32),
+            (67, 64, 64),
+        ];
+        let mut triplets = voice(TimeSignature::new(4, 4), &notes);
+        let applied = triplets.detect_tuplets(0.5).unwrap();
+        assert_eq!(applied.len(), 1);
+        assert!(triplets
+            .render_measures(VoiceRenderOptions::default())
+            .contains(r"\tuplet 3/2 { c'8 d'8 e'8 } f'4 g'2 |"));
+
+        // duplet in the dotted quarter beat
+        let notes = [(60, 0, 23), (62, 25, 23), (64, 48, 48)];
+        let mut duplets = voice(TimeSignature::new(6, 8), &notes);
+        let applied = duplets.detect_tuplets(0.5).unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].fit.rate(), Fraction::new(2_u64, 3_u64));
+        assert!(duplets
+            .render_measures(VoiceRenderOptions::default())
+            .contains(r"\tuplet 2/3 { c'8 d'8 } e'4. |"));
+    }
+}
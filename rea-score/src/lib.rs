@@ -1,6 +1,7 @@
 pub mod dom;
 pub mod lilypond_render;
 pub mod midi;
+pub mod musicxml_render;
 pub mod notation;
 pub mod primitives;
 
@@ -1,33 +1,110 @@
+use crate::{
+    dom::dynamics::DynamicsInference,
+    notation::chord_notations::PedalStyle,
+    primitives::{Quantization, RehearsalMarks},
+};
 use musical_note::Key;
 use rea_rs::{ExtState, Reaper, TimeSignature};
 use serde::{Deserialize, Serialize};
 use std::{
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::Mutex,
 };
 
+/// Render settings, used when REAPER is not available.
+static OFFLINE_RENDER_SETTINGS: Mutex<Option<RenderSettings>> =
+    Mutex::new(None);
+
+/// Set render settings, used when REAPER is not available (e.g. by
+/// the command line). Otherwise, default settings are used.
+pub fn set_offline_render_settings(settings: RenderSettings) {
+    *OFFLINE_RENDER_SETTINGS
+        .lock()
+        .expect("Render Settings lock is poisoned") = Some(settings);
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RenderSettings {
     pub key: Key,
+    /// Render rests of secondary voices as spacers, if voice has
+    /// nothing for the whole measure.
+    #[serde(default = "default_true")]
+    pub hide_empty_voice_rests: bool,
+    /// Infer dynamics from velocities of notes, if set.
+    #[serde(default)]
+    pub infer_dynamics: Option<DynamicsInference>,
+    #[serde(default)]
+    pub pedal_style: PedalStyle,
+    /// Notes, shorter than 1/grace_threshold of whole note, which are
+    /// followed by another note on the beat, become acciaccaturas.
+    /// Off, if not set.
+    #[serde(default)]
+    pub grace_threshold: Option<u64>,
+    /// Grid, positions and lengths of notes are snapped to.
+    #[serde(default)]
+    pub quantization: Quantization,
+    /// Wrap notes into tuplets, if they fit tuplet grid with at
+    /// least this confidence (from 0 to 1).
+    #[serde(default)]
+    pub detect_tuplets: Option<f64>,
+    /// Write project markers as rehearsal marks and regions as
+    /// section labels, if set.
+    #[serde(default)]
+    pub rehearsal_marks: Option<RehearsalMarks>,
+    /// Render single part as extracted one, with empty measures
+    /// joined into multi-measure rests.
+    #[serde(default)]
+    pub extracted_part: bool,
 }
 impl RenderSettings {
     pub fn new(key: Key) -> Self {
-        Self { key }
+        Self {
+            key,
+            hide_empty_voice_rests: true,
+            infer_dynamics: None,
+            pedal_style: PedalStyle::default(),
+            grace_threshold: None,
+            quantization: Quantization::default(),
+            detect_tuplets: None,
+            rehearsal_marks: None,
+            extracted_part: false,
+        }
     }
+}
+impl Default for RenderSettings {
     fn default() -> Self {
-        Self {
-            key: Key::from_str("c", musical_note::Scale::Major)
+        Self::new(
+            Key::from_str("c", musical_note::Scale::Major)
                 .expect("Should be valid key"),
-        }
+        )
     }
 }
 
+fn default_true() -> bool {
+    true
+}
+
+/// Error of rendering DOM to LilyPond or MusicXML.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RenderError {
+    #[error("Pitch can not be written: {0}")]
+    Pitch(String),
+    #[error("Events can not be normalized: {0}")]
+    Events(String),
+    #[error("Length can not be written by a single duration: {0}")]
+    Length(String),
+}
+
 pub trait RendersToLilypond {
     fn render_lilypond(&self) -> String;
     fn global_render_settings() -> RenderSettings {
         if !Reaper::is_available() {
-            return RenderSettings::default();
+            return OFFLINE_RENDER_SETTINGS
+                .lock()
+                .expect("Render Settings lock is poisoned")
+                .unwrap_or_default();
         }
         let rpr = Reaper::get();
         let pr = rpr.current_project();
@@ -59,12 +136,49 @@ fn pixels_to_mm(size: (u32, u32), dpi: u32) -> (u32, u32) {
     (size.0 * 10 / dpm, size.1 * 10 / dpm)
 }
 
-pub fn preview_string(
+/// Files, lilypond source can be rendered to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// lilypond source itself, without rendering.
+    Ly,
+    Png,
+    Pdf,
+    Svg,
+}
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ly => "ly",
+            Self::Png => "png",
+            Self::Pdf => "pdf",
+            Self::Svg => "svg",
+        }
+    }
+    /// Guess format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "ly" => Some(Self::Ly),
+            "png" => Some(Self::Png),
+            "pdf" => Some(Self::Pdf),
+            "svg" => Some(Self::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// Wrap rendered music into the document with the default LilyPond
+/// paper, as it is printed.
+pub fn score_source(string: impl Into<String>) -> String {
+    format!("\\version \"2.24\"\n{{{}}}\n", string.into())
+}
+
+/// Wrap rendered music into the document with preview paper settings.
+pub fn preview_source(
     string: impl Into<String>,
-    path: impl Into<PathBuf>,
     size: impl Into<Option<(u32, u32)>>,
     dpi: impl Into<Option<u32>>,
-) -> Result<(), std::io::Error> {
+) -> String {
     // line-width=120\mm
     // page-breaking = #ly:one-line-breaking
     // \include "lilypond-book-preamble.ly"
@@ -72,7 +186,7 @@ pub fn preview_string(
     let size = size.into().unwrap_or((300, 100));
     let dpi = dpi.into().unwrap_or(101);
     let (w_mm, h_mm) = pixels_to_mm(size, dpi);
-    let string = format!(
+    format!(
         r###"\version "2.24"
         \paper{{
             indent=0\mm
@@ -84,37 +198,84 @@ pub fn preview_string(
         }}
         {{{string}}}
         "###
-    );
+    )
+}
+
+/// Render music to png on the preview paper.
+pub fn preview_string(
+    string: impl Into<String>,
+    path: impl Into<PathBuf>,
+    size: impl Into<Option<(u32, u32)>>,
+    dpi: impl Into<Option<u32>>,
+) -> Result<(), std::io::Error> {
+    let dpi = dpi.into();
+    let source = preview_source(string, size, dpi);
+    render_source(source, path, OutputFormat::Png, dpi)
+}
+
+/// Render music to the file of the given format on the default
+/// paper.
+///
+/// Extension of path is replaced by one of format.
+pub fn render_string(
+    string: impl Into<String>,
+    path: impl Into<PathBuf>,
+    format: OutputFormat,
+    dpi: impl Into<Option<u32>>,
+) -> Result<(), std::io::Error> {
+    render_source(score_source(string), path, format, dpi)
+}
+
+/// Render the whole LilyPond document (see [score_source] and
+/// [preview_source]) to the file of the given format.
+///
+/// Extension of path is replaced by one of format.
+pub fn render_source(
+    string: String,
+    path: impl Into<PathBuf>,
+    format: OutputFormat,
+    dpi: impl Into<Option<u32>>,
+) -> Result<(), std::io::Error> {
+    let dpi = dpi.into().unwrap_or(101);
     let path = path.into();
+    if format == OutputFormat::Ly {
+        return std::fs::write(path.with_extension("ly"), string);
+    }
     // let input_path = path.with_extension("ly");
     // let mut input_file = File::create(input_path.clone())?;
     // input_file.write_all(string.as_bytes())?;
     let output_path = path.with_extension("");
-    let mut lily = {
-        Command::new("lilypond")
-            .stdin(Stdio::piped())
-            .arg(format!(
-                "--output={}",
-                output_path
-                    .to_str()
-                    .expect("can not make output file path")
-            ))
-            // .arg("-dbackend=svg")
-            // .arg("-danti-alias-factor=8")
-            .arg("--png")
-            .arg("-dbackend=eps")
-            .arg("-dno-gs-load-fonts")
-            .arg("-dinclude-eps-fonts")
-            .arg("-ddelete-intermediate-files")
-            .arg(format!("-dresolution={dpi}"))
-            .arg("-dgui=#t")
-            // .arg("-dcrop=#t")
-            // .arg("-dpreview")
-            .arg("-s")
-            .arg("-")
-            // .arg(input_path.to_str().expect("can not get path"))
-            .spawn()?
-    };
+    let mut command = Command::new("lilypond");
+    command.stdin(Stdio::piped()).arg(format!(
+        "--output={}",
+        output_path.to_str().expect("can not make output file path")
+    ));
+    match format {
+        OutputFormat::Svg => {
+            command.arg("--svg");
+        }
+        OutputFormat::Pdf => {
+            command.arg("--pdf");
+        }
+        _ => {
+            command
+                // .arg("-danti-alias-factor=8")
+                .arg("--png")
+                .arg("-dbackend=eps")
+                .arg("-dno-gs-load-fonts")
+                .arg("-dinclude-eps-fonts")
+                .arg(format!("-dresolution={dpi}"));
+        }
+    }
+    let mut lily = command
+        .arg("-ddelete-intermediate-files")
+        .arg("-dgui=#t")
+        // .arg("-dcrop=#t")
+        // .arg("-dpreview")
+        .arg("-s")
+        .arg("-")
+        // .arg(input_path.to_str().expect("can not get path"))
+        .spawn()?;
     let mut stdin = lily.stdin.take().expect("Failed to open stdin");
     std::thread::spawn(move || {
         stdin
@@ -122,8 +283,7 @@ pub fn preview_string(
             .expect("Failed to write to stdin");
     });
 
-    let output =
-        lily.wait_with_output().expect("Failed to read stdout");
+    let output = lily.wait_with_output().expect("Failed to read stdout");
     let output = String::from_utf8_lossy(&output.stdout);
     if !output.is_empty() {
         eprint!("{output}");
@@ -132,6 +292,5 @@ pub fn preview_string(
 }
 
 pub fn preview_file(path: PathBuf) {
-    open::that(path.with_extension("png"))
-        .expect("Can not open path");
+    open::that(path.with_extension("png")).expect("Can not open path");
 }
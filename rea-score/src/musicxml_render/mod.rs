@@ -0,0 +1,369 @@
+//! MusicXML backend, rendering the same DOM, as `lilypond_render`.
+//!
+//! Unlike LilyPond, MusicXML is organized measure by measure, so
+//! `Part` iterates over measures, and `Staff` with `Voice` render
+//! only their slice of the measure, which is currently written
+//! (see [MusicXmlContext::measure]).
+use std::collections::HashMap;
+
+use fraction::Fraction;
+use rea_rs::TimeSignature;
+
+use crate::{
+    lilypond_render::{RenderError, RenderSettings},
+    primitives::{GraceType, Length, Pitch, ResolvedPitch},
+};
+
+/// Divisions of quarter note, used for every duration in the
+/// document.
+///
+/// Divisible by 3, 5 and 7 to keep tuplets on the 1/128 grid
+/// integral.
+pub const DIVISIONS: u64 = 32 * 3 * 5 * 7;
+
+pub trait RendersToMusicXml {
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError>;
+}
+
+impl RendersToMusicXml for TimeSignature {
+    fn render_musicxml(
+        &self,
+        _ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let (num, denom) = (self.numerator, self.denominator);
+        Ok(format!(
+            "<time><beats>{num}</beats><beat-type>{denom}</beat-type></time>"
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TupletState {
+    /// product of rates of all tuplets, event is nested in
+    pub rate: Fraction,
+    /// numbers of tuplet brackets, event opens
+    pub start: Vec<u8>,
+    /// numbers of tuplet brackets, event closes
+    pub stop: Vec<u8>,
+    /// nesting level of the innermost tuplet, starting from 1
+    pub number: u8,
+}
+
+/// State, shared between elements, while document is rendered.
+#[derive(Debug, Clone)]
+pub struct MusicXmlContext {
+    pub settings: RenderSettings,
+    /// Measure, which is currently rendered by Staff and Voice.
+    pub measure: u32,
+    /// 1-based staff number inside part.
+    pub staff: u8,
+    /// 1-based voice number inside part.
+    pub voice: u8,
+    /// Length of event, which is currently rendered.
+    pub length: Length,
+    /// Set while events inside tuplet are rendered.
+    pub tuplet: Option<TupletState>,
+    /// Set by Chord for every note, except the first.
+    pub chord_member: bool,
+    /// Set while grace notes of the event are rendered.
+    pub grace: Option<GraceType>,
+    /// Duration in divisions, written by the current voice.
+    pub written: u64,
+    /// pitches, tied from the previous event, by voice.
+    ties: HashMap<u8, Vec<u8>>,
+    /// pitches, tied to the next event of the current voice.
+    next_ties: Vec<u8>,
+}
+impl MusicXmlContext {
+    pub fn new(settings: RenderSettings) -> Self {
+        Self {
+            settings,
+            measure: 0,
+            staff: 1,
+            voice: 1,
+            length: Length::from(0.0),
+            tuplet: None,
+            chord_member: false,
+            grace: None,
+            written: 0,
+            ties: HashMap::new(),
+            next_ties: Vec::new(),
+        }
+    }
+
+    /// Duration of length in divisions, taking current tuplet into
+    /// account.
+    pub fn duration(&self, length: &Length) -> u64 {
+        let mut frac = length.get_quantized()
+            * Fraction::new(4 * DIVISIONS, 1_u64);
+        if let Some(tuplet) = &self.tuplet {
+            frac = frac / tuplet.rate;
+        }
+        let (num, denom) = (
+            *frac.numer().expect("can not get numerator"),
+            *frac.denom().expect("can not get denominator"),
+        );
+        (num + denom / 2) / denom
+    }
+
+    /// True if pitch is tied from the previous event of the voice.
+    pub fn tied_from_previous(&self, midi: u8) -> bool {
+        match self.ties.get(&self.voice) {
+            None => false,
+            Some(pitches) => pitches.contains(&midi),
+        }
+    }
+
+    /// Remember pitch, tied to the next event of the voice.
+    pub fn tie_to_next(&mut self, midi: u8) {
+        self.next_ties.push(midi);
+    }
+
+    /// Should be called after every rendered event (not container).
+    pub fn finish_event(&mut self) {
+        let next = std::mem::take(&mut self.next_ties);
+        self.ties.insert(self.voice, next);
+        self.written += self.duration(&self.length);
+    }
+
+    /// `<time-modification>` of current tuplet, if any.
+    pub fn time_modification(&self) -> String {
+        match &self.tuplet {
+            None => String::new(),
+            Some(tuplet) => format!(
+                "<time-modification><actual-notes>{}</actual-notes>\
+                <normal-notes>{}</normal-notes></time-modification>",
+                tuplet.rate.numer().expect("Can not get rate numerator"),
+                tuplet.rate.denom().expect("Can not get rate denominator"),
+            ),
+        }
+    }
+
+    /// `<tuplet>` notations of current event, if any.
+    pub fn tuplet_notations(&self) -> Vec<String> {
+        let mut notations = Vec::new();
+        if let Some(tuplet) = &self.tuplet {
+            notations.extend(tuplet.start.iter().map(|number| {
+                format!(
+                    r#"<tuplet type="start" number="{number}" bracket="yes"/>"#
+                )
+            }));
+            notations.extend(tuplet.stop.iter().map(|number| {
+                format!(r#"<tuplet type="stop" number="{number}"/>"#)
+            }));
+        }
+        notations
+    }
+}
+
+/// Render items (voices or staves) of the same measure one after
+/// another, returning cursor by `<backup>` between them.
+///
+/// Cursor is returned only by the duration, the previous item has
+/// written, and items, which have written nothing, are skipped. After
+/// the call [MusicXmlContext::written] holds the duration of the last
+/// written item.
+pub fn join_with_backup<T, F>(
+    ctx: &mut MusicXmlContext,
+    items: impl IntoIterator<Item = T>,
+    mut render: F,
+) -> Result<String, RenderError>
+where
+    F: FnMut(&mut MusicXmlContext, T) -> Result<String, RenderError>,
+{
+    let mut rendered = Vec::new();
+    let mut written = 0;
+    for item in items {
+        ctx.written = 0;
+        let xml = render(ctx, item)?;
+        if xml.is_empty() {
+            continue;
+        }
+        if written > 0 {
+            rendered.push(format!(
+                "<backup><duration>{written}</duration></backup>"
+            ));
+        }
+        rendered.push(xml);
+        written = ctx.written;
+    }
+    ctx.written = written;
+    Ok(rendered.join("\n"))
+}
+
+/// Type and amount of dots of notated length.
+///
+/// None, if length can not be written by single note (see
+/// [Length::single_duration]).
+pub fn note_type(length: &Length) -> Option<(&'static str, usize)> {
+    let (base, dots) = length.single_duration()?;
+    let name = match (*base.numer()?, *base.denom()?) {
+        (4, 1) => "long",
+        (2, 1) => "breve",
+        (1, 1) => "whole",
+        (1, 2) => "half",
+        (1, 4) => "quarter",
+        (1, 8) => "eighth",
+        (1, 16) => "16th",
+        (1, 32) => "32nd",
+        (1, 64) => "64th",
+        (1, 128) => "128th",
+        (1, 256) => "256th",
+        _ => return None,
+    };
+    Some((name, dots))
+}
+
+/// `<type>` and `<dot>` elements of notated length.
+pub fn note_type_elements(length: &Length) -> String {
+    match note_type(length) {
+        None => String::new(),
+        Some((name, dots)) => {
+            format!("<type>{name}</type>{}", "<dot/>".repeat(dots))
+        }
+    }
+}
+
+/// Step, alter and octave of pitch, resolved in the given key.
+pub fn pitch_elements(
+    pitch: &Pitch,
+    settings: &RenderSettings,
+) -> Result<String, RenderError> {
+    let midi = pitch.midi() as i32;
+    let note = match pitch.resolve(&settings.key) {
+        ResolvedPitch::Note(note) => note,
+        ResolvedPitch::Name(_) => {
+            match Pitch::from_midi(pitch.midi(), None, None)
+                .resolve(&settings.key)
+            {
+                ResolvedPitch::Note(note) => note,
+                ResolvedPitch::Name(name) => {
+                    return Err(RenderError::Pitch(format!(
+                        "pitch without name resolved to name: {name}"
+                    )))
+                }
+            }
+        }
+    };
+    let step = note.note.to_string().to_uppercase();
+    let semitone = match step.as_str() {
+        "C" => 0,
+        "D" => 2,
+        "E" => 4,
+        "F" => 5,
+        "G" => 7,
+        "A" => 9,
+        "B" => 11,
+        x => {
+            return Err(RenderError::Pitch(format!(
+                "unexpected note name: {x}"
+            )))
+        }
+    };
+    let mut octave = note.octave.raw() as i32;
+    let mut alter = midi - (octave * 12 + semitone);
+    while alter > 6 {
+        alter -= 12;
+        octave += 1;
+    }
+    while alter < -6 {
+        alter += 12;
+        octave -= 1;
+    }
+    let alter = match alter {
+        0 => String::new(),
+        x => format!("<alter>{x}</alter>"),
+    };
+    Ok(format!(
+        "<pitch><step>{step}</step>{alter}<octave>{}</octave></pitch>",
+        octave - 1
+    ))
+}
+
+/// `<notations>` element, or empty string if nothing to notate.
+pub fn notations_element(notations: Vec<String>) -> String {
+    match notations.is_empty() {
+        true => String::new(),
+        false => format!("<notations>{}</notations>", notations.join("")),
+    }
+}
+
+pub fn escape(string: impl Into<String>) -> String {
+    string
+        .into()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Wrap rendered part into partwise MusicXML 4.0 document.
+pub fn partwise_document(
+    part_name: impl Into<String>,
+    measures: impl Into<String>,
+) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="4.0">
+<part-list>
+<score-part id="P1"><part-name>{}</part-name></score-part>
+</part-list>
+<part id="P1">
+{}
+</part>
+</score-partwise>
+"#,
+        escape(part_name),
+        measures.into()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+    use musical_note::Key;
+
+    use crate::{lilypond_render::RenderSettings, primitives::Length};
+
+    use super::{
+        join_with_backup, note_type, note_type_elements, MusicXmlContext,
+    };
+
+    #[test]
+    fn test_join_with_backup() {
+        let key = Key::from_str("c", musical_note::Scale::Major).unwrap();
+        let mut ctx = MusicXmlContext::new(RenderSettings::new(key));
+        let voices = [("", 0), ("<a/>", 10), ("", 0), ("<b/>", 5)];
+        let rendered = join_with_backup(&mut ctx, voices, |ctx, voice| {
+            ctx.written = voice.1;
+            Ok(voice.0.to_string())
+        });
+        // voices, which have written nothing, are not backed up
+        assert_eq!(
+            rendered,
+            Ok("<a/>\n<backup><duration>10</duration></backup>\n<b/>"
+                .to_string())
+        );
+        assert_eq!(ctx.written, 5);
+    }
+
+    #[test]
+    fn test_note_type() {
+        let length = |num: u64, denom: u64| {
+            Length::from(Fraction::new(num, denom))
+        };
+        assert_eq!(note_type(&length(3, 8)), Some(("quarter", 1)));
+        assert_eq!(note_type(&length(15, 16)), Some(("half", 3)));
+        assert_eq!(note_type(&length(7, 2)), Some(("breve", 2)));
+        assert_eq!(note_type(&length(6, 1)), Some(("long", 1)));
+        assert_eq!(note_type(&length(5, 8)), None);
+        assert_eq!(
+            note_type_elements(&length(15, 16)),
+            "<type>half</type><dot/><dot/><dot/>"
+        );
+    }
+}
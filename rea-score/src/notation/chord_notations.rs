@@ -1,6 +1,9 @@
 use std::{error::Error, str::FromStr};
 
 use fraction::Fraction;
+use serde::{Deserialize, Serialize};
+
+use crate::primitives::GraceType;
 
 use super::{
     get_token, reascore_tokens, NotationError, NotationRender,
@@ -10,8 +13,43 @@ use super::{
 #[derive(Debug, PartialEq, Clone)]
 pub enum ChordNotations {
     Dynamics(String),
-    TupletRate(Fraction),
-    TupletEnd,
+    /// Rates of tuplets, starting on the note, the outer first.
+    TupletRate(Vec<Fraction>),
+    /// Amount of the innermost tuplets, ending on the note.
+    TupletEnd(u8),
+    Articulation(Articulation),
+    SlurStart,
+    SlurEnd,
+    PhrasingSlurStart,
+    PhrasingSlurEnd,
+    Hairpin(Hairpin),
+    /// Explicit end of hairpin. Not needed, if hairpin ends on
+    /// the note with dynamics.
+    HairpinEnd,
+    PedalOn(Pedal),
+    PedalOff(Pedal),
+    /// Marks note as grace one. Such notes are lifted out of the
+    /// timeline and attached to the neighbour event.
+    Grace(GraceType),
+    /// Manual beam, e.g. over beat groups of measure.
+    BeamStart,
+    BeamEnd,
+}
+impl ChordNotations {
+    /// Element of MusicXML `<notations>`, if notation has one.
+    pub fn musicxml(&self) -> Option<String> {
+        let slur = |tp: &str, number: u8| {
+            format!(r#"<slur type="{tp}" number="{number}"/>"#)
+        };
+        match self {
+            Self::Articulation(art) => Some(art.musicxml()),
+            Self::SlurStart => Some(slur("start", 1)),
+            Self::SlurEnd => Some(slur("stop", 1)),
+            Self::PhrasingSlurStart => Some(slur("start", 2)),
+            Self::PhrasingSlurEnd => Some(slur("stop", 2)),
+            _ => None,
+        }
+    }
 }
 impl ToString for ChordNotations {
     fn to_string(&self) -> String {
@@ -19,14 +57,62 @@ impl ToString for ChordNotations {
             Self::Dynamics(idx) => {
                 format!("dyn{TOKENS_DELIMITER}{}", idx)
             }
-            Self::TupletRate(tpl) => {
+            Self::TupletRate(rates) => {
+                let rates: Vec<_> = rates
+                    .iter()
+                    .map(|rate| {
+                        format!(
+                            "{}/{}",
+                            rate.numer()
+                                .expect("can not get numerator"),
+                            rate.denom()
+                                .expect("can not get denominator"),
+                        )
+                    })
+                    .collect();
+                format!(
+                    "tuplet{TOKENS_DELIMITER}{}",
+                    rates.join(",")
+                )
+            }
+            Self::TupletEnd(1) => "tuplet_end".to_string(),
+            Self::TupletEnd(levels) => {
+                format!("tuplet_end{TOKENS_DELIMITER}{}", levels)
+            }
+            Self::Articulation(art) => {
+                format!("art{TOKENS_DELIMITER}{}", art.to_string())
+            }
+            Self::SlurStart => "slur".to_string(),
+            Self::SlurEnd => "slur_end".to_string(),
+            Self::PhrasingSlurStart => "phrasing_slur".to_string(),
+            Self::PhrasingSlurEnd => "phrasing_slur_end".to_string(),
+            Self::Hairpin(hairpin) => {
+                format!(
+                    "hairpin{TOKENS_DELIMITER}{}",
+                    hairpin.to_string()
+                )
+            }
+            Self::HairpinEnd => "hairpin_end".to_string(),
+            Self::PedalOn(pedal) => {
                 format!(
-                    "tuplet{TOKENS_DELIMITER}{}/{}",
-                    tpl.numer().expect("can not get numerator"),
-                    tpl.denom().expect("can not get denominator"),
+                    "pedal{TOKENS_DELIMITER}{}",
+                    pedal.to_string()
                 )
             }
-            Self::TupletEnd => "tuplet_end".to_string(),
+            Self::PedalOff(pedal) => {
+                format!(
+                    "pedal_off{TOKENS_DELIMITER}{}",
+                    pedal.to_string()
+                )
+            }
+            Self::Grace(grace_type) => {
+                format!(
+                    "grace{TOKENS_DELIMITER}{}",
+                    grace_type.to_string()
+                )
+            }
+            Self::BeamStart => "beam".to_string(),
+            Self::BeamEnd => "beam_end".to_string(),
         }
     }
 }
@@ -42,9 +128,45 @@ impl FromStr for ChordNotations {
             }
             "tuplet" => {
                 let expr = get_token(&tokens, 1)?;
-                Ok(Self::TupletRate(Fraction::from_str(expr)?))
+                let rates = expr
+                    .split(',')
+                    .map(|rate| Fraction::from_str(rate.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::TupletRate(rates))
+            }
+            "tuplet_end" => match tokens.get(1) {
+                None => Ok(Self::TupletEnd(1)),
+                Some(levels) => Ok(Self::TupletEnd(levels.parse()?)),
+            },
+            "art" => {
+                let art = get_token(&tokens, 1)?;
+                Ok(Self::Articulation(art.parse()?))
             }
-            "tuplet_end" => Ok(Self::TupletEnd),
+            "slur" => Ok(Self::SlurStart),
+            "slur_end" => Ok(Self::SlurEnd),
+            "phrasing_slur" => Ok(Self::PhrasingSlurStart),
+            "phrasing_slur_end" => Ok(Self::PhrasingSlurEnd),
+            "hairpin" => {
+                let hairpin = get_token(&tokens, 1)?;
+                Ok(Self::Hairpin(hairpin.parse()?))
+            }
+            "hairpin_end" => Ok(Self::HairpinEnd),
+            "pedal" => {
+                let pedal = get_token(&tokens, 1)?;
+                Ok(Self::PedalOn(pedal.parse()?))
+            }
+            "pedal_off" => {
+                let pedal = get_token(&tokens, 1)?;
+                Ok(Self::PedalOff(pedal.parse()?))
+            }
+            "grace" => match tokens.get(1) {
+                None => Ok(Self::Grace(GraceType::Grace)),
+                Some(grace_type) => {
+                    Ok(Self::Grace(grace_type.parse()?))
+                }
+            },
+            "beam" => Ok(Self::BeamStart),
+            "beam_end" => Ok(Self::BeamEnd),
             x => {
                 Err(NotationError::UnexpectedToken(x.to_string())
                     .into())
@@ -58,12 +180,47 @@ impl NotationRender for ChordNotations {
             Self::Dynamics(d) => {
                 format!("{}\\{}", pitch_string.into(), d)
             }
-            Self::TupletRate(_) => {
-                unimplemented!()
+            // tuplets are written by the events, that hold them.
+            Self::TupletRate(_) | Self::TupletEnd(_) => pitch_string.into(),
+            Self::Articulation(art) => {
+                format!("{}{}", pitch_string.into(), art.lilypond())
+            }
+            Self::SlurStart => format!("{}(", pitch_string.into()),
+            Self::SlurEnd => format!("{})", pitch_string.into()),
+            Self::PhrasingSlurStart => {
+                format!("{}\\(", pitch_string.into())
+            }
+            Self::PhrasingSlurEnd => {
+                format!("{}\\)", pitch_string.into())
+            }
+            Self::Hairpin(hairpin) => {
+                format!(
+                    "{}{}",
+                    pitch_string.into(),
+                    hairpin.lilypond()
+                )
             }
-            Self::TupletEnd => {
-                unimplemented!()
+            Self::HairpinEnd => {
+                format!("{}\\!", pitch_string.into())
             }
+            Self::PedalOn(pedal) => {
+                format!(
+                    "{}{}",
+                    pitch_string.into(),
+                    pedal.lilypond(true)
+                )
+            }
+            Self::PedalOff(pedal) => {
+                format!(
+                    "{}{}",
+                    pitch_string.into(),
+                    pedal.lilypond(false)
+                )
+            }
+            // rendered by the event, grace note is attached to.
+            Self::Grace(_) => pitch_string.into(),
+            Self::BeamStart => format!("{}[", pitch_string.into()),
+            Self::BeamEnd => format!("{}]", pitch_string.into()),
         }
     }
 }
@@ -72,7 +229,377 @@ impl NotationSplitPosition for ChordNotations {
         match self {
             Self::Dynamics(d) => d != "!",
             Self::TupletRate(_) => true,
-            Self::TupletEnd => false,
+            Self::TupletEnd(_) => false,
+            Self::Articulation(_) => true,
+            Self::SlurStart | Self::PhrasingSlurStart => true,
+            Self::SlurEnd | Self::PhrasingSlurEnd => false,
+            Self::Hairpin(_) => true,
+            Self::HairpinEnd => false,
+            // pedal marks are placed at attacks, both pressed and
+            // released.
+            Self::PedalOn(_) | Self::PedalOff(_) => true,
+            Self::Grace(_) => true,
+            Self::BeamStart => true,
+            Self::BeamEnd => false,
+        }
+    }
+}
+
+/// Notations, that should be rendered.
+///
+/// Explicit hairpin end is skipped, if the event has dynamics, which
+/// terminates hairpin by itself.
+pub fn rendered_chord_notations(
+    notations: &[ChordNotations],
+) -> impl Iterator<Item = &ChordNotations> {
+    let has_dynamics = notations.iter().any(|n| match n {
+        ChordNotations::Dynamics(d) => d != "!",
+        _ => false,
+    });
+    notations.iter().filter(move |n| {
+        !(has_dynamics && **n == ChordNotations::HairpinEnd)
+    })
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Hairpin {
+    Crescendo,
+    Decrescendo,
+}
+impl Hairpin {
+    pub fn lilypond(&self) -> &'static str {
+        match self {
+            Self::Crescendo => r"\<",
+            Self::Decrescendo => r"\>",
         }
     }
 }
+impl ToString for Hairpin {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Crescendo => "<",
+            Self::Decrescendo => ">",
+        }
+        .to_string()
+    }
+}
+impl FromStr for Hairpin {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "<" | "cresc" => Ok(Self::Crescendo),
+            ">" | "decresc" | "dim" => Ok(Self::Decrescendo),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+
+/// Piano pedal, driven by MIDI controller.
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy,
+)]
+pub enum Pedal {
+    /// CC64
+    Sustain,
+    /// CC66
+    Sostenuto,
+    /// CC67
+    UnaCorda,
+}
+impl Pedal {
+    pub fn from_controller(controller: u8) -> Option<Self> {
+        match controller {
+            64 => Some(Self::Sustain),
+            66 => Some(Self::Sostenuto),
+            67 => Some(Self::UnaCorda),
+            _ => None,
+        }
+    }
+    pub fn lilypond(&self, pressed: bool) -> &'static str {
+        match (self, pressed) {
+            (Self::Sustain, true) => r"\sustainOn",
+            (Self::Sustain, false) => r"\sustainOff",
+            (Self::Sostenuto, true) => r"\sostenutoOn",
+            (Self::Sostenuto, false) => r"\sostenutoOff",
+            (Self::UnaCorda, true) => r"\unaCorda",
+            (Self::UnaCorda, false) => r"\treCorde",
+        }
+    }
+}
+impl ToString for Pedal {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Sustain => "sustain",
+            Self::Sostenuto => "sostenuto",
+            Self::UnaCorda => "una_corda",
+        }
+        .to_string()
+    }
+}
+impl FromStr for Pedal {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "sustain" | "sus" => Ok(Self::Sustain),
+            "sostenuto" | "sos" => Ok(Self::Sostenuto),
+            "una_corda" | "unacorda" => Ok(Self::UnaCorda),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+
+/// How pedal marks are printed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(Serialize, Deserialize)]
+pub enum PedalStyle {
+    /// `Ped.` and `*`
+    #[default]
+    Text,
+    Bracket,
+    /// `Ped.` with bracket line.
+    Mixed,
+}
+impl PedalStyle {
+    /// Commands, setting the style of all pedals of the staff.
+    pub fn lilypond(&self) -> String {
+        let style = match self {
+            Self::Text => "text",
+            Self::Bracket => "bracket",
+            Self::Mixed => "mixed",
+        };
+        ["Sustain", "Sostenuto", "UnaCorda"]
+            .iter()
+            .map(|pedal| {
+                format!(r"\set Staff.pedal{pedal}Style = #'{style}")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Articulation, attached to the note or chord.
+///
+/// Can be parsed from its name or from LilyPond shorthand, e.g.
+/// `staccato` or `-.`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Articulation {
+    Staccato,
+    Staccatissimo,
+    Accent,
+    Tenuto,
+    Marcato,
+    Portato,
+    Fermata,
+    UpBow,
+    DownBow,
+    Stopped,
+    Open,
+}
+impl Articulation {
+    const ALL: [Self; 11] = [
+        Self::Staccato,
+        Self::Staccatissimo,
+        Self::Accent,
+        Self::Tenuto,
+        Self::Marcato,
+        Self::Portato,
+        Self::Fermata,
+        Self::UpBow,
+        Self::DownBow,
+        Self::Stopped,
+        Self::Open,
+    ];
+
+    pub fn lilypond(&self) -> &'static str {
+        match self {
+            Self::Staccato => "-.",
+            Self::Staccatissimo => "-!",
+            Self::Accent => "->",
+            Self::Tenuto => "--",
+            Self::Marcato => "-^",
+            Self::Portato => "-_",
+            Self::Fermata => r"\fermata",
+            Self::UpBow => r"\upbow",
+            Self::DownBow => r"\downbow",
+            Self::Stopped => "-+",
+            Self::Open => r"\open",
+        }
+    }
+
+    /// Element of MusicXML `<notations>`.
+    pub fn musicxml(&self) -> String {
+        let (group, element) = match self {
+            Self::Staccato => ("articulations", "staccato"),
+            Self::Staccatissimo => {
+                ("articulations", "staccatissimo")
+            }
+            Self::Accent => ("articulations", "accent"),
+            Self::Tenuto => ("articulations", "tenuto"),
+            Self::Marcato => ("articulations", "strong-accent"),
+            Self::Portato => ("articulations", "detached-legato"),
+            Self::Fermata => return "<fermata/>".to_string(),
+            Self::UpBow => ("technical", "up-bow"),
+            Self::DownBow => ("technical", "down-bow"),
+            Self::Stopped => ("technical", "stopped"),
+            Self::Open => ("technical", "open-string"),
+        };
+        format!("<{group}><{element}/></{group}>")
+    }
+}
+impl ToString for Articulation {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Staccato => "staccato",
+            Self::Staccatissimo => "staccatissimo",
+            Self::Accent => "accent",
+            Self::Tenuto => "tenuto",
+            Self::Marcato => "marcato",
+            Self::Portato => "portato",
+            Self::Fermata => "fermata",
+            Self::UpBow => "upbow",
+            Self::DownBow => "downbow",
+            Self::Stopped => "stopped",
+            Self::Open => "open",
+        }
+        .to_string()
+    }
+}
+impl FromStr for Articulation {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Self::ALL
+            .into_iter()
+            .find(|art| art.to_string() == s || art.lilypond() == s)
+            .ok_or(NotationError::UnexpectedToken(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+
+    use crate::notation::{NotationRender, NotationSplitPosition};
+
+    use super::{
+        rendered_chord_notations, Articulation, ChordNotations,
+        Hairpin, Pedal, PedalStyle,
+    };
+
+    #[test]
+    fn test_articulations() {
+        let staccato =
+            ChordNotations::Articulation(Articulation::Staccato);
+        assert_eq!(staccato.to_string(), "art:staccato");
+        assert_eq!(
+            "art:staccato".parse::<ChordNotations>().unwrap(),
+            staccato
+        );
+        assert_eq!(
+            "art:\\fermata".parse::<ChordNotations>().unwrap(),
+            ChordNotations::Articulation(Articulation::Fermata)
+        );
+        assert_eq!(
+            "->".parse::<Articulation>().unwrap(),
+            Articulation::Accent
+        );
+        assert!("art:legato".parse::<ChordNotations>().is_err());
+        assert_eq!(staccato.render("c'4"), "c'4-.");
+        assert!(staccato.is_head());
+        assert!(!staccato.is_tail());
+    }
+
+    #[test]
+    fn test_nested_tuplets() {
+        let rates = vec![
+            Fraction::new(5_u8, 4_u8),
+            Fraction::new(3_u8, 2_u8),
+        ];
+        let start = ChordNotations::TupletRate(rates);
+        assert_eq!(start.to_string(), "tuplet:5/4,3/2");
+        assert_eq!(
+            "tuplet:5/4,3/2".parse::<ChordNotations>().unwrap(),
+            start
+        );
+        assert_eq!(
+            "tuplet:3/2".parse::<ChordNotations>().unwrap(),
+            ChordNotations::TupletRate(vec![Fraction::new(
+                3_u8, 2_u8
+            )])
+        );
+        assert_eq!(
+            ChordNotations::TupletEnd(1).to_string(),
+            "tuplet_end"
+        );
+        assert_eq!(
+            "tuplet_end:2".parse::<ChordNotations>().unwrap(),
+            ChordNotations::TupletEnd(2)
+        );
+        assert!("tuplet_end:x".parse::<ChordNotations>().is_err());
+        assert_eq!(start.render("c'8"), "c'8");
+        assert_eq!(ChordNotations::TupletEnd(2).render("c'8"), "c'8");
+    }
+
+    #[test]
+    fn test_slurs() {
+        let start = ChordNotations::PhrasingSlurStart;
+        let end = ChordNotations::PhrasingSlurEnd;
+        assert_eq!(start.to_string(), "phrasing_slur");
+        assert_eq!(
+            "phrasing_slur_end".parse::<ChordNotations>().unwrap(),
+            end
+        );
+        assert_eq!(start.render("c'4"), r"c'4\(");
+        assert_eq!(ChordNotations::SlurEnd.render("c'4"), "c'4)");
+        assert!(start.is_head() && !start.is_tail());
+        assert!(!end.is_head() && end.is_tail());
+    }
+
+    #[test]
+    fn test_hairpins() {
+        let cresc = ChordNotations::Hairpin(Hairpin::Crescendo);
+        assert_eq!(cresc.to_string(), "hairpin:<");
+        assert_eq!(
+            "hairpin:dim".parse::<ChordNotations>().unwrap(),
+            ChordNotations::Hairpin(Hairpin::Decrescendo)
+        );
+        assert_eq!(cresc.render("c'4"), r"c'4\<");
+        assert_eq!(
+            ChordNotations::HairpinEnd.render("c'4"),
+            r"c'4\!"
+        );
+        assert!(cresc.is_head());
+        assert!(ChordNotations::HairpinEnd.is_tail());
+        let notations = vec![
+            ChordNotations::HairpinEnd,
+            ChordNotations::Dynamics("f".to_string()),
+        ];
+        assert_eq!(
+            rendered_chord_notations(&notations).collect::<Vec<_>>(),
+            vec![&ChordNotations::Dynamics("f".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_pedals() {
+        let on = ChordNotations::PedalOn(Pedal::UnaCorda);
+        assert_eq!(on.to_string(), "pedal:una_corda");
+        assert_eq!(
+            "pedal_off:sus".parse::<ChordNotations>().unwrap(),
+            ChordNotations::PedalOff(Pedal::Sustain)
+        );
+        assert_eq!(on.render("c'4"), r"c'4\unaCorda");
+        assert_eq!(
+            ChordNotations::PedalOff(Pedal::Sustain).render("c'4"),
+            r"c'4\sustainOff"
+        );
+        assert_eq!(
+            Pedal::from_controller(66),
+            Some(Pedal::Sostenuto)
+        );
+        assert_eq!(Pedal::from_controller(65), None);
+        assert!(PedalStyle::Bracket.lilypond().starts_with(
+            r"\set Staff.pedalSustainStyle = #'bracket"
+        ));
+    }
+}
@@ -0,0 +1,404 @@
+use std::{error::Error, str::FromStr};
+
+use fraction::Fraction;
+use itertools::Itertools;
+
+use crate::primitives::{Barline, Jump, KeySignature, MeterRules};
+
+use super::{
+    get_token, reascore_tokens, NotationError, NOTATION_DELIMITER, SECTION,
+    TOKENS_DELIMITER,
+};
+
+/// Notations, that are applied to the whole measure, rather than
+/// to the single event.
+///
+/// Can be found in track notation events, or in project markers.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MeasureNotations {
+    Key(KeySignature),
+    /// Grouping of beats, e.g. `2+2+3` for 7/8.
+    ///
+    /// Applied only to measures, which numerator is the sum of groups.
+    BeatGrouping(Vec<u8>),
+    /// Rules of splitting events by beats, e.g. `meter:beats`.
+    Meter(MeterRules),
+    /// Partial measure (e.g. pickup) of the given length in whole
+    /// notes, like `partial:1/4`.
+    ///
+    /// Only the end of the measure is written, as `\partial` does in
+    /// LilyPond. Applied only to the measure, notation is placed in.
+    Partial(Fraction),
+    /// Textual tempo indication, like `tempo:Allegro`, written
+    /// together with the metronome mark at the start of measure.
+    ///
+    /// Applied only to the measure, notation is placed in.
+    Tempo(String),
+    /// Rehearsal mark, like `mark:Verse`. Made from project markers,
+    /// which names are not notations.
+    Rehearsal(String),
+    /// Section label, like `section:Chorus`. Made from project
+    /// regions.
+    Section(String),
+    /// Barline before the measure, like `bar:double`. `double_bar`
+    /// is the same as `bar:double`.
+    Barline(Barline),
+    /// Barline after the measure, like `end_bar:final` in the last
+    /// one.
+    EndBarline(Barline),
+    /// Line break before the measure.
+    Break,
+    /// Repeated section starts at the measure: `repeat:start`.
+    RepeatStart,
+    /// Repeated section ends with the measure and is played the given
+    /// times: `repeat:end` (twice) or `repeat:end:3`.
+    RepeatEnd(u8),
+    /// Alternative ending starts at the measure and lasts the given
+    /// amount of measures: `volta:1` (one measure) or `volta:2:3`.
+    Volta(u8, u8),
+    /// Navigation mark, like `jump:segno` or `jump:ds_al_coda`.
+    Jump(Jump),
+}
+impl MeasureNotations {
+    /// Notation belongs to TimeMap, so it is applied to every part,
+    /// even if it is found in track notations. Key, beat grouping and
+    /// meter rules are kept per part.
+    ///
+    /// Such notation is applied only to the measure it is placed in,
+    /// while per-part notations last until they are changed.
+    pub fn is_global(&self) -> bool {
+        matches!(
+            self,
+            Self::Partial(_)
+                | Self::Tempo(_)
+                | Self::Rehearsal(_)
+                | Self::Section(_)
+                | Self::Barline(_)
+                | Self::EndBarline(_)
+                | Self::Break
+                | Self::RepeatStart
+                | Self::RepeatEnd(_)
+                | Self::Volta(..)
+                | Self::Jump(_)
+        )
+    }
+}
+impl ToString for MeasureNotations {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Key(key) => {
+                format!("key{TOKENS_DELIMITER}{}", key.to_string())
+            }
+            Self::BeatGrouping(groups) => {
+                format!("beats{TOKENS_DELIMITER}{}", groups.iter().join("+"))
+            }
+            Self::Meter(rules) => {
+                format!("meter{TOKENS_DELIMITER}{}", rules.to_string())
+            }
+            Self::Partial(length) => {
+                format!("partial{TOKENS_DELIMITER}{length}")
+            }
+            Self::Tempo(text) => format!("tempo{TOKENS_DELIMITER}{text}"),
+            Self::Rehearsal(name) => format!("mark{TOKENS_DELIMITER}{name}"),
+            Self::Section(name) => format!("section{TOKENS_DELIMITER}{name}"),
+            Self::Barline(barline) => {
+                format!("bar{TOKENS_DELIMITER}{}", barline.to_string())
+            }
+            Self::EndBarline(barline) => {
+                format!("end_bar{TOKENS_DELIMITER}{}", barline.to_string())
+            }
+            Self::Break => "break".to_string(),
+            Self::RepeatStart => format!("repeat{TOKENS_DELIMITER}start"),
+            Self::RepeatEnd(times) => {
+                format!("repeat{TOKENS_DELIMITER}end{TOKENS_DELIMITER}{times}")
+            }
+            Self::Volta(number, length) => format!(
+                "volta{TOKENS_DELIMITER}{number}{TOKENS_DELIMITER}{length}"
+            ),
+            Self::Jump(jump) => {
+                format!("jump{TOKENS_DELIMITER}{}", jump.to_string())
+            }
+        }
+    }
+}
+impl FromStr for MeasureNotations {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = reascore_tokens(s, None)?;
+        match tokens[0] {
+            "key" => {
+                let key = get_token(&tokens, 1)?;
+                Ok(Self::Key(key.parse()?))
+            }
+            "beats" => {
+                let groups = get_token(&tokens, 1)?
+                    .split('+')
+                    .map(|group| group.trim().parse::<u8>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                match groups.contains(&0) {
+                    true => Err(NotationError::UnexpectedToken(
+                        tokens[1].to_string(),
+                    )
+                    .into()),
+                    false => Ok(Self::BeatGrouping(groups)),
+                }
+            }
+            "meter" => {
+                let rules = get_token(&tokens, 1)?;
+                Ok(Self::Meter(rules.parse()?))
+            }
+            "partial" => {
+                let length = Fraction::from_str(get_token(&tokens, 1)?)?;
+                match length > Fraction::from(0) {
+                    true => Ok(Self::Partial(length)),
+                    false => Err(NotationError::UnexpectedToken(
+                        tokens[1].to_string(),
+                    )
+                    .into()),
+                }
+            }
+            "tempo" => {
+                let text = get_token(&tokens, 1)?;
+                Ok(Self::Tempo(text.trim().to_string()))
+            }
+            "mark" => {
+                let name = get_token(&tokens, 1)?;
+                Ok(Self::Rehearsal(name.trim().to_string()))
+            }
+            "section" => {
+                let name = get_token(&tokens, 1)?;
+                Ok(Self::Section(name.trim().to_string()))
+            }
+            "bar" => {
+                let barline = get_token(&tokens, 1)?;
+                Ok(Self::Barline(barline.parse()?))
+            }
+            "double_bar" => Ok(Self::Barline(Barline::Double)),
+            "end_bar" => {
+                let barline = get_token(&tokens, 1)?;
+                Ok(Self::EndBarline(barline.parse()?))
+            }
+            "break" => Ok(Self::Break),
+            "repeat" => match (get_token(&tokens, 1)?, tokens.get(2)) {
+                ("start", None) => Ok(Self::RepeatStart),
+                ("end", None) => Ok(Self::RepeatEnd(2)),
+                ("end", Some(times)) => match times.parse()? {
+                    0 | 1 => {
+                        Err(NotationError::UnexpectedToken(times.to_string())
+                            .into())
+                    }
+                    times => Ok(Self::RepeatEnd(times)),
+                },
+                (x, _) => {
+                    Err(NotationError::UnexpectedToken(x.to_string()).into())
+                }
+            },
+            "volta" => {
+                let number = get_token(&tokens, 1)?.parse()?;
+                let length = match tokens.get(2) {
+                    None => 1,
+                    Some(length) => length.parse()?,
+                };
+                match (number, length) {
+                    (0, _) | (_, 0) => Err(NotationError::UnexpectedToken(
+                        tokens[1..].join(TOKENS_DELIMITER),
+                    )
+                    .into()),
+                    _ => Ok(Self::Volta(number, length)),
+                }
+            }
+            "jump" => {
+                let jump = get_token(&tokens, 1)?;
+                Ok(Self::Jump(jump.parse()?))
+            }
+            x => Err(NotationError::UnexpectedToken(x.to_string()).into()),
+        }
+    }
+}
+
+/// Parse measure notations from the marker name, like
+/// `key:Eb-major` or `ReaScore|key:Eb-major|...`.
+///
+/// Tokens, that can not be parsed, are ignored.
+pub fn measure_notations_from_string(string: &str) -> Vec<MeasureNotations> {
+    string
+        .trim()
+        .split(NOTATION_DELIMITER)
+        .filter(|tk| *tk != SECTION)
+        .filter_map(|tk| tk.parse().ok())
+        .collect()
+}
+
+/// Parse notations from the name of project marker or region.
+///
+/// Marker, which name holds no notations, becomes rehearsal mark.
+/// Region becomes section label, and its notations are ignored.
+/// Names, starting from `ReaScore`, are never written.
+pub fn marker_notations_from_string(
+    name: &str,
+    is_region: bool,
+) -> Vec<MeasureNotations> {
+    let notations = measure_notations_from_string(name);
+    let name = name.trim();
+    let is_text =
+        notations.is_empty() && !name.is_empty() && !name.starts_with(SECTION);
+    match (is_region, is_text) {
+        (false, false) => notations,
+        (false, true) => vec![MeasureNotations::Rehearsal(name.to_string())],
+        (true, true) => vec![MeasureNotations::Section(name.to_string())],
+        (true, false) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+
+    use crate::primitives::{
+        Barline, Jump, KeyMode, KeySignature, MeterRules, NoteName,
+    };
+
+    use super::{
+        marker_notations_from_string, measure_notations_from_string,
+        MeasureNotations,
+    };
+
+    #[test]
+    fn test_measure_notations() {
+        let key = MeasureNotations::Key(KeySignature::new(
+            NoteName::E,
+            -1,
+            KeyMode::Major,
+        ));
+        assert_eq!("key:Eb-major".parse::<MeasureNotations>().unwrap(), key);
+        assert_eq!(key.to_string(), "key:Eb-major");
+        assert!("key".parse::<MeasureNotations>().is_err());
+        assert_eq!(
+            measure_notations_from_string("ReaScore|key:Eb-major"),
+            vec![key.clone()]
+        );
+        assert_eq!(measure_notations_from_string("Verse"), vec![]);
+    }
+
+    #[test]
+    fn test_beat_grouping() {
+        let grouping = MeasureNotations::BeatGrouping(vec![2, 2, 3]);
+        assert_eq!(
+            "beats:2+2+3".parse::<MeasureNotations>().unwrap(),
+            grouping
+        );
+        assert_eq!(grouping.to_string(), "beats:2+2+3");
+        assert!(!grouping.is_global());
+        assert!("beats:2+0+3".parse::<MeasureNotations>().is_err());
+        assert!("beats:2+x".parse::<MeasureNotations>().is_err());
+        assert_eq!(
+            measure_notations_from_string("ReaScore|key:C-major|beats:3+2"),
+            vec![
+                MeasureNotations::Key("C-major".parse().unwrap()),
+                MeasureNotations::BeatGrouping(vec![3, 2])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_meter_rules() {
+        let meter = MeasureNotations::Meter(MeterRules::Beats);
+        assert_eq!("meter:beats".parse::<MeasureNotations>().unwrap(), meter);
+        assert_eq!(meter.to_string(), "meter:beats");
+        assert!("meter:free".parse::<MeasureNotations>().is_err());
+    }
+
+    #[test]
+    fn test_partial() {
+        let partial = MeasureNotations::Partial(Fraction::new(3_u64, 8_u64));
+        assert_eq!(
+            "partial:3/8".parse::<MeasureNotations>().unwrap(),
+            partial
+        );
+        assert_eq!(partial.to_string(), "partial:3/8");
+        assert!(partial.is_global());
+        assert!("partial:0".parse::<MeasureNotations>().is_err());
+        assert!("partial".parse::<MeasureNotations>().is_err());
+    }
+
+    #[test]
+    fn test_tempo() {
+        let tempo = MeasureNotations::Tempo("Allegro ma non troppo".into());
+        assert_eq!(
+            "tempo:Allegro ma non troppo"
+                .parse::<MeasureNotations>()
+                .unwrap(),
+            tempo
+        );
+        assert_eq!(tempo.to_string(), "tempo:Allegro ma non troppo");
+        assert!(tempo.is_global());
+    }
+
+    #[test]
+    fn test_marker_notations() {
+        assert_eq!(
+            marker_notations_from_string("Verse", false),
+            vec![MeasureNotations::Rehearsal("Verse".to_string())]
+        );
+        assert_eq!(
+            marker_notations_from_string(" Chorus ", true),
+            vec![MeasureNotations::Section("Chorus".to_string())]
+        );
+        assert_eq!(
+            marker_notations_from_string("ReaScore|double_bar|break", false),
+            vec![
+                MeasureNotations::Barline(Barline::Double),
+                MeasureNotations::Break
+            ]
+        );
+        assert!(
+            marker_notations_from_string("ReaScore|wrong", false).is_empty()
+        );
+        assert!(marker_notations_from_string("break", true).is_empty());
+        assert!(marker_notations_from_string("", false).is_empty());
+    }
+
+    #[test]
+    fn test_repeats() {
+        let parse = |s: &str| s.parse::<MeasureNotations>();
+        assert_eq!(
+            parse("repeat:start").unwrap(),
+            MeasureNotations::RepeatStart
+        );
+        assert_eq!(
+            parse("repeat:end").unwrap(),
+            MeasureNotations::RepeatEnd(2)
+        );
+        assert_eq!(
+            parse("repeat:end:3").unwrap(),
+            MeasureNotations::RepeatEnd(3)
+        );
+        assert!(parse("repeat:end:1").is_err());
+        assert!(parse("repeat:middle").is_err());
+        assert_eq!(parse("volta:1").unwrap(), MeasureNotations::Volta(1, 1));
+        assert_eq!(parse("volta:2:3").unwrap(), MeasureNotations::Volta(2, 3));
+        assert!(parse("volta:0").is_err());
+        assert_eq!(
+            parse("bar:final").unwrap(),
+            MeasureNotations::Barline(Barline::Final)
+        );
+        assert_eq!(
+            parse("end_bar:final").unwrap(),
+            MeasureNotations::EndBarline(Barline::Final)
+        );
+        assert_eq!(
+            parse("jump:dc_al_fine").unwrap(),
+            MeasureNotations::Jump(Jump::DaCapoAlFine)
+        );
+        for notation in [
+            MeasureNotations::RepeatEnd(3),
+            MeasureNotations::Volta(2, 3),
+            MeasureNotations::Barline(Barline::Dashed),
+            MeasureNotations::EndBarline(Barline::Final),
+            MeasureNotations::Jump(Jump::Coda),
+        ] {
+            assert_eq!(parse(&notation.to_string()).unwrap(), notation);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::{
-    reascore_tokens, NotationError, NotationType, NOTATION_DELIMITER, SECTION,
+    chord_notations::ChordNotations, reascore_tokens, NotationError,
+    NotationType, NOTATION_DELIMITER, SECTION,
 };
 use rea_rs::midi::Notation as MNotation;
 use rea_rs::NotationMessage;
@@ -23,8 +24,17 @@ impl MidiFuncs {
                 });
                 Some(notes.chain(chords).collect())
             }
-            MNotation::Track(_) => todo!(),
-            MNotation::Unknown(_) => todo!(),
+            MNotation::Track(tokens) => {
+                let tokens = reascore_notation_string(&tokens)?;
+                let measures = tokens.iter().filter_map(|tk| {
+                    Some(NotationType::Measure(tk.parse().ok()?))
+                });
+                let tracks = tokens.iter().filter_map(|tk| {
+                    Some(NotationType::Track(tk.parse().ok()?))
+                });
+                Some(measures.chain(tracks).collect())
+            }
+            MNotation::Unknown(_) => None,
         }
     }
 
@@ -55,9 +65,10 @@ impl MidiFuncs {
                 let mut rs_tokens = rs_tokens
                     .iter_mut()
                     .map(|tk| -> Result<String, NotationError> {
-                        let tk_start = reascore_tokens(tk, None)?[0];
+                        let tk_key = replacement_key(tk)?;
                         for (idx, st) in strings.iter().enumerate() {
-                            if st.starts_with(tk_start) {
+                            // e.g. `slur` should not replace `slur_end`
+                            if replacement_key(st)? == tk_key {
                                 return Ok(strings.swap_remove(idx));
                             }
                         }
@@ -122,6 +133,19 @@ impl MidiFuncs {
         msg
     }
 }
+/// Tokens with the same key replace each other.
+///
+/// It is the first segment of token, but every articulation has its
+/// own key, as a note can hold several of them.
+fn replacement_key(token: &str) -> Result<String, NotationError> {
+    match token.parse::<ChordNotations>() {
+        Ok(ChordNotations::Articulation(art)) => {
+            Ok(ChordNotations::Articulation(art).to_string())
+        }
+        _ => Ok(reascore_tokens(token, None)?[0].to_string()),
+    }
+}
+
 /// Get reascore tokens, if any.
 fn reascore_notation_string(tokens: &Vec<String>) -> Option<Vec<String>> {
     let v: Vec<String> = tokens
@@ -171,7 +195,7 @@ mod tests {
     use rea_rs::{midi::Notation as MNotation, NotationMessage};
 
     use crate::notation::{
-        chord_notations::ChordNotations,
+        chord_notations::{Articulation, ChordNotations},
         message::MidiFuncs,
         note_notations::{self, NoteNotations},
         reascore_tokens, NotationError, NotationType,
@@ -272,4 +296,51 @@ mod tests {
             )),]
         );
     }
+
+    #[test]
+    fn test_replace_slur() {
+        let msg = NotationMessage::from(MNotation::Note {
+            channel: 1,
+            note: 60,
+            tokens: vec!["ReaScore|slur".to_string()],
+        });
+        let msg = MidiFuncs::replace_notations(
+            msg,
+            vec![NotationType::Chord(ChordNotations::SlurEnd)],
+        )
+        .expect("Can not replace notations");
+        assert_eq!(
+            MidiFuncs::parse_notations(msg).unwrap(),
+            vec![
+                NotationType::Chord(ChordNotations::SlurStart),
+                NotationType::Chord(ChordNotations::SlurEnd),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_articulation() {
+        let msg = NotationMessage::from(MNotation::Note {
+            channel: 1,
+            note: 60,
+            tokens: vec!["ReaScore|art:staccato".to_string()],
+        });
+        let accent = NotationType::Chord(ChordNotations::Articulation(
+            Articulation::Accent,
+        ));
+        let msg = MidiFuncs::replace_notations(msg, vec![accent.clone()])
+            .expect("Can not replace notations");
+        // the same articulation is not duplicated
+        let msg = MidiFuncs::replace_notations(msg, vec![accent.clone()])
+            .expect("Can not replace notations");
+        assert_eq!(
+            MidiFuncs::parse_notations(msg).unwrap(),
+            vec![
+                NotationType::Chord(ChordNotations::Articulation(
+                    Articulation::Staccato
+                )),
+                accent,
+            ]
+        );
+    }
 }
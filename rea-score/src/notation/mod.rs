@@ -1,11 +1,15 @@
 use thiserror;
 
 use self::chord_notations::ChordNotations;
+use self::measure_notations::MeasureNotations;
 use self::note_notations::NoteNotations;
+use self::track_notations::TrackNotations;
 
 pub mod chord_notations;
+pub mod measure_notations;
 pub mod message;
 pub mod note_notations;
+pub mod track_notations;
 
 #[derive(Debug, thiserror::Error)]
 pub enum NotationError {
@@ -68,6 +72,10 @@ pub enum NotationType {
     /// channel, note: note still presents, as chord
     /// events will be de-duplicated by mapping to events.
     Chord(ChordNotations),
+    /// Applied to the measure, and all following measures.
+    Measure(MeasureNotations),
+    /// Applied to the whole Part, e.g. its staff clef.
+    Track(TrackNotations),
     Event,
 }
 impl ToString for NotationType {
@@ -75,6 +83,8 @@ impl ToString for NotationType {
         match self {
             Self::Note(n) => n.to_string(),
             Self::Chord(c) => c.to_string(),
+            Self::Measure(m) => m.to_string(),
+            Self::Track(t) => t.to_string(),
             Self::Event => unimplemented!(),
         }
     }
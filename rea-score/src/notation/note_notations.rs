@@ -107,6 +107,31 @@ impl ToString for NoteHead {
         }
     }
 }
+impl NoteHead {
+    /// `<notehead>` element of MusicXML.
+    pub fn musicxml(&self) -> String {
+        let (value, filled) = match *self {
+            Self::Default
+            | Self::AltDefault
+            | Self::Baroque
+            | Self::Neomensural
+            | Self::Mensural
+            | Self::Petrucci => return String::new(),
+            Self::Harmonic => ("diamond", false),
+            Self::HarmonicBlack => ("diamond", true),
+            Self::HarmonicMixed => ("diamond", false),
+            Self::Diamond => ("diamond", false),
+            Self::Cross => ("x", false),
+            Self::XCircle => ("circle-x", false),
+            Self::Triangle => ("triangle", false),
+            Self::Slash => ("slash", false),
+        };
+        match filled {
+            true => format!(r#"<notehead filled="yes">{value}</notehead>"#),
+            false => format!("<notehead>{value}</notehead>"),
+        }
+    }
+}
 impl FromStr for NoteHead {
     type Err = NotationError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -0,0 +1,71 @@
+use std::{error::Error, str::FromStr};
+
+use crate::primitives::Clef;
+
+use super::{get_token, reascore_tokens, NotationError, TOKENS_DELIMITER};
+
+/// Notations, that configure the whole Part, wherever they are placed
+/// in track notation events.
+///
+/// Staff index is optional in tokens (e.g. `clef:bass` or
+/// `clef:bass:2`), and is 1 by default.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TrackNotations {
+    /// staff index, clef
+    Clef(u8, Clef),
+    /// staff index. Clef of the staff is chosen by pitch range of
+    /// every measure, starting from the staff clef.
+    AutoClef(u8),
+}
+impl ToString for TrackNotations {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Clef(staff, clef) => format!(
+                "clef{TOKENS_DELIMITER}{}{TOKENS_DELIMITER}{staff}",
+                clef.to_string()
+            ),
+            Self::AutoClef(staff) => {
+                format!("clef{TOKENS_DELIMITER}auto{TOKENS_DELIMITER}{staff}")
+            }
+        }
+    }
+}
+impl FromStr for TrackNotations {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = reascore_tokens(s, None)?;
+        match tokens[0] {
+            "clef" => {
+                let staff = match tokens.get(2) {
+                    None => 1,
+                    Some(staff) => staff.parse()?,
+                };
+                match get_token(&tokens, 1)? {
+                    "auto" => Ok(Self::AutoClef(staff)),
+                    clef => Ok(Self::Clef(staff, clef.parse()?)),
+                }
+            }
+            x => Err(NotationError::UnexpectedToken(x.to_string()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::primitives::Clef;
+
+    use super::TrackNotations;
+
+    #[test]
+    fn test_track_notations() {
+        assert_eq!(
+            "clef:bass".parse::<TrackNotations>().unwrap(),
+            TrackNotations::Clef(1, Clef::Bass)
+        );
+        let auto = "clef:auto:2".parse::<TrackNotations>().unwrap();
+        assert_eq!(auto, TrackNotations::AutoClef(2));
+        assert_eq!(auto.to_string(), "clef:auto:2");
+        assert!("clef:soprano".parse::<TrackNotations>().is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Clefs of staff, and automatic clef selection by pitch range.
+use std::str::FromStr;
+
+use crate::{lilypond_render::RendersToLilypond, notation::NotationError};
+
+/// How far (in semitones) notes can go out of the staff lines,
+/// before auto-clef switches to another clef.
+///
+/// About three ledger lines. Keeps clef from flipping on every
+/// measure, which wanders around the middle C.
+pub const AUTO_CLEF_HYSTERESIS: u8 = 5;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Clef {
+    #[default]
+    Treble,
+    /// Treble, sounding an octave lower (tenor voice, guitar).
+    Treble8,
+    Bass,
+    Alto,
+    Tenor,
+}
+impl Clef {
+    /// MIDI pitches of the lowest and the highest staff lines.
+    pub fn staff_range(&self) -> (u8, u8) {
+        match self {
+            Self::Treble => (64, 77),
+            Self::Treble8 => (52, 65),
+            Self::Bass => (43, 57),
+            Self::Alto => (53, 67),
+            Self::Tenor => (50, 64),
+        }
+    }
+
+    /// Clefs, auto-clef chooses from, if staff starts with this one.
+    fn auto_candidates(&self) -> &'static [Clef] {
+        match self {
+            Self::Treble8 => &[Self::Treble8, Self::Bass],
+            _ => &[Self::Treble, Self::Bass],
+        }
+    }
+
+    /// Whether pitch range can be written without too many ledger
+    /// lines.
+    fn fits(&self, range: (u8, u8)) -> bool {
+        let (low, high) = self.staff_range();
+        range.0.saturating_add(AUTO_CLEF_HYSTERESIS) >= low
+            && range.1 <= high.saturating_add(AUTO_CLEF_HYSTERESIS)
+    }
+
+    /// Doubled distance between middles of staff and pitch range.
+    fn distance(&self, range: (u8, u8)) -> u16 {
+        let (low, high) = self.staff_range();
+        (low as i16 + high as i16 - range.0 as i16 - range.1 as i16)
+            .unsigned_abs()
+    }
+
+    /// Choose clef for every measure by pitch range of its notes.
+    ///
+    /// Clef is kept, while notes fit its staff with a few ledger lines
+    /// (see [AUTO_CLEF_HYSTERESIS]). Otherwise, the clef, which staff
+    /// is centered closer to the notes, is taken. Measures without
+    /// notes keep the current clef.
+    ///
+    /// Returns clef changes as `(measure index, clef)`, where the
+    /// first item is always the clef of the first measure.
+    pub fn auto_clefs(
+        initial: Clef,
+        ranges: impl IntoIterator<Item = (u32, Option<(u8, u8)>)>,
+    ) -> Vec<(u32, Clef)> {
+        let mut clefs: Vec<(u32, Clef)> = Vec::new();
+        let mut current = initial;
+        for (index, range) in ranges {
+            if let Some(range) = range {
+                if !current.fits(range) {
+                    current = *initial
+                        .auto_candidates()
+                        .iter()
+                        .min_by_key(|clef| clef.distance(range))
+                        .unwrap_or(&current);
+                }
+            }
+            if clefs.last().map(|(_, clef)| *clef) != Some(current) {
+                clefs.push((index, current));
+            }
+        }
+        clefs
+    }
+
+    /// `<clef>` element of MusicXML for the staff with given number.
+    pub fn musicxml(&self, number: usize) -> String {
+        let (sign, line, octave) = match self {
+            Self::Treble => ("G", 2, 0),
+            Self::Treble8 => ("G", 2, -1),
+            Self::Bass => ("F", 4, 0),
+            Self::Alto => ("C", 3, 0),
+            Self::Tenor => ("C", 4, 0),
+        };
+        let octave = match octave {
+            0 => String::new(),
+            x => format!("<clef-octave-change>{x}</clef-octave-change>"),
+        };
+        format!(
+            "<clef number=\"{number}\"><sign>{sign}</sign>\
+            <line>{line}</line>{octave}</clef>"
+        )
+    }
+}
+impl ToString for Clef {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Treble => "treble",
+            Self::Treble8 => "treble_8",
+            Self::Bass => "bass",
+            Self::Alto => "alto",
+            Self::Tenor => "tenor",
+        }
+        .to_string()
+    }
+}
+impl FromStr for Clef {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "treble" => Ok(Self::Treble),
+            "treble_8" => Ok(Self::Treble8),
+            "bass" => Ok(Self::Bass),
+            "alto" => Ok(Self::Alto),
+            "tenor" => Ok(Self::Tenor),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+impl RendersToLilypond for Clef {
+    fn render_lilypond(&self) -> String {
+        match self {
+            Self::Treble8 => r#"\clef "treble_8""#.to_string(),
+            clef => format!(r"\clef {}", clef.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lilypond_render::RendersToLilypond;
+
+    use super::Clef;
+
+    #[test]
+    fn test_auto_clefs() {
+        assert_eq!(Clef::Treble8.render_lilypond(), r#"\clef "treble_8""#);
+        assert_eq!("bass".parse::<Clef>().unwrap(), Clef::Bass);
+        let ranges = vec![
+            (1, Some((64, 72))),
+            // around middle C: still treble
+            (2, Some((59, 67))),
+            (3, Some((40, 52))),
+            (4, None),
+            // around middle C: still bass
+            (5, Some((55, 62))),
+            (6, Some((67, 79))),
+        ];
+        assert_eq!(
+            Clef::auto_clefs(Clef::Treble, ranges.clone()),
+            vec![(1, Clef::Treble), (3, Clef::Bass), (6, Clef::Treble)]
+        );
+        assert_eq!(
+            Clef::auto_clefs(Clef::Treble8, ranges),
+            vec![(1, Clef::Treble8), (3, Clef::Bass), (6, Clef::Treble8)]
+        );
+    }
+}
@@ -3,8 +3,8 @@ use std::collections::VecDeque;
 use fraction::Fraction;
 
 use super::{
-    event::EventTupletType, Chord, EventInfo, EventType, Length,
-    RelativePosition,
+    event::{EventTupletType, Tuplet},
+    Chord, EventInfo, EventType, Length, RelativePosition,
 };
 
 #[derive(Debug, PartialEq, Clone)]
@@ -12,7 +12,8 @@ pub struct Container {
     events: VecDeque<EventInfo>,
     position: RelativePosition,
     length: Length,
-    tuplet: Option<Box<EventInfo>>,
+    /// Open tuplets, the outer first.
+    tuplets: Vec<EventInfo>,
 }
 impl Container {
     fn new(
@@ -24,7 +25,7 @@ impl Container {
             events,
             position,
             length,
-            tuplet: None,
+            tuplets: Vec::new(),
         }
     }
     pub fn empty(
@@ -94,54 +95,36 @@ impl Container {
             _ => (),
         };
 
-        match event.tuplet_type {
-            EventTupletType::TupletStart(rate) => {
-                event = event.convert_to_tuplet(rate);
-                event.position.set_position(
-                    event.position.position_quantized(),
-                );
-                self.tuplet = Some(Box::new(event));
+        match event.tuplet_type.clone() {
+            EventTupletType::TupletStart(rates)
+                if !rates.is_empty() =>
+            {
+                self.open_tuplets(event, rates)?;
                 return Ok(None);
             }
-            EventTupletType::NonTuplet => match &mut self.tuplet {
-                Some(tuplet) => {
-                    match &mut tuplet.event {
-                        EventType::Tuplet(tuplet) => {
-                            tuplet.push(event)?;
-                        }
-                        _ => panic!(
-                            "Tuplet event is not a tuplet: {:?}",
-                            tuplet
-                        ),
-                    }
+            EventTupletType::NonTuplet
+            | EventTupletType::TupletStart(_) => {
+                if !self.tuplets.is_empty() {
+                    self.push_to_tuplet(event)?;
                     return Ok(None);
                 }
-                None => (),
-            },
-            EventTupletType::TupletEnd => match &mut self.tuplet {
-                None => eprintln!("Unexpected tuplet end event."),
-                Some(tuplet) => {
-                    event.quantize_end(None);
-                    event.tuplet_type = EventTupletType::NonTuplet;
-                    let end_pos: RelativePosition;
-                    match &mut tuplet.event {
-                        EventType::Tuplet(tuplet) => {
-                            tuplet.push(event)?;
-                            end_pos = tuplet.end_position();
-                        }
-                        _ => panic!(
-                            "Tuplet event is not a tuplet: {:?}",
-                            tuplet
-                        ),
+            }
+            EventTupletType::TupletEnd(levels) => {
+                if self.tuplets.is_empty() {
+                    eprintln!("Unexpected tuplet end event.");
+                } else {
+                    self.push_to_tuplet(event)?;
+                    match self.close_tuplets(levels)? {
+                        None => return Ok(None),
+                        Some(tuplet) => event = tuplet,
                     }
-                    event = *self
-                        .tuplet
-                        .take()
-                        .expect("Should be tuplet here");
-                    event.set_end_position(end_pos);
-                    self.tuplet = None;
                 }
-            },
+            }
+        }
+        if let EventType::Tuplet(_) = event.event {
+            if event.end_position().position() > self.end() {
+                return self.insert_crossing_tuplet(event);
+            }
         }
         let mut idx = self
             .events()
@@ -251,6 +234,164 @@ impl Container {
         }
     }
 
+    /// True if tuplet is started in the container, but not finished
+    /// yet.
+    pub fn has_open_tuplets(&self) -> bool {
+        !self.tuplets.is_empty()
+    }
+
+    /// Split container at the position from its start, and return
+    /// the second part, which starts at the container position.
+    ///
+    /// Event, sounding at the position, is split and tied.
+    pub fn cut_head_at(
+        &mut self,
+        position: Fraction,
+    ) -> Result<Self, String> {
+        let split = self.position.position() + position;
+        let mut head = VecDeque::new();
+        while let Some(mut event) = self.events.pop_back() {
+            if event.position.position() >= split {
+                head.push_front(event);
+                continue;
+            }
+            if event.end_position().position() > split {
+                let mut split_position = event.position.clone();
+                split_position.set_position(split);
+                head.push_front(
+                    event.cut_head_at_position(&split_position)?,
+                );
+            }
+            self.events.push_back(event);
+            break;
+        }
+        for event in head.iter_mut() {
+            event
+                .position
+                .set_position(event.position.position() - position);
+        }
+        let head_length = Length::from(self.length.get() - position);
+        self.length = Length::from(position);
+        Ok(Self::new(head, self.position.clone(), head_length))
+    }
+
+    fn end(&self) -> Fraction {
+        self.position.position() + self.length.get()
+    }
+
+    /// Split tuplet at the barline, insert the first part and return
+    /// the second one, moved to the next measure.
+    fn insert_crossing_tuplet(
+        &mut self,
+        mut event: EventInfo,
+    ) -> Result<Option<EventInfo>, String> {
+        let mut barline = event.position.clone();
+        barline.set_position(self.end());
+        let mut head = event.cut_head_at_position(&barline)?;
+        head.position.set_measure_index(
+            self.position.get_measure_index() + 1,
+        );
+        head.position.set_position(Fraction::from(0));
+        match self.insert(event)? {
+            None => Ok(Some(head)),
+            Some(unexpected) => Err(format!(
+                "unexpected head of event found: {:?}",
+                unexpected
+            )),
+        }
+    }
+
+    /// Scale positions and lengths of events from the container
+    /// start, including contents of nested tuplets.
+    pub fn scale(&mut self, factor: Fraction) {
+        let start = self.position.position();
+        for event in self.events.iter_mut() {
+            event.position.set_position(
+                start + (event.position.position() - start) * factor,
+            );
+            event.length = Length::from(event.length.get() * factor);
+            if let EventType::Tuplet(tuplet) = &mut event.event {
+                tuplet.scale(factor);
+            }
+        }
+        self.length = Length::from(self.length.get() * factor);
+    }
+
+    /// Open tuplets, starting on the event, the outer first.
+    ///
+    /// Outer tuplets start with rest, which is replaced by the
+    /// nested tuplet when it is closed.
+    fn open_tuplets(
+        &mut self,
+        mut event: EventInfo,
+        rates: Vec<Fraction>,
+    ) -> Result<(), String> {
+        event.tuplet_type = EventTupletType::NonTuplet;
+        let innermost = rates.len() - 1;
+        for (idx, rate) in rates.into_iter().enumerate() {
+            let mut start = self.tuplets.iter_mut().try_fold(
+                event.clone(),
+                |ev, tuplet| {
+                    Ok::<_, String>(tuplet_mut(tuplet)?.scale_event(ev))
+                },
+            )?;
+            if idx < innermost {
+                start.event = EventType::Rest;
+            }
+            let mut tuplet = start.convert_to_tuplet(rate);
+            tuplet
+                .position
+                .set_position(tuplet.position.position_quantized());
+            self.tuplets.push(tuplet);
+        }
+        Ok(())
+    }
+
+    /// Push event to the innermost open tuplet.
+    ///
+    /// End of the event, which closes tuplet, is quantized in the time
+    /// of the outer tuplet.
+    fn push_to_tuplet(
+        &mut self,
+        event: EventInfo,
+    ) -> Result<(), String> {
+        let (innermost, outer) = self
+            .tuplets
+            .split_last_mut()
+            .ok_or("No open tuplet to push event to.")?;
+        let mut event = outer.iter_mut().try_fold(event, |ev, tuplet| {
+            Ok::<_, String>(tuplet_mut(tuplet)?.scale_event(ev))
+        })?;
+        if let EventTupletType::TupletEnd(_) = event.tuplet_type {
+            event.quantize_end(None);
+            event.tuplet_type = EventTupletType::NonTuplet;
+        }
+        tuplet_mut(innermost)?.push(event)
+    }
+
+    /// Close the innermost tuplets, putting every closed one to its
+    /// outer tuplet.
+    ///
+    /// Returns the outermost tuplet, if it is closed.
+    fn close_tuplets(
+        &mut self,
+        levels: u8,
+    ) -> Result<Option<EventInfo>, String> {
+        for _ in 0..levels.max(1) {
+            let mut tuplet = match self.tuplets.pop() {
+                Some(tuplet) => tuplet,
+                None => break,
+            };
+            let end = tuplet_mut(&mut tuplet)?.end_position();
+            tuplet.set_end_position(end);
+            match self.tuplets.last_mut() {
+                None => return Ok(Some(tuplet)),
+                Some(outer) => tuplet_mut(outer)?.push_scaled(tuplet)?,
+            }
+        }
+        Ok(None)
+    }
+
     /// cuts head from inserted event, or from the current
     /// measure event, depends on their overlaps.
     ///
@@ -295,3 +436,10 @@ impl Container {
         Ok((event, append_to_self))
     }
 }
+
+fn tuplet_mut(event: &mut EventInfo) -> Result<&mut Tuplet, String> {
+    match &mut event.event {
+        EventType::Tuplet(tuplet) => Ok(tuplet),
+        event => Err(format!("Tuplet event is not a tuplet: {:?}", event)),
+    }
+}
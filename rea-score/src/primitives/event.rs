@@ -2,28 +2,40 @@
 use std::collections::VecDeque;
 
 use fraction::Fraction;
-use itertools::Itertools;
 
 use crate::{
-    lilypond_render::{RenderSettings, RendersToLilypond},
+    lilypond_render::{RenderError, RenderSettings, RendersToLilypond},
+    musicxml_render::{
+        note_type_elements, notations_element, pitch_elements,
+        MusicXmlContext, RendersToMusicXml, TupletState,
+    },
     notation::{
-        chord_notations::ChordNotations,
-        note_notations::NoteNotations, NotationError,
-        NotationRender, NotationSplitPosition, NotationType,
+        chord_notations::{
+            rendered_chord_notations, ChordNotations,
+        },
+        note_notations::NoteNotations,
+        NotationError, NotationRender, NotationSplitPosition,
+        NotationType,
     },
 };
 
 use super::{
-    container::Container, limit_denominator, normalize_fraction,
-    Length, Pitch, RelativePosition, ResolvedPitch,
-    LIMIT_DENOMINATOR,
+    container::Container,
+    grace::{
+        remove_head_graces, remove_tail_graces, render_with_graces,
+        with_graces_musicxml,
+    },
+    limit_denominator, GraceGroup, GraceType, Length, Pitch, RelativePosition,
+    ResolvedPitch, LIMIT_DENOMINATOR,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum EventTupletType {
     NonTuplet,
-    TupletStart(Fraction),
-    TupletEnd,
+    /// Rates of tuplets, opened by the event, the outer first.
+    TupletStart(Vec<Fraction>),
+    /// Amount of the innermost tuplets, closed by the event.
+    TupletEnd(u8),
     // Tuplet(Fraction),
 }
 
@@ -40,10 +52,81 @@ pub struct EventInfo {
     pub tuplet_type: EventTupletType,
 }
 impl RendersToLilypond for EventInfo {
+    /// Event, that can not be rendered, is written as a comment.
     fn render_lilypond(&self) -> String {
-        let settings = Self::global_render_settings();
-        let length_string = self.length.render_lilypond();
-        self.event.render_lilypond(length_string, &settings)
+        match self.render_lilypond_with(&Self::global_render_settings()) {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                log::error!("Can not render event: {err}");
+                format!("%{{ error: {err} %}}")
+            }
+        }
+    }
+}
+impl EventInfo {
+    /// Render with the given settings, e.g. with key of the measure.
+    ///
+    /// Length, that can not be written by a single duration, is
+    /// rendered as tied pieces of the event.
+    pub fn render_lilypond_with(
+        &self,
+        settings: &RenderSettings,
+    ) -> Result<String, RenderError> {
+        match (self.length.try_render_lilypond(), &self.event) {
+            (Ok(length_string), _) => {
+                self.event.render_lilypond(length_string, settings)
+            }
+            // tuplet renders its own events
+            (Err(_), EventType::Tuplet(_)) => {
+                self.event.render_lilypond(String::new(), settings)
+            }
+            (Err(_), _) => Ok(self
+                .with_normalized_length()
+                .iter()
+                .map(|ev| {
+                    ev.event.render_lilypond(
+                        ev.length.try_render_lilypond()?,
+                        settings,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(" ")),
+        }
+    }
+}
+impl RendersToMusicXml for EventInfo {
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        ctx.length = self.length.clone();
+        match &self.event {
+            EventType::Tuplet(tuplet) => tuplet.render_musicxml(ctx),
+            EventType::Rest => {
+                let rest = format!(
+                    "<note><rest/><duration>{}</duration>\
+                    <voice>{}</voice>{}{}<staff>{}</staff>{}</note>",
+                    ctx.duration(&self.length),
+                    ctx.voice,
+                    note_type_elements(&self.length),
+                    ctx.time_modification(),
+                    ctx.staff,
+                    notations_element(ctx.tuplet_notations()),
+                );
+                ctx.finish_event();
+                Ok(rest)
+            }
+            EventType::Note(note) => {
+                let note = note.render_musicxml(ctx)?;
+                ctx.finish_event();
+                Ok(note)
+            }
+            EventType::Chord(chord) => {
+                let chord = chord.render_musicxml(ctx)?;
+                ctx.finish_event();
+                Ok(chord)
+            }
+        }
     }
 }
 impl EventInfo {
@@ -64,18 +147,17 @@ impl EventInfo {
     /// only one event.
     ///
     /// Later events can be pushed to the tuplet ([EventType]) itself
-    /// and it will grow.
+    /// and it will grow. If event is a tuplet, it becomes nested
+    /// into the new one.
     pub fn convert_to_tuplet(mut self, rate: Fraction) -> Self {
-        match &self.event {
-            EventType::Tuplet(_) => self,
-            _ => {
-                self.tuplet_type = EventTupletType::NonTuplet;
-                let mut event = self.clone();
-                event.event =
-                    EventType::Tuplet(Tuplet::new(rate, vec![self]));
-                event
-            }
+        self.tuplet_type = EventTupletType::NonTuplet;
+        if let EventType::Tuplet(tuplet) = &mut self.event {
+            tuplet.scale(rate);
         }
+        let mut event = self.clone();
+        event.event =
+            EventType::Tuplet(Tuplet::new(rate, vec![self]));
+        event
     }
 
     /// adjust event length to make event end quantized.
@@ -248,7 +330,6 @@ impl EventInfo {
         &mut self,
         head_length: Length,
     ) -> Result<Self, String> {
-        let (l_evt, r_evt) = self.event.clone().split();
         if self.length < head_length {
             return Err(format!(
                 "Trying to cut head bigger, than body: head: {:?}, body: {:?}",
@@ -259,6 +340,13 @@ impl EventInfo {
             Length::from(self.length.get() - head_length.get()),
             head_length,
         );
+        let (l_evt, r_evt) = match &mut self.event {
+            EventType::Tuplet(tuplet) => {
+                let head = tuplet.cut_head(l_len.get())?;
+                (self.event.clone(), EventType::Tuplet(head))
+            }
+            event => event.clone().split(),
+        };
         let mut r_pos = self.position.clone();
         r_pos.set_position(self.position.position() + l_len.get());
         self.set_event(l_evt).set_length(l_len);
@@ -307,10 +395,7 @@ impl EventInfo {
 
     /// Get events, split by normalized length.
     pub fn with_normalized_length(&self) -> VecDeque<Self> {
-        let lengths = normalize_fraction(
-            self.length.get_quantized(),
-            VecDeque::new(),
-        );
+        let lengths = self.length.normalized();
         let len = lengths.len();
         let mut pos = self.position.clone();
         let mut events = VecDeque::new();
@@ -377,14 +462,17 @@ impl EventInfo {
     ) -> Result<(), NotationError> {
         match notation {
             NotationType::Chord(ChordNotations::TupletRate(
-                rate,
+                rates,
             )) => {
                 self.tuplet_type =
-                    EventTupletType::TupletStart(rate);
+                    EventTupletType::TupletStart(rates);
                 Ok(())
             }
-            NotationType::Chord(ChordNotations::TupletEnd) => {
-                self.tuplet_type = EventTupletType::TupletEnd;
+            NotationType::Chord(ChordNotations::TupletEnd(
+                levels,
+            )) => {
+                self.tuplet_type =
+                    EventTupletType::TupletEnd(levels);
                 Ok(())
             }
             notation => self.event.push_notation(notation),
@@ -404,6 +492,22 @@ pub enum EventType {
     Tuplet(Tuplet),
 }
 impl EventType {
+    /// MIDI pitches of all notes of the event.
+    pub fn pitches(&self) -> Vec<u8> {
+        match self {
+            Self::Rest => Vec::new(),
+            Self::Note(note) => vec![note.pitch.midi()],
+            Self::Chord(chord) => {
+                chord.notes().iter().map(|note| note.pitch.midi()).collect()
+            }
+            Self::Tuplet(tuplet) => tuplet
+                .container()
+                .events()
+                .iter()
+                .flat_map(|ev| ev.event.pitches())
+                .collect(),
+        }
+    }
     fn split(self) -> (Self, Self) {
         let a = match self.clone() {
             Self::Note(mut note) => {
@@ -424,10 +528,12 @@ impl EventType {
         let b = match self {
             Self::Note(mut note) => {
                 note.remove_head_notations();
+                note.velocity = None;
                 Self::Note(note)
             }
             Self::Chord(mut ch) => {
                 ch.remove_head_notations();
+                ch.notes.iter_mut().for_each(|n| n.velocity = None);
                 Self::Chord(ch)
             }
             Self::Rest => Self::Rest,
@@ -459,18 +565,50 @@ impl EventType {
             }
         }
     }
+    /// Attach grace notes to note or chord.
+    pub fn push_grace(
+        &mut self,
+        grace: GraceGroup,
+    ) -> Result<(), String> {
+        match self {
+            Self::Note(note) => note.graces.push(grace),
+            Self::Chord(chord) => chord.graces.push(grace),
+            x => {
+                return Err(format!(
+                    "Can not attach grace notes to {:?}",
+                    x
+                ))
+            }
+        }
+        Ok(())
+    }
+    /// Remove grace notation from the event, if it has one.
+    pub fn take_grace_notation(&mut self) -> Option<GraceType> {
+        let notations = match self {
+            Self::Note(note) => &mut note.chord_notations,
+            Self::Chord(chord) => &mut chord.chord_notations,
+            _ => return None,
+        };
+        let idx = notations
+            .iter()
+            .position(|n| matches!(n, ChordNotations::Grace(_)))?;
+        match notations.remove(idx) {
+            ChordNotations::Grace(grace_type) => Some(grace_type),
+            _ => None,
+        }
+    }
     pub fn render_lilypond(
         &self,
         length_string: String,
         settings: &RenderSettings,
-    ) -> String {
+    ) -> Result<String, RenderError> {
         match self {
-            Self::Rest => format!("r{}", length_string),
+            Self::Rest => Ok(format!("r{}", length_string)),
             Self::Note(note) => {
-                note.render_lilypond(length_string, settings)
+                Ok(note.render_lilypond(length_string, settings))
             }
             Self::Chord(chord) => {
-                chord.render_lilypond(length_string, settings)
+                Ok(chord.render_lilypond(length_string, settings))
             }
             Self::Tuplet(tuplet) => {
                 tuplet.render_lilypond(length_string, settings)
@@ -488,21 +626,45 @@ impl Default for EventType {
 pub struct Note {
     pub pitch: Pitch,
     tie: bool,
+    /// MIDI velocity of note attack. None for notes, continuing
+    /// the tied ones.
+    velocity: Option<u8>,
     notations: Vec<NoteNotations>,
     chord_notations: Vec<ChordNotations>,
+    graces: Vec<GraceGroup>,
 }
 impl Note {
     pub fn new(pitch: Pitch) -> Self {
         Self {
             pitch,
             tie: false,
+            velocity: None,
             notations: Vec::new(),
             chord_notations: Vec::new(),
+            graces: Vec::new(),
         }
     }
+    pub fn set_velocity(&mut self, velocity: u8) {
+        self.velocity = Some(velocity);
+    }
+    pub fn velocity(&self) -> Option<u8> {
+        self.velocity
+    }
     pub fn set_tie(&mut self, tie: bool) {
         self.tie = tie;
     }
+    pub fn tie(&self) -> bool {
+        self.tie
+    }
+    pub fn notations(&self) -> &Vec<NoteNotations> {
+        &self.notations
+    }
+    pub fn chord_notations(&self) -> &Vec<ChordNotations> {
+        &self.chord_notations
+    }
+    pub fn graces(&self) -> &Vec<GraceGroup> {
+        &self.graces
+    }
     fn notation_error(
         &self,
         notation: NotationType,
@@ -531,9 +693,9 @@ impl Note {
                 self.chord_notations.push(n);
                 Ok(())
             }
-            NotationType::Event => {
-                Err(self.notation_error(notation))
-            }
+            NotationType::Measure(_)
+            | NotationType::Track(_)
+            | NotationType::Event => Err(self.notation_error(notation)),
         }
     }
     pub fn remove_head_notations(&mut self) {
@@ -549,6 +711,7 @@ impl Note {
             .filter(|nt| !nt.is_head())
             .map(|nt| nt.clone())
             .collect();
+        remove_head_graces(&mut self.graces);
     }
     pub fn remove_tail_notations(&mut self) {
         self.notations = self
@@ -563,6 +726,7 @@ impl Note {
             .filter(|nt| !nt.is_tail())
             .map(|nt| nt.clone())
             .collect();
+        remove_tail_graces(&mut self.graces);
     }
     pub fn render_lilypond(
         &self,
@@ -591,14 +755,82 @@ impl Note {
         let pitch = format!("{pitch}{length_string}");
         let s =
             self.notations.iter().fold(pitch, |p, n| n.render(p));
-        let s =
-            self.chord_notations.iter().fold(s, |p, n| n.render(p));
+        let s = rendered_chord_notations(&self.chord_notations)
+            .fold(s, |p, n| n.render(p));
         let s = match self.tie {
             true => format!("{}~", s),
             false => s,
         };
-
-        s
+        render_with_graces(&self.graces, s, settings)
+    }
+}
+impl RendersToMusicXml for Note {
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        with_graces_musicxml(&self.graces, ctx, |ctx| {
+            self.render_note_musicxml(ctx)
+        })
+    }
+}
+impl Note {
+    /// Render the note itself, or the grace note, if
+    /// [MusicXmlContext::grace] is set.
+    fn render_note_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let midi = self.pitch.midi();
+        let mut ties = String::new();
+        let mut notations = Vec::new();
+        let grace = ctx.grace.map(|grace| grace.musicxml());
+        if grace.is_none() && ctx.tied_from_previous(midi) {
+            ties.push_str(r#"<tie type="stop"/>"#);
+            notations.push(r#"<tied type="stop"/>"#.to_string());
+        }
+        if grace.is_none() && self.tie {
+            ties.push_str(r#"<tie type="start"/>"#);
+            notations.push(r#"<tied type="start"/>"#.to_string());
+            ctx.tie_to_next(midi);
+        }
+        if grace.is_none() && !ctx.chord_member {
+            notations.extend(ctx.tuplet_notations());
+        }
+        notations.extend(
+            self.chord_notations.iter().filter_map(|n| n.musicxml()),
+        );
+        let notehead = self
+            .notations
+            .iter()
+            .filter_map(|n| match n {
+                NoteNotations::NoteHead(head) => Some(head.musicxml()),
+                _ => None,
+            })
+            .last()
+            .unwrap_or_default();
+        // grace notes take no duration.
+        let (duration, time_modification) = match grace {
+            Some(_) => (String::new(), String::new()),
+            None => (
+                format!("<duration>{}</duration>", ctx.duration(&ctx.length)),
+                ctx.time_modification(),
+            ),
+        };
+        Ok(format!(
+            "<note>{}{}{}{duration}{ties}<voice>{}</voice>\
+            {}{time_modification}{notehead}<staff>{}</staff>{}</note>",
+            grace.unwrap_or_default(),
+            match ctx.chord_member {
+                true => "<chord/>",
+                false => "",
+            },
+            pitch_elements(&self.pitch, &ctx.settings)?,
+            ctx.voice,
+            note_type_elements(&ctx.length),
+            ctx.staff,
+            notations_element(notations),
+        ))
     }
 }
 impl PartialOrd for Note {
@@ -619,14 +851,22 @@ impl PartialOrd for Note {
 pub struct Chord {
     notes: Vec<Note>,
     chord_notations: Vec<ChordNotations>,
+    graces: Vec<GraceGroup>,
 }
 impl Chord {
     pub fn new() -> Self {
         Self {
             notes: Vec::new(),
             chord_notations: Vec::new(),
+            graces: Vec::new(),
         }
     }
+    pub fn notes(&self) -> &Vec<Note> {
+        &self.notes
+    }
+    pub fn chord_notations(&self) -> &Vec<ChordNotations> {
+        &self.chord_notations
+    }
     fn grab_chord_notations(
         &mut self,
         notations: &mut Vec<ChordNotations>,
@@ -649,6 +889,7 @@ impl Chord {
             )),
             EventType::Note(mut note) => {
                 self.grab_chord_notations(&mut note.chord_notations);
+                self.graces.append(&mut note.graces);
                 self.notes.push(note);
                 Ok(self)
             }
@@ -656,6 +897,7 @@ impl Chord {
                 self.grab_chord_notations(
                     &mut chord.chord_notations,
                 );
+                self.graces.append(&mut chord.graces);
                 self.notes.append(&mut chord.notes);
                 Ok(self)
             }
@@ -668,6 +910,10 @@ impl Chord {
     pub fn set_ties(&mut self, tie: bool) {
         self.notes.iter_mut().map(|n| n.set_tie(tie)).count();
     }
+    /// The loudest attack of the chord notes.
+    pub fn velocity(&self) -> Option<u8> {
+        self.notes.iter().filter_map(|n| n.velocity).max()
+    }
     pub fn remove_head_notations(&mut self) {
         self.chord_notations = self
             .chord_notations
@@ -675,6 +921,7 @@ impl Chord {
             .filter(|nt| !nt.is_head())
             .map(|nt| nt.clone())
             .collect();
+        remove_head_graces(&mut self.graces);
     }
     pub fn remove_tail_notations(&mut self) {
         self.chord_notations = self
@@ -683,6 +930,7 @@ impl Chord {
             .filter(|nt| !nt.is_tail())
             .map(|nt| nt.clone())
             .collect();
+        remove_tail_graces(&mut self.graces);
     }
     pub fn apply_notation(
         &mut self,
@@ -695,7 +943,19 @@ impl Chord {
                 Ok(())
             }
             ChordNotations::TupletRate(_) => Ok(()),
-            ChordNotations::TupletEnd => Ok(()),
+            ChordNotations::TupletEnd(_) => Ok(()),
+            // every pedal change counts, even if it repeats.
+            notation @ (ChordNotations::PedalOn(_)
+            | ChordNotations::PedalOff(_)) => {
+                self.chord_notations.push(notation);
+                Ok(())
+            }
+            notation => {
+                if !self.chord_notations.contains(&notation) {
+                    self.chord_notations.push(notation);
+                }
+                Ok(())
+            }
         }
     }
 
@@ -711,9 +971,47 @@ impl Chord {
             .collect::<Vec<_>>();
         let s =
             format!("< {} >{length_string}", note_string.join(" "));
-        let s =
-            self.chord_notations.iter().fold(s, |p, n| n.render(p));
-        s
+        let s = rendered_chord_notations(&self.chord_notations)
+            .fold(s, |p, n| n.render(p));
+        render_with_graces(&self.graces, s, settings)
+    }
+}
+
+impl RendersToMusicXml for Chord {
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        with_graces_musicxml(&self.graces, ctx, |ctx| {
+            self.render_notes_musicxml(ctx)
+        })
+    }
+}
+impl Chord {
+    fn render_notes_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let notes = self
+            .notes
+            .iter()
+            .enumerate()
+            .map(|(idx, note)| {
+                ctx.chord_member = idx > 0;
+                match idx {
+                    // chord notations are written once, on the first note
+                    0 => {
+                        let mut note = note.clone();
+                        note.chord_notations =
+                            self.chord_notations.clone();
+                        note.render_musicxml(ctx)
+                    }
+                    _ => note.render_musicxml(ctx),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>();
+        ctx.chord_member = false;
+        Ok(notes?.join("\n"))
     }
 }
 
@@ -751,6 +1049,10 @@ impl Tuplet {
         }
     }
 
+    pub fn rate(&self) -> Fraction {
+        self.rate
+    }
+
     pub fn push(&mut self, event: EventInfo) -> Result<(), String> {
         self.set_end_position(event.end_position());
         let event = self.apply_rate_to_event(event);
@@ -762,28 +1064,99 @@ impl Tuplet {
         Ok(())
     }
 
+    /// Split tuplet at the position from its start (measured outside
+    /// of the tuplet), and return the second part with the same rate.
+    ///
+    /// Event, sounding at the position, is split and tied.
+    pub fn cut_head(
+        &mut self,
+        position: Fraction,
+    ) -> Result<Self, String> {
+        let written = position * self.rate;
+        if limit_denominator(written, LIMIT_DENOMINATOR)? != written
+        {
+            return Err(format!(
+                "Can not split tuplet {} at {}: the split does not fall \
+                on a note value inside the tuplet.",
+                self.rate, position
+            ));
+        }
+        let container = self.container.cut_head_at(written)?;
+        let mut head_position = self.position.clone();
+        head_position
+            .set_position(head_position.position() + position);
+        let head = Self {
+            rate: self.rate,
+            length: Length::from(
+                container.length().get() / self.rate,
+            ),
+            container,
+            position: head_position,
+        };
+        self.length = Length::from(position);
+        Ok(head)
+    }
+
+    /// Push event, which position and length are already in the
+    /// tuplet time, e.g. closed nested tuplet.
+    pub fn push_scaled(
+        &mut self,
+        event: EventInfo,
+    ) -> Result<(), String> {
+        let end = event.end_position();
+        if end.position() > self.container.length().get() {
+            self.length = Length::from(end.position() / self.rate);
+            self.container_mut().set_end_position(end);
+        }
+        self.container.insert(event)?;
+        Ok(())
+    }
+
+    /// Event, moved from the time outside of the tuplet to the tuplet
+    /// time, without quantization.
+    ///
+    /// Used to pass events through outer tuplets to the nested one.
+    pub fn scale_event(&self, mut event: EventInfo) -> EventInfo {
+        let position = (event.position.position()
+            - self.position.position())
+            * self.rate;
+        event.position.set_position(position);
+        event.set_length(Length::from(
+            event.length.get() * self.rate,
+        ));
+        event
+    }
+
+    /// Stretch tuplet contents by factor, e.g. when the tuplet is
+    /// nested into another one.
+    pub fn scale(&mut self, factor: Fraction) {
+        self.container.scale(factor);
+    }
+
     pub fn render_lilypond(
         &self,
         _length_string: String,
-        _settings: &RenderSettings,
-    ) -> String {
-        format!(
+        settings: &RenderSettings,
+    ) -> Result<String, RenderError> {
+        let events = self
+            .container
+            .events()
+            .iter()
+            .flat_map(|ev| ev.with_normalized_length())
+            .map(|ev| ev.render_lilypond_with(settings))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!(
             "\\tuplet {}/{} {{ {} }}",
             self.rate.numer().expect("Can not get rate numerator"),
             self.rate.denom().expect("Can not get rate denominator"),
-            self.container
-                .events()
-                .iter()
-                .map(|ev| {
-                    let evts = ev.with_normalized_length();
-                    evts.iter()
-                        .map(|ev1| ev1.render_lilypond())
-                        .join(" ")
-                })
-                .join(" ")
-        )
+            events.join(" ")
+        ))
     }
 
+    /// Move events to the tuplet time.
+    ///
+    /// Events are expected to be snapped to the grid of the tuplet
+    /// already, see [EventInfo::quantize_from].
     fn apply_rate(
         rate: Fraction,
         events: impl IntoIterator<Item = EventInfo>,
@@ -791,10 +1164,9 @@ impl Tuplet {
         // println!("apply_rate");
         let mut events = events.into_iter();
         let mut frst = events.next().expect("No first event");
-        let quantize = 256;
         let container_position = frst.position.clone();
-        let frst_pos = frst.position.position_quantized_to(quantize);
-        let length = frst.length.get_quantized_to(quantize);
+        let frst_pos = frst.position.position();
+        let length = frst.length.get();
         frst.length =
             limit_denominator(length * rate, LIMIT_DENOMINATOR)
                 .expect("Can not quantize first tuplet event length")
@@ -808,8 +1180,7 @@ impl Tuplet {
         // println!("--- first: {:#?}", frst);
         result.push_back(frst);
         for mut event in events {
-            let position =
-                event.position.position_quantized_to(quantize);
+            let position = event.position.position();
             let position = limit_denominator(
                 (position - frst_pos) * rate,
                 LIMIT_DENOMINATOR,
@@ -818,7 +1189,7 @@ impl Tuplet {
             event.position.set_position(position);
 
             let length = limit_denominator(
-                event.length.get_quantized_to(quantize) * rate,
+                event.length.get() * rate,
                 LIMIT_DENOMINATOR,
             )
             .expect("Can not quantize new event length");
@@ -880,3 +1251,55 @@ impl Tuplet {
         &mut self.container
     }
 }
+impl RendersToMusicXml for Tuplet {
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let events = self
+            .container
+            .events()
+            .iter()
+            .flat_map(|ev| ev.with_normalized_length())
+            .collect::<Vec<_>>();
+        let last = match events.len().checked_sub(1) {
+            None => return Ok(String::new()),
+            Some(last) => last,
+        };
+        let outer = ctx.tuplet.take();
+        let number =
+            outer.as_ref().map_or(1, |outer| outer.number + 1);
+        let rate = outer
+            .as_ref()
+            .map_or(self.rate, |outer| outer.rate * self.rate);
+        let rendered = events
+            .iter()
+            .enumerate()
+            .map(|(idx, ev)| {
+                let mut start = Vec::new();
+                let mut stop = Vec::new();
+                if idx == 0 {
+                    if let Some(outer) = &outer {
+                        start.extend(outer.start.iter());
+                    }
+                    start.push(number);
+                }
+                if idx == last {
+                    stop.push(number);
+                    if let Some(outer) = &outer {
+                        stop.extend(outer.stop.iter());
+                    }
+                }
+                ctx.tuplet = Some(TupletState {
+                    rate,
+                    start,
+                    stop,
+                    number,
+                });
+                ev.render_musicxml(ctx)
+            })
+            .collect::<Result<Vec<_>, _>>();
+        ctx.tuplet = outer;
+        Ok(rendered?.join("\n"))
+    }
+}
@@ -0,0 +1,255 @@
+//! Grace notes, that do not take time in the measure.
+//!
+//! They are lifted out of the timeline while parsing and attached to
+//! the note or chord, they are played with.
+use std::str::FromStr;
+
+use fraction::Fraction;
+use itertools::Itertools;
+
+use crate::{
+    lilypond_render::{RenderError, RenderSettings},
+    musicxml_render::{MusicXmlContext, RendersToMusicXml},
+    notation::NotationError,
+};
+
+use super::{EventType, Length};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GraceType {
+    Grace,
+    /// Slashed grace note.
+    Acciaccatura,
+    Appoggiatura,
+    /// Grace notes at the end of the main note.
+    AfterGrace,
+}
+impl GraceType {
+    pub fn lilypond(&self) -> &'static str {
+        match self {
+            Self::Grace => r"\grace",
+            Self::Acciaccatura => r"\acciaccatura",
+            Self::Appoggiatura => r"\appoggiatura",
+            Self::AfterGrace => r"\afterGrace",
+        }
+    }
+    /// If grace notes are attached to the previous event, not to the
+    /// following one.
+    pub fn is_after(&self) -> bool {
+        *self == Self::AfterGrace
+    }
+    /// `<grace>` element of MusicXML note.
+    pub fn musicxml(&self) -> &'static str {
+        match self {
+            Self::Acciaccatura => r#"<grace slash="yes"/>"#,
+            _ => "<grace/>",
+        }
+    }
+}
+impl ToString for GraceType {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Grace => "grace",
+            Self::Acciaccatura => "acciaccatura",
+            Self::Appoggiatura => "appoggiatura",
+            Self::AfterGrace => "after_grace",
+        }
+        .to_string()
+    }
+}
+impl FromStr for GraceType {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "grace" => Ok(Self::Grace),
+            "acciaccatura" | "acc" => Ok(Self::Acciaccatura),
+            "appoggiatura" | "app" => Ok(Self::Appoggiatura),
+            "after_grace" | "after" => Ok(Self::AfterGrace),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+
+/// Notes (or chords) of one grace group.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GraceGroup {
+    pub grace_type: GraceType,
+    pub events: Vec<EventType>,
+}
+impl GraceGroup {
+    pub fn new(grace_type: GraceType, events: Vec<EventType>) -> Self {
+        Self { grace_type, events }
+    }
+
+    /// Render the main event together with grace notes.
+    ///
+    /// Single grace note is written as eighth, groups as sixteenths,
+    /// regardless of recorded lengths.
+    pub fn render_lilypond(
+        &self,
+        main: String,
+        settings: &RenderSettings,
+    ) -> String {
+        let length = match self.events.len() {
+            1 => "8",
+            _ => "16",
+        };
+        // grace groups hold only notes and chords.
+        let notes = self
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                EventType::Note(note) => {
+                    Some(note.render_lilypond(length.to_string(), settings))
+                }
+                EventType::Chord(chord) => {
+                    Some(chord.render_lilypond(length.to_string(), settings))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        match self.grace_type {
+            GraceType::AfterGrace => {
+                format!("{} {main} {{ {notes} }}", self.grace_type.lilypond())
+            }
+            tp => format!("{} {{ {notes} }} {main}", tp.lilypond()),
+        }
+    }
+}
+impl RendersToMusicXml for GraceGroup {
+    /// Render grace notes, which take no duration, with the same
+    /// types, as in LilyPond.
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let (length, chord_member) = (ctx.length.clone(), ctx.chord_member);
+        ctx.grace = Some(self.grace_type);
+        ctx.chord_member = false;
+        ctx.length = Length::from(match self.events.len() {
+            1 => Fraction::new(1_u64, 8_u64),
+            _ => Fraction::new(1_u64, 16_u64),
+        });
+        let rendered = self
+            .events
+            .iter()
+            .map(|event| match event {
+                EventType::Note(note) => note.render_musicxml(ctx),
+                EventType::Chord(chord) => chord.render_musicxml(ctx),
+                _ => Ok(String::new()),
+            })
+            .collect::<Result<Vec<_>, _>>();
+        ctx.grace = None;
+        (ctx.length, ctx.chord_member) = (length, chord_member);
+        Ok(rendered?.join("\n"))
+    }
+}
+
+/// Render the main event of LilyPond with its grace groups:
+/// after-graces wrap the event, other graces precede it.
+pub fn render_with_graces(
+    graces: &[GraceGroup],
+    main: String,
+    settings: &RenderSettings,
+) -> String {
+    graces
+        .iter()
+        .sorted_by_key(|grace| !grace.grace_type.is_after())
+        .fold(main, |main, grace| grace.render_lilypond(main, settings))
+}
+
+/// Drop graces, played before the event, as it is cut from its head.
+pub fn remove_head_graces(graces: &mut Vec<GraceGroup>) {
+    graces.retain(|grace| grace.grace_type.is_after());
+}
+
+/// Drop after-graces, as the event is cut from its tail.
+pub fn remove_tail_graces(graces: &mut Vec<GraceGroup>) {
+    graces.retain(|grace| !grace.grace_type.is_after());
+}
+
+/// Render the main event of MusicXML between its grace notes.
+pub fn with_graces_musicxml(
+    graces: &[GraceGroup],
+    ctx: &mut MusicXmlContext,
+    main: impl FnOnce(&mut MusicXmlContext) -> Result<String, RenderError>,
+) -> Result<String, RenderError> {
+    let mut rendered = Vec::new();
+    for grace in graces.iter().filter(|g| !g.grace_type.is_after()) {
+        rendered.push(grace.render_musicxml(ctx)?);
+    }
+    rendered.push(main(ctx)?);
+    for grace in graces.iter().filter(|g| g.grace_type.is_after()) {
+        rendered.push(grace.render_musicxml(ctx)?);
+    }
+    Ok(rendered.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+
+    use crate::{
+        lilypond_render::RendersToLilypond,
+        musicxml_render::{MusicXmlContext, RendersToMusicXml, DIVISIONS},
+        primitives::{
+            EventInfo, EventType, Length, Note, Pitch, RelativePosition,
+        },
+    };
+
+    use super::{render_with_graces, GraceGroup, GraceType};
+
+    #[test]
+    fn test_grace_group() {
+        let settings = EventInfo::global_render_settings();
+        let note = |midi| {
+            EventType::Note(Note::new(Pitch::from_midi(midi, None, None)))
+        };
+        let acc = GraceGroup::new(GraceType::Acciaccatura, vec![note(62)]);
+        assert_eq!(
+            acc.render_lilypond("c'4".to_string(), &settings),
+            r"\acciaccatura { d'8 } c'4"
+        );
+        let after =
+            GraceGroup::new(GraceType::AfterGrace, vec![note(62), note(64)]);
+        assert_eq!(
+            after.render_lilypond("c'2".to_string(), &settings),
+            r"\afterGrace c'2 { d'16 e'16 }"
+        );
+        assert_eq!(
+            render_with_graces(
+                &[after, acc.clone()],
+                "c'2".to_string(),
+                &settings
+            ),
+            r"\acciaccatura { d'8 } \afterGrace c'2 { d'16 e'16 }"
+        );
+        assert_eq!(
+            "app".parse::<GraceType>().unwrap(),
+            GraceType::Appoggiatura
+        );
+
+        let mut main = note(60);
+        main.push_grace(acc).unwrap();
+        let mut ctx = MusicXmlContext::new(settings);
+        let xml = EventInfo::new(
+            RelativePosition::new(1, Fraction::from(0)),
+            Length::from(Fraction::new(1_u64, 4_u64)),
+            main,
+        )
+        .render_musicxml(&mut ctx)
+        .unwrap();
+        let notes = xml.split('\n').collect::<Vec<_>>();
+        assert_eq!(notes.len(), 2);
+        assert!(notes[0].starts_with(r#"<note><grace slash="yes"/><pitch>"#));
+        assert!(notes[0].contains("<step>D</step>"));
+        assert!(notes[0].contains("<type>eighth</type>"));
+        assert!(!notes[0].contains("<duration>"));
+        assert!(notes[1].contains("<step>C</step>"));
+        assert!(
+            notes[1].contains(&format!("<duration>{DIVISIONS}</duration>"))
+        );
+        assert_eq!(ctx.written, DIVISIONS);
+    }
+}
@@ -0,0 +1,207 @@
+//! Key signature, that can change from measure to measure.
+use std::{error::Error, str::FromStr};
+
+use crate::{
+    lilypond_render::{RenderError, RendersToLilypond},
+    musicxml_render::{MusicXmlContext, RendersToMusicXml},
+};
+
+use super::{Accidental, Key, NoteName, Scale};
+
+/// Note names in the order of the circle of fifths, starting from F.
+const FIFTHS: [NoteName; 7] = [
+    NoteName::F,
+    NoteName::C,
+    NoteName::G,
+    NoteName::D,
+    NoteName::A,
+    NoteName::E,
+    NoteName::B,
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KeyMode {
+    Major,
+    Minor,
+}
+
+/// Key signature, written as `Eb-major`, `f#-minor` or just `D`.
+///
+/// Unlike [Key], it can be rendered and compared, and is used
+/// to resolve pitches in measures, where it is in force.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct KeySignature {
+    pub tonic: NoteName,
+    /// -1 for flat, 1 for sharp, 0 for natural.
+    pub alteration: i8,
+    pub mode: KeyMode,
+}
+impl KeySignature {
+    pub fn new(tonic: NoteName, alteration: i8, mode: KeyMode) -> Self {
+        Self {
+            tonic,
+            alteration,
+            mode,
+        }
+    }
+
+    /// Build from the amount of sharps (positive) or flats (negative),
+    /// as stored in MIDI files.
+    pub fn from_fifths(fifths: i8, mode: KeyMode) -> Self {
+        let value = match mode {
+            KeyMode::Major => fifths as i32,
+            KeyMode::Minor => fifths as i32 + 3,
+        } + 1;
+        Self::new(
+            FIFTHS[value.rem_euclid(7) as usize],
+            value.div_euclid(7) as i8,
+            mode,
+        )
+    }
+
+    /// Amount of sharps (positive) or flats (negative).
+    pub fn fifths(&self) -> i8 {
+        let tonic = FIFTHS
+            .iter()
+            .position(|name| *name == self.tonic)
+            .expect("Every note name is in the circle of fifths")
+            as i8
+            - 1;
+        let fifths = tonic + self.alteration * 7;
+        match self.mode {
+            KeyMode::Major => fifths,
+            KeyMode::Minor => fifths - 3,
+        }
+    }
+
+    /// Key, pitches are resolved with.
+    pub fn key(&self) -> Key {
+        let accidental = match self.alteration {
+            x if x < 0 => Accidental::Flat,
+            x if x > 0 => Accidental::Sharp,
+            _ => Accidental::White,
+        };
+        let scale = match self.mode {
+            KeyMode::Major => Scale::Major,
+            KeyMode::Minor => Scale::Minor,
+        };
+        Key::new(self.tonic, accidental, scale)
+    }
+
+    fn tonic_letter(&self) -> &'static str {
+        match self.tonic {
+            NoteName::C => "c",
+            NoteName::D => "d",
+            NoteName::E => "e",
+            NoteName::F => "f",
+            NoteName::G => "g",
+            NoteName::A => "a",
+            NoteName::B => "b",
+        }
+    }
+}
+impl ToString for KeySignature {
+    fn to_string(&self) -> String {
+        let alteration = match self.alteration {
+            -1 => "b",
+            1 => "#",
+            _ => "",
+        };
+        let mode = match self.mode {
+            KeyMode::Major => "major",
+            KeyMode::Minor => "minor",
+        };
+        format!("{}{alteration}-{mode}", self.tonic_letter().to_uppercase())
+    }
+}
+impl FromStr for KeySignature {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tonic, mode) = match s.split_once('-') {
+            None => (s, "major"),
+            Some(split) => split,
+        };
+        let mode = match mode.to_lowercase().as_str() {
+            "major" | "maj" => KeyMode::Major,
+            "minor" | "min" => KeyMode::Minor,
+            x => return Err(format!("Unknown key mode: {x}").into()),
+        };
+        let mut chars = tonic.chars();
+        let tonic = match chars.next().map(|ch| ch.to_ascii_lowercase()) {
+            Some('c') => NoteName::C,
+            Some('d') => NoteName::D,
+            Some('e') => NoteName::E,
+            Some('f') => NoteName::F,
+            Some('g') => NoteName::G,
+            Some('a') => NoteName::A,
+            Some('b') => NoteName::B,
+            _ => return Err(format!("Can not parse key: {s}").into()),
+        };
+        let alteration = match chars.as_str() {
+            "" => 0,
+            "b" | "es" | "s" => -1,
+            "#" | "is" => 1,
+            x => return Err(format!("Unknown key accidental: {x}").into()),
+        };
+        Ok(Self::new(tonic, alteration, mode))
+    }
+}
+impl RendersToLilypond for KeySignature {
+    fn render_lilypond(&self) -> String {
+        let alteration = match (self.tonic, self.alteration) {
+            (NoteName::E, -1) | (NoteName::A, -1) => "s",
+            (_, -1) => "es",
+            (_, 1) => "is",
+            _ => "",
+        };
+        let mode = match self.mode {
+            KeyMode::Major => r"\major",
+            KeyMode::Minor => r"\minor",
+        };
+        format!(r"\key {}{alteration} {mode}", self.tonic_letter())
+    }
+}
+impl RendersToMusicXml for KeySignature {
+    fn render_musicxml(
+        &self,
+        _ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        let mode = match self.mode {
+            KeyMode::Major => "major",
+            KeyMode::Minor => "minor",
+        };
+        Ok(format!(
+            "<key><fifths>{}</fifths><mode>{mode}</mode></key>",
+            self.fifths()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lilypond_render::RendersToLilypond, primitives::NoteName};
+
+    use super::{KeyMode, KeySignature};
+
+    #[test]
+    fn test_key_signature() {
+        let es: KeySignature = "Eb-major".parse().unwrap();
+        assert_eq!(es, KeySignature::new(NoteName::E, -1, KeyMode::Major));
+        assert_eq!(es.fifths(), -3);
+        assert_eq!(es.render_lilypond(), r"\key es \major");
+        assert_eq!(es.to_string(), "Eb-major");
+        assert_eq!(KeySignature::from_fifths(-3, KeyMode::Major), es);
+
+        let fis: KeySignature = "f#-minor".parse().unwrap();
+        assert_eq!(fis.fifths(), 3);
+        assert_eq!(KeySignature::from_fifths(3, KeyMode::Minor), fis);
+        assert_eq!(fis.render_lilypond(), r"\key fis \minor");
+
+        assert_eq!(
+            KeySignature::from_fifths(0, KeyMode::Minor),
+            "a-minor".parse().unwrap()
+        );
+        assert!("h-major".parse::<KeySignature>().is_err());
+    }
+}
@@ -1,14 +1,21 @@
-use std::ops::{Add, Sub};
+use std::{
+    collections::VecDeque,
+    iter::repeat,
+    ops::{Add, Sub},
+};
 
 use fraction::Fraction;
 use rea_rs::{Position, Reaper, TimeSignature};
 
-use crate::lilypond_render::RendersToLilypond;
+use crate::lilypond_render::RenderError;
 
 use super::{
-    limit_denominator, normalize_fraction, LIMIT_DENOMINATOR,
+    limit_denominator, normalize_fraction, Quantization, LIMIT_DENOMINATOR,
 };
 
+/// The most dots, length can be rendered with.
+pub const MAX_DOTS: u32 = 3;
+
 #[derive(Debug, PartialOrd, Clone)]
 pub struct Length {
     fraction: Fraction,
@@ -23,6 +30,73 @@ impl Length {
     pub fn get_quantized_to(&self, denom: u64) -> Fraction {
         limit_denominator(self.fraction, denom).unwrap()
     }
+    /// Quantize to the grid of settings.
+    pub fn quantized_by(&self, quantization: &Quantization) -> Fraction {
+        quantization.quantize_length(self.fraction)
+    }
+
+    /// Base note value and amount of dots, if length can be written
+    /// as a single duration: plain or dotted (up to [MAX_DOTS] dots)
+    /// note value, breve or longa.
+    pub fn single_duration(&self) -> Option<(Fraction, usize)> {
+        let frac = self.get_quantized();
+        for dots in 0..=MAX_DOTS {
+            // every dot adds half of the previous value:
+            // length = base * (2^(dots+1) - 1) / 2^dots
+            let base = frac
+                * Fraction::new(
+                    2_u64.pow(dots),
+                    2_u64.pow(dots + 1) - 1,
+                );
+            let (num, denom) = match (base.numer(), base.denom()) {
+                (Some(num), Some(denom)) => (*num, *denom),
+                _ => break,
+            };
+            match (num, denom) {
+                (4, 1) | (2, 1) => (),
+                (1, d) if d.is_power_of_two() => (),
+                _ => continue,
+            }
+            return Some((base, dots as usize));
+        }
+        None
+    }
+
+    /// Render as a single LilyPond duration: plain or dotted (up to
+    /// three dots) note value, breve or longa.
+    ///
+    /// Err, if length can not be written without ties. Such events
+    /// are split by
+    /// [EventInfo::render_lilypond_with](super::EventInfo::render_lilypond_with).
+    pub fn try_render_lilypond(&self) -> Result<String, RenderError> {
+        let (base, dots) = self.single_duration().ok_or_else(|| {
+            RenderError::Length(self.get_quantized().to_string())
+        })?;
+        let value = match (base.numer(), base.denom()) {
+            (Some(&4), Some(&1)) => r"\longa".to_string(),
+            (Some(&2), Some(&1)) => r"\breve".to_string(),
+            (_, Some(denom)) => denom.to_string(),
+            _ => return Err(RenderError::Length(base.to_string())),
+        };
+        Ok(format!("{}{}", value, ".".repeat(dots)))
+    }
+
+    /// Split length by pieces, as [normalize_fraction] does, from the
+    /// smallest to the largest.
+    ///
+    /// Lengths, longer than longa, are split by longas first.
+    pub fn normalized(&self) -> VecDeque<Fraction> {
+        let longa = Fraction::from(4);
+        let mut frac = self.get_quantized();
+        let mut longas = 0;
+        while frac > longa {
+            frac -= longa;
+            longas += 1;
+        }
+        let mut pieces = normalize_fraction(frac, VecDeque::new());
+        pieces.extend(repeat(longa).take(longas));
+        pieces
+    }
 }
 impl PartialEq for Length {
     fn eq(&self, other: &Self) -> bool {
@@ -82,27 +156,14 @@ impl Sub for Length {
     }
     type Output = Self;
 }
-impl RendersToLilypond for Length {
-    fn render_lilypond(&self) -> String {
-        match self.get_quantized().numer().unwrap() {
-            1_u64 => format!(
-                "{}",
-                self.get_quantized().denom().expect("No Denominator in Length")
-            ),
-            3_u64 => match *self.get_quantized().denom().expect("No Denominator in Length"){
-                    x if x >1=>format!("{}.",x/2),
-                    _ => "\\breve.".to_string()
-                },
-            _ => panic!("Invalid Length to render: {:?}. What happens if normalize it? : {:?}", self, normalize_fraction(self.get_quantized(), Vec::new().into())),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use fraction::Fraction;
 
-    use crate::primitives::Length;
+    use crate::{
+        lilypond_render::RendersToLilypond,
+        primitives::{EventInfo, EventType, Length, RelativePosition},
+    };
 
     #[test]
     fn length() {
@@ -120,6 +181,38 @@ mod tests {
         );
     }
     #[test]
+    fn render_length() {
+        let render = |num: u64, denom: u64| {
+            Length::from(Fraction::new(num, denom))
+                .try_render_lilypond()
+        };
+        assert_eq!(render(1, 4), Ok("4".to_string()));
+        assert_eq!(render(3, 8), Ok("4.".to_string()));
+        assert_eq!(render(7, 8), Ok("2..".to_string()));
+        assert_eq!(render(15, 16), Ok("2...".to_string()));
+        assert_eq!(render(2, 1), Ok(r"\breve".to_string()));
+        assert_eq!(render(3, 1), Ok(r"\breve.".to_string()));
+        assert_eq!(render(4, 1), Ok(r"\longa".to_string()));
+        assert_eq!(render(6, 1), Ok(r"\longa.".to_string()));
+        assert!(render(5, 8).is_err());
+        assert!(render(9, 1).is_err());
+        // events, not lengths, are split and tied.
+        let rest = EventInfo::new(
+            RelativePosition::new(1, Fraction::from(0)),
+            Length::from(Fraction::new(5_u64, 8_u64)),
+            EventType::Rest,
+        );
+        assert_eq!(rest.render_lilypond(), "r2 r8");
+        assert_eq!(
+            Length::from(9.0).normalized(),
+            vec![
+                Fraction::from(1),
+                Fraction::from(4),
+                Fraction::from(4)
+            ]
+        );
+    }
+    #[test]
     #[should_panic]
     fn length_negative_sub() {
         let _ = Length::from(1.0) - Length::from(2.0);
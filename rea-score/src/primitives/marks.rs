@@ -0,0 +1,244 @@
+//! Song structure of measures: rehearsal marks from project markers,
+//! section labels from regions, barlines, repeats, alternative
+//! endings, navigation marks and line breaks.
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::notation::NotationError;
+
+/// How project markers are written as rehearsal marks.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(Serialize, Deserialize)]
+pub enum RehearsalMarks {
+    /// Sequential letters: `\mark \default`.
+    #[default]
+    Letters,
+    /// Marker name in the box.
+    Text,
+}
+impl RehearsalMarks {
+    pub fn lilypond(&self, name: &str) -> String {
+        match self {
+            Self::Letters => r"\mark \default".to_string(),
+            Self::Text => {
+                format!(r#"\mark \markup \box "{}""#, escape_quotes(name))
+            }
+        }
+    }
+}
+
+/// Barline, written by `\bar`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Barline {
+    Double,
+    Final,
+    Dashed,
+}
+impl Barline {
+    pub fn lilypond(&self) -> String {
+        let glyph = match self {
+            Self::Double => "||",
+            Self::Final => "|.",
+            Self::Dashed => "!",
+        };
+        format!(r#"\bar "{glyph}""#)
+    }
+}
+impl ToString for Barline {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Double => "double",
+            Self::Final => "final",
+            Self::Dashed => "dashed",
+        }
+        .to_string()
+    }
+}
+impl FromStr for Barline {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "double" => Ok(Self::Double),
+            "final" => Ok(Self::Final),
+            "dashed" => Ok(Self::Dashed),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+
+/// Navigation marks. Segno and coda are written at the start of
+/// measure, the others at its end.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Jump {
+    Segno,
+    Coda,
+    ToCoda,
+    Fine,
+    DaCapo,
+    DaCapoAlFine,
+    DalSegno,
+    DalSegnoAlCoda,
+}
+impl Jump {
+    pub fn is_at_start(&self) -> bool {
+        matches!(self, Self::Segno | Self::Coda)
+    }
+    pub fn lilypond(&self) -> String {
+        let jump = |text: &str| format!(r#"\jump "{text}""#);
+        match self {
+            Self::Segno => r"\segnoMark \default".to_string(),
+            Self::Coda => r"\codaMark \default".to_string(),
+            Self::Fine => r"\fine".to_string(),
+            Self::ToCoda => jump("To Coda"),
+            Self::DaCapo => jump("D.C."),
+            Self::DaCapoAlFine => jump("D.C. al Fine"),
+            Self::DalSegno => jump("D.S."),
+            Self::DalSegnoAlCoda => jump("D.S. al Coda"),
+        }
+    }
+}
+impl ToString for Jump {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Segno => "segno",
+            Self::Coda => "coda",
+            Self::ToCoda => "to_coda",
+            Self::Fine => "fine",
+            Self::DaCapo => "dc",
+            Self::DaCapoAlFine => "dc_al_fine",
+            Self::DalSegno => "ds",
+            Self::DalSegnoAlCoda => "ds_al_coda",
+        }
+        .to_string()
+    }
+}
+impl FromStr for Jump {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "segno" => Ok(Self::Segno),
+            "coda" => Ok(Self::Coda),
+            "to_coda" => Ok(Self::ToCoda),
+            "fine" => Ok(Self::Fine),
+            "dc" => Ok(Self::DaCapo),
+            "dc_al_fine" => Ok(Self::DaCapoAlFine),
+            "ds" => Ok(Self::DalSegno),
+            "ds_al_coda" => Ok(Self::DalSegnoAlCoda),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+
+/// Structure marks of the measure.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MeasureMarks {
+    /// Name of the project marker.
+    pub rehearsal: Option<String>,
+    /// Name of the project region.
+    pub section: Option<String>,
+    /// Barline before the measure.
+    pub barline: Option<Barline>,
+    /// Barline after the measure, e.g. the final one.
+    pub end_barline: Option<Barline>,
+    pub line_break: bool,
+    /// Repeated section starts at the measure.
+    pub repeat_start: bool,
+    /// Repeated section ends with the measure and is played the given
+    /// times.
+    pub repeat_end: Option<u8>,
+    /// Alternative ending of the given number starts at the measure
+    /// and lasts the given amount of measures.
+    pub volta: Option<(u8, u8)>,
+    pub jumps: Vec<Jump>,
+}
+impl MeasureMarks {
+    /// Commands, placed before the measure contents.
+    ///
+    /// Rehearsal marks and section labels are written only if
+    /// `rehearsal_marks` style is given.
+    pub fn lilypond(&self, rehearsal_marks: Option<RehearsalMarks>) -> String {
+        let mut commands = Vec::new();
+        if let Some(barline) = self.barline {
+            commands.push(barline.lilypond());
+        }
+        if self.line_break {
+            commands.push(r"\break".to_string());
+        }
+        if let Some(style) = rehearsal_marks {
+            if let Some(section) = &self.section {
+                commands.push(format!(
+                    r#"\sectionLabel "{}""#,
+                    escape_quotes(section)
+                ));
+            }
+            if let Some(name) = &self.rehearsal {
+                commands.push(style.lilypond(name));
+            }
+        }
+        commands.extend(
+            self.jumps
+                .iter()
+                .filter(|jump| jump.is_at_start())
+                .map(|jump| jump.lilypond()),
+        );
+        commands.join(" ")
+    }
+
+    /// Commands, placed after the measure contents.
+    pub fn end_lilypond(&self) -> String {
+        self.jumps
+            .iter()
+            .filter(|jump| !jump.is_at_start())
+            .map(|jump| jump.lilypond())
+            .chain(self.end_barline.map(|barline| barline.lilypond()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Make string safe to be written inside LilyPond quotes.
+pub(crate) fn escape_quotes(string: &str) -> String {
+    string.replace('"', r#"\""#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Barline, Jump, MeasureMarks, RehearsalMarks};
+
+    #[test]
+    fn test_measure_marks() {
+        let mut marks = MeasureMarks::default();
+        assert_eq!(marks.lilypond(Some(RehearsalMarks::Letters)), "");
+        marks.rehearsal = Some(r#"Verse "A""#.to_string());
+        marks.section = Some("Chorus".to_string());
+        marks.barline = Some(Barline::Double);
+        assert_eq!(marks.lilypond(None), r#"\bar "||""#);
+        assert_eq!(
+            marks.lilypond(Some(RehearsalMarks::Letters)),
+            r#"\bar "||" \sectionLabel "Chorus" \mark \default"#
+        );
+        marks.barline = None;
+        marks.line_break = true;
+        marks.section = None;
+        assert_eq!(
+            marks.lilypond(Some(RehearsalMarks::Text)),
+            r#"\break \mark \markup \box "Verse \"A\"""#
+        );
+    }
+
+    #[test]
+    fn test_jumps() {
+        let mut marks = MeasureMarks::default();
+        marks.jumps = vec![Jump::Segno, Jump::DalSegnoAlCoda];
+        assert_eq!(marks.lilypond(None), r"\segnoMark \default");
+        assert_eq!(marks.end_lilypond(), r#"\jump "D.S. al Coda""#);
+        marks.end_barline = Some(Barline::Final);
+        assert_eq!(marks.end_lilypond(), r#"\jump "D.S. al Coda" \bar "|.""#);
+        assert_eq!(
+            "ds_al_coda".parse::<Jump>().unwrap(),
+            Jump::DalSegnoAlCoda
+        );
+        assert_eq!(Barline::Final.lilypond(), r#"\bar "|.""#);
+    }
+}
@@ -19,20 +19,47 @@ use std::collections::VecDeque;
 use fraction::Fraction;
 use rea_rs::TimeSignature;
 
+use itertools::Itertools;
+
+use crate::{
+    lilypond_render::{RenderError, RenderSettings},
+    musicxml_render::{MusicXmlContext, RendersToMusicXml},
+    notation::{
+        chord_notations::ChordNotations, measure_notations::MeasureNotations,
+        NotationType,
+    },
+};
+
 use super::{
-    container::Container, EventInfo, Length, MeasureInfo,
-    RelativePosition,
+    container::Container, time_map::apply_part_notation, EventInfo, EventType,
+    KeySignature, Length, MeasureInfo, MeasureMarks, MeterRules,
+    RelativePosition, TempoMark,
 };
 
 #[derive(Debug, PartialEq)]
 pub struct Measure {
     index: u32,
     time_signature: TimeSignature,
+    key: Option<KeySignature>,
+    beat_grouping: Option<Vec<u8>>,
+    meter_rules: MeterRules,
+    tempo: Vec<TempoMark>,
+    marks: MeasureMarks,
     container: Container,
 }
 impl From<&MeasureInfo> for Measure {
     fn from(measure: &MeasureInfo) -> Self {
-        Self::new(measure.index, measure.time_signature.clone())
+        let mut new = Self::new(measure.index, measure.time_signature.clone());
+        new.key = measure.key;
+        new.beat_grouping = measure.beat_grouping.clone();
+        new.meter_rules = measure.meter_rules;
+        new.tempo = measure.tempo.clone();
+        new.marks = measure.marks.clone();
+        if measure.is_partial() {
+            let position = RelativePosition::new(measure.index, 0.into());
+            new.container = Container::empty(position, measure.length.clone());
+        }
+        new
     }
 }
 impl Measure {
@@ -43,6 +70,11 @@ impl Measure {
         Self {
             index,
             time_signature,
+            key: None,
+            beat_grouping: None,
+            meter_rules: MeterRules::default(),
+            tempo: Vec::new(),
+            marks: MeasureMarks::default(),
             container: Container::empty(position, length),
         }
     }
@@ -65,93 +97,170 @@ impl Measure {
     pub fn time_signature(&self) -> &TimeSignature {
         &self.time_signature
     }
+    /// Key in force at the measure, if differs from the global one.
+    pub fn key(&self) -> Option<KeySignature> {
+        self.key
+    }
+    pub fn set_key(&mut self, key: Option<KeySignature>) {
+        self.key = key;
+    }
+    pub fn apply_notation(&mut self, notation: &MeasureNotations) {
+        // notations of the timeline are applied by TimeMap only.
+        apply_part_notation(
+            notation,
+            &self.time_signature,
+            &mut self.key,
+            &mut self.beat_grouping,
+            &mut self.meter_rules,
+        );
+    }
+    pub fn beat_grouping(&self) -> Option<&Vec<u8>> {
+        self.beat_grouping.as_ref()
+    }
+    pub fn meter_rules(&self) -> MeterRules {
+        self.meter_rules
+    }
+    pub fn set_meter_rules(&mut self, rules: MeterRules) {
+        self.meter_rules = rules;
+    }
+    /// Tempo changes inside the measure.
+    pub fn tempo(&self) -> &Vec<TempoMark> {
+        &self.tempo
+    }
+    /// Rehearsal marks, sections and breaks at the measure start.
+    pub fn marks(&self) -> &MeasureMarks {
+        &self.marks
+    }
+    /// True, if measure is shorter, than its time signature.
+    pub fn is_partial(&self) -> bool {
+        self.length() < &Length::from(&self.time_signature)
+    }
+    /// `\partial` with the length of partial measure.
+    pub fn partial_lilypond(&self) -> Option<String> {
+        if !self.is_partial() {
+            return None;
+        }
+        Some(format!(r"\partial {}", duration_lilypond(self.length())?))
+    }
+    /// Duration of the whole-measure rest, like `2.` for 3/4 or `8*5`
+    /// for 5/8.
+    pub fn full_rest_duration(&self) -> Option<String> {
+        duration_lilypond(&Length::from(&self.time_signature))
+    }
+    /// Start and length of every beat group. Without
+    /// [Measure::beat_grouping] groups are taken from
+    /// [MeterRules::default_grouping].
+    ///
+    /// Partial measure holds only the last beats, that can be cut.
+    pub fn beats(&self) -> Vec<(Fraction, Fraction)> {
+        let denominator = self.time_signature.denominator as u64;
+        let groups = match &self.beat_grouping {
+            Some(groups) => groups.clone(),
+            None => self.meter_rules.default_grouping(&self.time_signature),
+        };
+        let offset =
+            Length::from(&self.time_signature).get() - self.length().get();
+        let mut position = Fraction::from(0);
+        groups
+            .into_iter()
+            .filter_map(|group| {
+                let length = Fraction::new(group as u64, denominator);
+                let (start, end) = (position, position + length);
+                position = end;
+                match (end <= offset, start < offset) {
+                    (true, _) => None,
+                    (false, true) => Some((Fraction::from(0), end - offset)),
+                    (false, false) => Some((start - offset, length)),
+                }
+            })
+            .collect()
+    }
+    /// `\set Timing.beatStructure`, if measure has beat grouping.
+    pub fn beat_structure_lilypond(&self) -> Option<String> {
+        self.beat_grouping.as_ref().map(|groups| {
+            format!(
+                r"\set Timing.beatStructure = {}",
+                groups.iter().join(",")
+            )
+        })
+    }
+    /// False, if measure holds only rests.
+    pub fn has_notes(&self) -> bool {
+        self.events().iter().any(|ev| ev.event != EventType::Rest)
+    }
+    /// True, if tuplet, started in the measure, is not finished yet.
+    pub fn has_open_tuplets(&self) -> bool {
+        self.container.has_open_tuplets()
+    }
+
+    /// Lowest and highest MIDI pitches of the measure, if it has notes.
+    pub fn pitch_range(&self) -> Option<(u8, u8)> {
+        let pitches = self.events().iter().flat_map(|ev| ev.event.pitches());
+        pitches.fold(None, |range, pitch| match range {
+            None => Some((pitch, pitch)),
+            Some((low, high)) => Some((low.min(pitch), high.max(pitch))),
+        })
+    }
 
-    /// Get events, split and tied based on the time signature.
+    /// Get events, split and tied based on the time signature, beat
+    /// grouping and [MeterRules] of the measure.
     pub fn get_events_normalized(
         &self,
     ) -> Result<Vec<EventInfo>, String> {
-        let mut ts_events = Vec::new();
-        (0..self.time_signature.numerator)
-            .map(|idx| {
-                let g = Fraction::new(
-                    idx as u64,
-                    self.time_signature.denominator as u64,
-                );
-                let position = RelativePosition::new(self.index, g);
-                let length = Length::from(Fraction::new(
-                    1_u64,
-                    self.time_signature.denominator as u64,
-                ));
-                ts_events.push(EventInfo::new(
-                    position,
-                    length,
-                    Default::default(),
-                ))
-            })
-            .count();
+        let beats = self.beats();
         let mut events = Vec::new();
         for event in self.events() {
+            if let EventType::Tuplet(_) = event.event {
+                events.push(event.clone());
+                continue;
+            }
+            let start = event.position.position_quantized();
+            let end = start + event.length.get_quantized();
             let mut event = event.clone();
-            for ts_event in ts_events.iter() {
-                if !ts_event.overlaps(&event) {
-                    continue;
-                }
-                match event.position == ts_event.position {
-                    false => match event.outlasts(&ts_event) {
-                        None => {
-                            events.extend(
-                                event.with_normalized_length(),
-                            );
-                            break;
-                        }
-                        Some(length) => match event.position
-                            > ts_event.position
-                        {
-                            false => continue,
-                            true => {
-                                let head = event.cut_head(length)?;
-                                events.extend(
-                                    event.with_normalized_length(),
-                                );
-                                event = head;
-                            }
-                        },
-                    },
-                    true => {
-                        let ev_end =
-                            event.position.position_quantized()
-                                + event.length.get_quantized();
-                        let ts_ev_end =
-                            ts_event.position.position_quantized()
-                                + ts_event.length.get_quantized();
-                        match ev_end == ts_ev_end {
-                            true => {
-                                events.extend(
-                                    event.with_normalized_length(),
-                                );
-                                break;
-                            }
-                            false => {
-                                match event.outlasts(ts_event) {
-                                    None => {
-                                        events.extend(
-                                        event.with_normalized_length(),
-                                    );
-                                        break;
-                                    }
-                                    Some(_length) => {
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+            let rest = event.event == EventType::Rest;
+            for point in
+                self.meter_rules.split_points(&beats, start, end, rest)
+            {
+                let mut position = event.position.clone();
+                position.set_position(point);
+                let head = event.cut_head_at_position(&position)?;
+                events.extend(self.meter_rules.normalize_piece(&event));
+                event = head;
             }
+            events.extend(self.meter_rules.normalize_piece(&event));
         }
         Ok(events)
     }
 
+    /// Render normalized events.
+    ///
+    /// If measure has beat grouping, notes shorter than quarter are
+    /// beamed manually inside every beat group.
+    pub fn render_events_lilypond(
+        &self,
+        settings: &RenderSettings,
+    ) -> Result<String, RenderError> {
+        let mut events =
+            self.get_events_normalized().map_err(RenderError::Events)?;
+        if self.beat_grouping.is_some() {
+            for (first, last) in beam_runs(&events, &self.beats()) {
+                for (idx, beam) in [
+                    (first, ChordNotations::BeamStart),
+                    (last, ChordNotations::BeamEnd),
+                ] {
+                    events[idx]
+                        .push_notation(NotationType::Chord(beam))
+                        .map_err(|err| RenderError::Events(err.to_string()))?;
+                }
+            }
+        }
+        let rendered: Vec<_> = events
+            .iter()
+            .map(|ev| ev.render_lilypond_with(settings))
+            .collect::<Result<_, _>>()?;
+        Ok(rendered.join(" "))
+    }
+
     /// insert event to the measure, resolving how to place it
     /// with other events.
     ///
@@ -255,3 +364,83 @@ impl Measure {
         None
     }
 }
+
+/// Single duration, or the shortest note value, multiplied.
+fn duration_lilypond(length: &Length) -> Option<String> {
+    let fraction = length.get_quantized();
+    let duration = match length.try_render_lilypond() {
+        Ok(duration) => duration,
+        Err(_) => format!("{}*{}", fraction.denom()?, fraction.numer()?),
+    };
+    Some(duration)
+}
+
+/// Indexes of the first and the last event of every run of notes
+/// shorter than quarter, which lay in the same beat group.
+///
+/// Rests and tuplets break runs. Single notes are not beamed.
+fn beam_runs(
+    events: &[EventInfo],
+    beats: &[(Fraction, Fraction)],
+) -> Vec<(usize, usize)> {
+    let group_of = |event: &EventInfo| {
+        let beamable =
+            matches!(event.event, EventType::Note(_) | EventType::Chord(_))
+                && event.length.get() < Fraction::new(1_u64, 4_u64);
+        let start = event.position.position();
+        let end = start + event.length.get();
+        beats.iter().position(|(position, length)| {
+            beamable && start >= *position && end <= *position + *length
+        })
+    };
+    let mut runs = Vec::new();
+    // (group, first, last)
+    let mut run: Option<(usize, usize, usize)> = None;
+    for (idx, event) in events.iter().enumerate() {
+        let group = group_of(event);
+        run = match (run, group) {
+            (Some((current, first, _)), Some(group)) if current == group => {
+                Some((group, first, idx))
+            }
+            (previous, group) => {
+                if let Some((_, first, last)) = previous {
+                    runs.push((first, last));
+                }
+                group.map(|group| (group, idx, idx))
+            }
+        };
+    }
+    if let Some((_, first, last)) = run {
+        runs.push((first, last));
+    }
+    runs.retain(|(first, last)| last > first);
+    runs
+}
+
+impl RendersToMusicXml for Measure {
+    fn render_musicxml(
+        &self,
+        ctx: &mut MusicXmlContext,
+    ) -> Result<String, RenderError> {
+        if self.events().len() == 1 && self.events()[0].event == EventType::Rest
+        {
+            ctx.length = self.length().clone();
+            let rest = format!(
+                "<note><rest measure=\"yes\"/><duration>{}</duration>\
+                <voice>{}</voice><staff>{}</staff></note>",
+                ctx.duration(self.length()),
+                ctx.voice,
+                ctx.staff
+            );
+            ctx.finish_event();
+            return Ok(rest);
+        }
+        Ok(self
+            .get_events_normalized()
+            .map_err(RenderError::Events)?
+            .iter()
+            .map(|ev| ev.render_musicxml(ctx))
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n"))
+    }
+}
@@ -0,0 +1,222 @@
+//! Rules, by which events are split and tied inside the measure.
+//!
+//! The rule set is chosen per measure by
+//! [MeasureNotations::Meter](crate::notation::measure_notations::MeasureNotations).
+use std::{collections::VecDeque, str::FromStr};
+
+use fraction::Fraction;
+use rea_rs::TimeSignature;
+
+use crate::notation::NotationError;
+
+use super::{EventInfo, Length};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MeterRules {
+    /// Every beat of time signature denominator is a group. Events,
+    /// that start inside a group, are split at its end, and the rest
+    /// is split by [Length::normalized].
+    Beats,
+    /// Compound meters (6/8, 9/8, 12/8...) are counted in dotted
+    /// beats. Event, that starts on a beat, is written by the longest
+    /// single (may be dotted) note value, that ends on a beat or at
+    /// the event end, so syncopation across the middle of 4/4 and
+    /// dotted values spanning several beats are kept untied. Event,
+    /// that starts off-beat, is split at the next beat.
+    ///
+    /// Rests are not syncopated: they are split at beats and joined
+    /// only into a whole beat group, or into a plain (not dotted)
+    /// value, that starts at a multiple of its length. So rest from
+    /// the second beat of 4/4 is `r4 r2`.
+    #[default]
+    Standard,
+}
+impl MeterRules {
+    /// Beat groups in units of time signature denominator, when
+    /// measure has no explicit grouping.
+    pub fn default_grouping(&self, time_signature: &TimeSignature) -> Vec<u8> {
+        let numerator = time_signature.numerator as usize;
+        match self {
+            Self::Standard if is_compound(time_signature) => {
+                vec![3; numerator / 3]
+            }
+            _ => vec![1; numerator],
+        }
+    }
+
+    /// Positions (from the start of measure), at which event from
+    /// `start` to `end` should be split.
+    ///
+    /// `beats` are start and length of every beat group, as
+    /// [Measure::beats](super::Measure::beats) returns. `rest` tells,
+    /// if the event is rest.
+    pub fn split_points(
+        &self,
+        beats: &[(Fraction, Fraction)],
+        start: Fraction,
+        end: Fraction,
+        rest: bool,
+    ) -> Vec<Fraction> {
+        let on_beat = |position: Fraction| {
+            beats.iter().any(|(beat, _)| *beat == position)
+        };
+        let rest_fits = |position: Fraction, piece_end: Fraction| {
+            let length = piece_end - position;
+            let group = beats
+                .iter()
+                .any(|(beat, group)| *beat == position && *group == length);
+            let plain = Length::from(length)
+                .try_render_lilypond()
+                .map_or(false, |duration| !duration.contains('.'));
+            group || (plain && (position / length).denom() == Some(&1))
+        };
+        let next_beats = |position: Fraction| {
+            beats
+                .iter()
+                .map(|(beat, length)| *beat + *length)
+                .filter(|beat_end| *beat_end > position && *beat_end < end)
+                .chain([end])
+                .collect::<Vec<_>>()
+        };
+        let mut points = Vec::new();
+        let mut position = start;
+        while position < end {
+            let candidates = next_beats(position);
+            let piece_end = match (self, on_beat(position)) {
+                (Self::Standard, _) if rest => candidates
+                    .iter()
+                    .rev()
+                    .find(|candidate| rest_fits(position, **candidate))
+                    .copied()
+                    .unwrap_or(candidates[0]),
+                (Self::Beats, true) => end,
+                (Self::Standard, true) => candidates
+                    .iter()
+                    .rev()
+                    .find(|candidate| {
+                        Length::from(**candidate - position)
+                            .try_render_lilypond()
+                            .is_ok()
+                    })
+                    .copied()
+                    .unwrap_or(candidates[0]),
+                (_, false) => candidates[0],
+            };
+            if piece_end < end {
+                points.push(piece_end);
+            }
+            position = piece_end;
+        }
+        points
+    }
+
+    /// Split and tie the piece of event, that is already split by
+    /// [MeterRules::split_points].
+    pub fn normalize_piece(&self, piece: &EventInfo) -> VecDeque<EventInfo> {
+        match self {
+            Self::Standard if piece.length.try_render_lilypond().is_ok() => {
+                VecDeque::from([piece.clone()])
+            }
+            _ => piece.with_normalized_length(),
+        }
+    }
+}
+impl ToString for MeterRules {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Beats => "beats",
+            Self::Standard => "standard",
+        }
+        .to_string()
+    }
+}
+impl FromStr for MeterRules {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "beats" => Ok(Self::Beats),
+            "standard" => Ok(Self::Standard),
+            x => Err(NotationError::UnexpectedToken(x.to_string())),
+        }
+    }
+}
+
+/// Time signature, which beats are divided by three: 6/8, 9/8, 12/16
+/// etc.
+pub fn is_compound(time_signature: &TimeSignature) -> bool {
+    time_signature.numerator > 3
+        && time_signature.numerator % 3 == 0
+        && time_signature.denominator >= 8
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use super::MeterRules;
+
+    #[test]
+    fn test_split_points() {
+        let frac = |n: u64, d: u64| Fraction::new(n, d);
+        let beats = |groups: Vec<u8>, denom: u64| {
+            let mut position = Fraction::from(0);
+            groups
+                .into_iter()
+                .map(|group| {
+                    let beat = (position, frac(group as u64, denom));
+                    position += beat.1;
+                    beat
+                })
+                .collect::<Vec<_>>()
+        };
+        let six_eight = TimeSignature::new(6, 8);
+        let rules = MeterRules::Standard;
+        assert_eq!(rules.default_grouping(&six_eight), vec![3, 3]);
+        assert_eq!(MeterRules::Beats.default_grouping(&six_eight), vec![1; 6]);
+        let compound = beats(rules.default_grouping(&six_eight), 8);
+        // dotted quarter and dotted half are not split
+        assert!(rules
+            .split_points(&compound, frac(0, 1), frac(3, 8), false)
+            .is_empty());
+        assert!(rules
+            .split_points(&compound, frac(0, 1), frac(3, 4), false)
+            .is_empty());
+        // off-beat quarter, crossing the beat
+        assert_eq!(
+            rules.split_points(&compound, frac(1, 4), frac(1, 2), false),
+            vec![frac(3, 8)]
+        );
+
+        let common = beats(vec![1; 4], 4);
+        // syncopation across the middle of 4/4
+        assert!(rules
+            .split_points(&common, frac(1, 4), frac(3, 4), false)
+            .is_empty());
+        // half tied to eighth
+        assert_eq!(
+            rules.split_points(&common, frac(1, 4), frac(7, 8), false),
+            vec![frac(3, 4)]
+        );
+        assert_eq!(
+            rules.split_points(&common, frac(1, 8), frac(3, 8), false),
+            vec![frac(1, 4)]
+        );
+        assert!(MeterRules::Beats
+            .split_points(&common, frac(0, 1), frac(5, 8), false)
+            .is_empty());
+        // rests keep the beat split
+        assert_eq!(
+            rules.split_points(&common, frac(1, 4), frac(1, 1), true),
+            vec![frac(1, 2)]
+        );
+        assert_eq!(
+            rules.split_points(&common, frac(0, 1), frac(3, 4), true),
+            vec![frac(1, 2)]
+        );
+        assert_eq!(
+            rules.split_points(&compound, frac(0, 1), frac(3, 4), true),
+            vec![frac(3, 8)]
+        );
+    }
+}
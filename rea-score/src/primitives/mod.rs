@@ -6,26 +6,42 @@
 //! Then multiple voices organized in DOM.
 //! then rendered to *.ly source file and compiled by LilyPong.
 
+pub mod clef;
 pub mod container;
 pub mod event;
 pub mod fraction_tools;
+pub mod grace;
+pub mod key;
 pub mod length;
+pub mod marks;
 pub mod measure;
+pub mod meter;
 pub mod pitch;
 pub mod position;
+pub mod quantization;
+pub mod tempo;
 pub mod time_map;
 
+pub use clef::Clef;
 pub use event::{Chord, EventInfo, EventType, Note};
 pub use fraction_tools::{limit_denominator, normalize_fraction};
+pub use grace::{GraceGroup, GraceType};
+pub use key::{KeyMode, KeySignature};
 pub use length::Length;
+pub use marks::{Barline, Jump, MeasureMarks, RehearsalMarks};
 pub use measure::Measure;
+pub use meter::MeterRules;
 pub use pitch::{
     midi_to_note, Accidental, Key, NoteName, Octave, Pitch, ResolvedPitch,
     Scale,
 };
 pub use position::{AbsolutePosition, RelativeDistance, RelativePosition};
+pub use quantization::Quantization;
+pub use tempo::{Ramp, TempoMark, TempoPoint};
 pub use time_map::{MeasureInfo, TimeMap, TimeMapMeasures};
 
+/// The finest resolution of lengths and positions. The grid, music is
+/// snapped to, is [Quantization] of render settings.
 static LIMIT_DENOMINATOR: u64 = 128;
 
 #[cfg(test)]
@@ -39,16 +39,8 @@
 //! use rea_rs::TimeSignature;
 //!
 //! let measures = Vec::from([
-//!     MeasureInfo{
-//!             index: 1,
-//!             time_signature: TimeSignature::new(7, 8),
-//!             length: Length::from(7.0/8.0),
-//!         },
-//!     MeasureInfo{
-//!             index: 2,
-//!             time_signature: TimeSignature::new(5, 8),
-//!             length: Length::from(5.0/8.0),
-//!         },
+//!     MeasureInfo::new(1, TimeSignature::new(7, 8)),
+//!     MeasureInfo::new(2, TimeSignature::new(5, 8)),
 //! ]);
 //! let time_map = TimeMap::new(measures, 0.0.into());
 //!
@@ -87,7 +79,8 @@ use fraction::Fraction;
 use rea_rs::{Measure, Reaper};
 
 use super::{
-    limit_denominator, time_map::TimeMap, Length, LIMIT_DENOMINATOR,
+    limit_denominator, time_map::TimeMap, Length, Quantization,
+    LIMIT_DENOMINATOR,
 };
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
@@ -184,6 +177,10 @@ impl AbsolutePosition {
     pub fn get_quantized_to(&self, denominator: u64) -> Fraction {
         limit_denominator(self.position, denominator).unwrap()
     }
+    /// Quantize to the grid of settings.
+    pub fn quantized_by(&self, quantization: &Quantization) -> Fraction {
+        quantization.quantize(self.position)
+    }
 }
 impl Distance<RelativePosition> for AbsolutePosition {}
 impl Distance<AbsolutePosition> for AbsolutePosition {}
@@ -332,6 +329,13 @@ impl RelativePosition {
         limit_denominator(self.measure_position, denominator)
             .unwrap()
     }
+    /// position in measure, quantized to the grid of settings.
+    pub fn quantized_by(
+        &self,
+        quantization: &Quantization,
+    ) -> Fraction {
+        quantization.quantize(self.measure_position)
+    }
     /// measure (1-based)
     pub fn get_measure_index(&self) -> u32 {
         self.measure_index
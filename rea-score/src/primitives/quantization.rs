@@ -0,0 +1,267 @@
+//! Snapping of human-played positions and lengths to the grid.
+//!
+//! [LIMIT_DENOMINATOR](super::LIMIT_DENOMINATOR) is only the finest
+//! resolution, fractions are kept in. The grid, music is quantized to
+//! before it is placed into voices, is taken from
+//! [RenderSettings](crate::lilypond_render::RenderSettings).
+use fraction::Fraction;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AbsolutePosition, EventInfo, Length, RelativePosition, TimeMap,
+    LIMIT_DENOMINATOR,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quantization {
+    /// Straight grid, e.g. 16 is 1/16 grid.
+    Grid(u64),
+    /// Straight grid, which off-beats are played late.
+    ///
+    /// Off-beats are notated straight.
+    Swing {
+        /// Notated grid, e.g. 8 for swung eighths.
+        grid: u64,
+        /// Position of off-beat in percents of the pair of grid
+        /// steps: 50 is straight, 67 is triplet swing.
+        swing: u8,
+    },
+    /// Every position is snapped to the nearest of straight or
+    /// triplet grids, e.g. 16 and 12.
+    Mixed { straight: u64, triplet: u64 },
+}
+impl Default for Quantization {
+    fn default() -> Self {
+        Self::Grid(LIMIT_DENOMINATOR)
+    }
+}
+impl Quantization {
+    /// The shortest length, grid can produce.
+    pub fn step(&self) -> Fraction {
+        match *self {
+            Self::Grid(denom) => Fraction::new(1_u64, denom),
+            Self::Swing { grid, .. } => Fraction::new(1_u64, grid),
+            Self::Mixed { straight, triplet } => {
+                Fraction::new(1_u64, straight.max(triplet))
+            }
+        }
+    }
+
+    /// Snap position (measured from the start of measure) to the
+    /// grid.
+    pub fn quantize(&self, position: Fraction) -> Fraction {
+        match *self {
+            Self::Grid(denom) => snap(position, Fraction::new(1_u64, denom)),
+            Self::Swing { grid, swing } => {
+                let pair = Fraction::new(2_u64, grid);
+                let pair_start = (position / pair).floor() * pair;
+                let offset = position - pair_start;
+                let off_beat = pair * Fraction::new(swing as u64, 100_u64);
+                // (played, notated)
+                [
+                    (Fraction::from(0), Fraction::from(0)),
+                    (off_beat, pair / Fraction::from(2)),
+                    (pair, pair),
+                ]
+                .into_iter()
+                .min_by_key(|(played, _)| distance(offset, *played))
+                .map(|(_, notated)| pair_start + notated)
+                .expect("There are always candidates")
+            }
+            Self::Mixed { straight, triplet } => nearest(
+                position,
+                snap(position, Fraction::new(1_u64, straight)),
+                snap(position, Fraction::new(1_u64, triplet)),
+            ),
+        }
+    }
+
+    /// Snap length, which position is unknown, to the grid.
+    ///
+    /// Length never becomes shorter than the [Quantization::step].
+    pub fn quantize_length(&self, length: Fraction) -> Fraction {
+        let quantized = match *self {
+            Self::Grid(denom) | Self::Swing { grid: denom, .. } => {
+                snap(length, Fraction::new(1_u64, denom))
+            }
+            Self::Mixed { .. } => self.quantize(length),
+        };
+        match quantized < self.step() {
+            true => self.step(),
+            false => quantized,
+        }
+    }
+}
+
+/// Nearest multiple of step.
+pub(crate) fn snap(value: Fraction, step: Fraction) -> Fraction {
+    (value / step).round() * step
+}
+
+fn distance(a: Fraction, b: Fraction) -> Fraction {
+    match a > b {
+        true => a - b,
+        false => b - a,
+    }
+}
+
+/// The nearest to value of a and b. On equal distance a wins.
+fn nearest(value: Fraction, a: Fraction, b: Fraction) -> Fraction {
+    match distance(value, b) < distance(value, a) {
+        true => b,
+        false => a,
+    }
+}
+
+impl EventInfo {
+    /// Snap start and end of the event to the grid.
+    ///
+    /// Positions are quantized from the start of the measure, event
+    /// starts in. Event never becomes shorter than one
+    /// [Quantization::step].
+    pub fn quantize(
+        &mut self,
+        quantization: &Quantization,
+        time_map: &TimeMap,
+    ) {
+        let measure = self.position.get_measure_index();
+        let start = self.position.quantized_by(quantization);
+        let end = quantization
+            .quantize(self.position.position() + self.length.get());
+        let end = match end < start + quantization.step() {
+            true => start + quantization.step(),
+            false => end,
+        };
+        self.length = Length::from(end - start);
+        if start < time_map.get_measure_info(measure).length.get() {
+            self.position.set_position(start);
+            return;
+        }
+        // snapped to the next measure
+        let measure_start = time_map.get_absolute_position_of_measure(measure);
+        if let Some(position) = time_map.pos_relative_from_absolute(
+            &AbsolutePosition::from(measure_start.get() + start),
+        ) {
+            self.position = position;
+        }
+    }
+
+    /// Snap start and end of the event to the grid of `step`, that
+    /// begins at `start`.
+    ///
+    /// Used for events of tuplets, which grid is not the straight
+    /// one: e.g. with 1/16 grid events of a triplet are snapped to
+    /// 1/24 from the tuplet start. Event never becomes shorter than
+    /// one step.
+    pub fn quantize_from(
+        &mut self,
+        start: &RelativePosition,
+        step: Fraction,
+        time_map: &TimeMap,
+    ) {
+        let absolute = |position: &RelativePosition| {
+            time_map
+                .get_absolute_position_of_measure(position.get_measure_index())
+                .get()
+                + position.position()
+        };
+        let tuplet_start = absolute(start);
+        let offset = absolute(&self.position) - tuplet_start;
+        let begin = snap(offset, step);
+        let end = snap(offset + self.length.get(), step);
+        let end = match end < begin + step {
+            true => begin + step,
+            false => end,
+        };
+        self.length = Length::from(end - begin);
+        if let Some(position) = time_map.pos_relative_from_absolute(
+            &AbsolutePosition::from(tuplet_start + begin),
+        ) {
+            self.position = position;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use crate::primitives::{
+        AbsolutePosition, EventInfo, EventType, Length, MeasureInfo,
+        RelativePosition, TimeMap,
+    };
+
+    use super::Quantization;
+
+    #[test]
+    fn test_quantization() {
+        let frac = |n: u64, d: u64| Fraction::new(n, d);
+        let grid = Quantization::Grid(16);
+        assert_eq!(grid.quantize(frac(33, 128)), frac(1, 4));
+        assert_eq!(grid.quantize_length(frac(1, 128)), frac(1, 16));
+
+        let swing = Quantization::Swing { grid: 8, swing: 67 };
+        // played off-beat of the second beat
+        assert_eq!(swing.quantize(frac(1, 4) + frac(21, 128)), frac(3, 8));
+        assert_eq!(swing.quantize(frac(31, 128)), frac(1, 4));
+
+        let mixed = Quantization::Mixed {
+            straight: 16,
+            triplet: 12,
+        };
+        // near 1/3 goes to the triplet grid, near 1/4 to the straight
+        assert_eq!(mixed.quantize(frac(43, 128)), frac(1, 3));
+        assert_eq!(mixed.quantize(frac(33, 128)), frac(1, 4));
+        assert_eq!(mixed.quantize(frac(49, 128)), frac(3, 8));
+        assert_eq!(
+            Length::from(frac(11, 128)).quantized_by(&mixed),
+            frac(1, 12)
+        );
+        assert_eq!(
+            AbsolutePosition::from(frac(43, 128)).quantized_by(&mixed),
+            frac(1, 3)
+        );
+    }
+
+    #[test]
+    fn test_quantize_event() {
+        let time_map = Arc::new(TimeMap::new(
+            vec![
+                MeasureInfo::new(1, TimeSignature::new(4, 4)),
+                MeasureInfo::new(2, TimeSignature::new(4, 4)),
+            ],
+            AbsolutePosition::from(0.0),
+        ));
+        let mut event = EventInfo::new(
+            RelativePosition::new(1, Fraction::new(125_u64, 128_u64)),
+            Length::from(Fraction::new(17_u64, 64_u64)),
+            EventType::Rest,
+        );
+        event.quantize(&Quantization::Grid(16), &time_map);
+        assert_eq!(
+            event.position,
+            RelativePosition::new(2, Fraction::from(0))
+        );
+        assert_eq!(event.length, Length::from(0.25));
+
+        // the second eighth of triplet, played late
+        let mut event = EventInfo::new(
+            RelativePosition::new(1, Fraction::new(45_u64, 128_u64)),
+            Length::from(Fraction::new(5_u64, 64_u64)),
+            EventType::Rest,
+        );
+        event.quantize_from(
+            &RelativePosition::new(1, Fraction::new(1_u64, 4_u64)),
+            Fraction::new(1_u64, 24_u64),
+            &time_map,
+        );
+        assert_eq!(
+            event.position,
+            RelativePosition::new(1, Fraction::new(1_u64, 3_u64))
+        );
+        assert_eq!(event.length, Length::from(Fraction::new(1_u64, 12_u64)));
+    }
+}
@@ -0,0 +1,163 @@
+//! Tempo marks, built from the tempo map of the project.
+//!
+//! Tempo map is given as [TempoPoint]s, which [TimeMap] converts to
+//! [TempoMark]s of measures. Textual tempo names come from markers
+//! (see [MeasureNotations::Tempo]).
+//!
+//! [TimeMap]: super::TimeMap
+//! [MeasureNotations::Tempo]: crate::notation::measure_notations::MeasureNotations
+use fraction::Fraction;
+use rea_rs::TimeSignature;
+
+use super::{marks::escape_quotes, meter::is_compound, AbsolutePosition};
+
+/// Point of the tempo map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoPoint {
+    pub position: AbsolutePosition,
+    /// Quarter notes per minute.
+    pub bpm: f64,
+    /// Tempo changes gradually from this point to the next one.
+    pub ramp: bool,
+}
+impl TempoPoint {
+    pub fn new(position: AbsolutePosition, bpm: f64, ramp: bool) -> Self {
+        Self {
+            position,
+            bpm,
+            ramp,
+        }
+    }
+}
+
+/// Direction of gradual tempo change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ramp {
+    Accelerando,
+    Ritardando,
+}
+impl Ramp {
+    pub fn text(&self) -> &'static str {
+        match self {
+            Self::Accelerando => "accel.",
+            Self::Ritardando => "rit.",
+        }
+    }
+}
+
+/// Tempo indication inside the measure.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TempoMark {
+    /// Distance from the start of measure.
+    pub position: Fraction,
+    /// Metronome mark in quarter notes per minute. None, if tempo is
+    /// not changed.
+    pub bpm: Option<f64>,
+    /// Tempo name, like "Allegro".
+    pub text: Option<String>,
+    /// Gradual tempo change, that starts here.
+    pub ramp: Option<Ramp>,
+    /// Gradual tempo change of the previous mark ends here.
+    pub ramp_end: bool,
+}
+impl TempoMark {
+    pub fn new(position: Fraction) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    /// `\tempo` and text spanner settings, placed before the event.
+    ///
+    /// Metronome is written in beats of time signature: dotted
+    /// quarters for 6/8, halves for 2/2.
+    pub fn lilypond_prefix(&self, time_signature: &TimeSignature) -> String {
+        let metronome = self.bpm.map(|bpm| {
+            let (unit, quarters) = metronome_unit(time_signature);
+            format!("{unit} = {}", (bpm / quarters).round())
+        });
+        let text = self.text.as_deref().map(escape_quotes);
+        let tempo = match (text, metronome) {
+            (None, None) => None,
+            (Some(text), None) => Some(format!(r#"\tempo "{text}""#)),
+            (None, Some(metronome)) => Some(format!(r"\tempo {metronome}")),
+            (Some(text), Some(metronome)) => {
+                Some(format!(r#"\tempo "{text}" {metronome}"#))
+            }
+        };
+        let ramp = self.ramp.map(|ramp| {
+            format!(
+                r#"\override TextSpanner.bound-details.left.text = "{}""#,
+                ramp.text()
+            )
+        });
+        [tempo, ramp]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Text spanner commands, placed after the event.
+    pub fn lilypond_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if self.ramp_end {
+            suffix.push_str(r"\stopTextSpan");
+        }
+        if self.ramp.is_some() {
+            suffix.push_str(r"\startTextSpan");
+        }
+        suffix
+    }
+}
+
+/// Note value of metronome mark and its length in quarters.
+fn metronome_unit(time_signature: &TimeSignature) -> (&'static str, f64) {
+    match time_signature.denominator {
+        8 if is_compound(time_signature) => ("4.", 1.5),
+        2 => ("2", 2.0),
+        _ => ("4", 1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fraction::Fraction;
+    use rea_rs::TimeSignature;
+
+    use super::{Ramp, TempoMark};
+
+    #[test]
+    fn test_tempo_mark() {
+        let mut mark = TempoMark::new(Fraction::from(0));
+        mark.bpm = Some(132.0);
+        mark.text = Some("Allegro".to_string());
+        let common = TimeSignature::new(4, 4);
+        assert_eq!(
+            mark.lilypond_prefix(&common),
+            r#"\tempo "Allegro" 4 = 132"#
+        );
+        assert_eq!(
+            mark.lilypond_prefix(&TimeSignature::new(6, 8)),
+            r#"\tempo "Allegro" 4. = 88"#
+        );
+        mark.text = None;
+        mark.ramp = Some(Ramp::Ritardando);
+        assert_eq!(
+            mark.lilypond_prefix(&common),
+            r#"\tempo 4 = 132 \override TextSpanner.bound-details.left.text = "rit.""#
+        );
+        assert_eq!(mark.lilypond_suffix(), r"\startTextSpan");
+        let mut end = TempoMark::new(Fraction::from(0));
+        end.ramp_end = true;
+        assert_eq!(end.lilypond_prefix(&common), "");
+        assert_eq!(end.lilypond_suffix(), r"\stopTextSpan");
+        let mut quoted = TempoMark::new(Fraction::from(0));
+        quoted.text = Some(r#"Allegro "ma non troppo""#.to_string());
+        assert_eq!(
+            quoted.lilypond_prefix(&common),
+            r#"\tempo "Allegro \"ma non troppo\"""#
+        );
+    }
+}
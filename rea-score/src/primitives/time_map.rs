@@ -1,9 +1,15 @@
 //! Main "ruler" for making voices and moving through score.
-use rea_rs::{Position, Reaper, TimeSignature};
+use fraction::Fraction;
+use rea_rs::{Position, Project, Reaper, TimeSignature};
+
+use crate::notation::measure_notations::{
+    marker_notations_from_string, MeasureNotations,
+};
 
 use super::{
     position::{AbsolutePosition, RelativePosition},
-    Length,
+    KeySignature, Length, MeasureMarks, MeterRules, Ramp, TempoMark,
+    TempoPoint,
 };
 pub type TimeMapMeasures = Vec<MeasureInfo>;
 
@@ -43,16 +49,31 @@ impl TimeMap {
     }
     /// Get absolute position of measure start.
     /// Index is 1-based.
+    ///
+    /// Partial measure starts at its [MeasureInfo::offset] from the
+    /// start of measure on the timeline.
     pub fn get_absolute_position_of_measure(
         &self,
         measure_index: u32,
+    ) -> AbsolutePosition {
+        let offset = match self.index_in_map(measure_index) {
+            Some(idx) => Length::from(self.measures[idx].offset()),
+            None => Length::from(0.0),
+        };
+        self.timeline_position_of_measure(measure_index) + offset
+    }
+    /// Get absolute position of measure start on the timeline, as
+    /// REAPER counts it, regardless of partial measures.
+    pub fn timeline_position_of_measure(
+        &self,
+        measure_index: u32,
     ) -> AbsolutePosition {
         let mut counted_abs = self.start_position.clone();
         for measure in self.measures.iter() {
             if measure.index == measure_index {
                 break;
             }
-            counted_abs += measure.length.clone();
+            counted_abs += measure.timeline_length();
         }
         counted_abs
     }
@@ -66,8 +87,9 @@ impl TimeMap {
     ) -> Option<(MeasureInfo, AbsolutePosition)> {
         let mut counted_abs = self.start_position.clone();
         for measure in self.measures.iter() {
-            let last_measure_pos = counted_abs.clone();
-            counted_abs += measure.length.clone();
+            let last_measure_pos =
+                counted_abs.clone() + Length::from(measure.offset());
+            counted_abs += measure.timeline_length();
             if counted_abs > *absolute {
                 return Some((measure.clone(), last_measure_pos));
             }
@@ -75,21 +97,50 @@ impl TimeMap {
         None
     }
 
+    /// Positions before the start of partial measure are snapped to
+    /// its start with a warning.
     pub fn pos_relative_from_absolute(
         &self,
         absolute: &AbsolutePosition,
     ) -> Option<RelativePosition> {
         match self.get_measure_from_absolute_position(absolute) {
             Some((measure, measure_start)) => {
-                Some(RelativePosition::new(
-                    measure.index,
-                    absolute.get_quantized()
-                        - measure_start.get_quantized(),
-                ))
+                let position = absolute.get_quantized()
+                    - measure_start.get_quantized();
+                let position = notated_position(&measure, position)
+                    .unwrap_or_else(|err| {
+                        log::warn!("{err}, moved to its start");
+                        Fraction::from(0)
+                    });
+                Some(RelativePosition::new(measure.index, position))
             }
             None => None,
         }
     }
+    /// Convert position, measured from the start of measure on the
+    /// timeline (as REAPER does), to the position in the notated
+    /// measure, which differs for partial measures.
+    ///
+    /// Positions outside the TimeMap are returned as they are. Err,
+    /// if position is in the cut-off head of partial measure.
+    pub fn notated_from_timeline(
+        &self,
+        position: &RelativePosition,
+    ) -> Result<RelativePosition, String> {
+        match self.index_in_map(position.get_measure_index()) {
+            None => Ok(position.clone()),
+            Some(idx) => {
+                let measure = &self.measures[idx];
+                Ok(RelativePosition::new(
+                    position.get_measure_index(),
+                    notated_position(
+                        measure,
+                        position.position() - measure.offset(),
+                    )?,
+                ))
+            }
+        }
+    }
     pub fn pos_absolute_from_relative(
         &self,
         relative: &RelativePosition,
@@ -109,6 +160,12 @@ impl TimeMap {
     pub fn get(&self) -> &Vec<MeasureInfo> {
         &self.measures
     }
+    fn index_in_map(&self, measure_index: u32) -> Option<usize> {
+        match (self.begin..=self.end).contains(&measure_index) {
+            true => Some((measure_index - self.begin) as usize),
+            false => None,
+        }
+    }
 
     pub fn build_from_bounds(
         start_pos: impl Into<Position>,
@@ -134,7 +191,162 @@ impl TimeMap {
                     MeasureInfo::new(idx, measure.time_signature)
                 }),
         );
-        Self::new(measures, start)
+        let mut time_map = Self::new(measures, start);
+        time_map.apply_tempo(Self::tempo_points(&project));
+        time_map.apply_measure_notations(Self::marker_notations(
+            &project, end_pos,
+        ));
+        time_map
+    }
+
+    /// Points of the project tempo map. Project without tempo markers
+    /// has the single point of its tempo at the start.
+    fn tempo_points(project: &Project) -> Vec<TempoPoint> {
+        let low = Reaper::get().low();
+        let context = project.context().to_raw();
+        let count = unsafe { low.CountTempoTimeSigMarkers(context) };
+        if count == 0 {
+            let bpm = unsafe { low.Master_GetTempo() };
+            return vec![TempoPoint::new(0.0.into(), bpm, false)];
+        }
+        (0..count)
+            .filter_map(|idx| {
+                let (mut seconds, mut beat, mut bpm) = (0.0, 0.0, 0.0);
+                let (mut measure, mut num, mut denom) = (0, 0, 0);
+                let mut linear = false;
+                let found = unsafe {
+                    low.GetTempoTimeSigMarker(
+                        context,
+                        idx,
+                        &mut seconds,
+                        &mut measure,
+                        &mut beat,
+                        &mut bpm,
+                        &mut num,
+                        &mut denom,
+                        &mut linear,
+                    )
+                };
+                found.then(|| {
+                    TempoPoint::new(
+                        AbsolutePosition::from(Position::new(seconds)),
+                        bpm,
+                        linear,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Measure notations from project markers and regions, that
+    /// are placed before the end position.
+    ///
+    /// See [marker_notations_from_string].
+    fn marker_notations(
+        project: &Project,
+        end_pos: Position,
+    ) -> Vec<(u32, MeasureNotations)> {
+        project
+            .iter_markers_and_regions()
+            .filter(|info| info.position <= end_pos)
+            .flat_map(|info| {
+                let index =
+                    rea_rs::Measure::from_position(info.position, project)
+                        .index;
+                marker_notations_from_string(&info.name, info.is_region)
+                    .into_iter()
+                    .map(move |notation| (index, notation))
+            })
+            .collect()
+    }
+    /// Apply notations to measures, starting from the given index.
+    ///
+    /// Notation, starting before the TimeMap, is applied from its
+    /// first measure. [MeasureNotations::is_global] notations are
+    /// applied only to the measure with the given index. Per-part
+    /// ones last until they are changed and are also recorded in
+    /// [MeasureInfo::changes].
+    pub fn apply_measure_notations(
+        &mut self,
+        notations: impl IntoIterator<Item = (u32, MeasureNotations)>,
+    ) {
+        let mut notations = notations.into_iter().collect::<Vec<_>>();
+        notations.sort_by_key(|(index, _)| *index);
+        for (index, notation) in notations {
+            if !notation.is_global() {
+                if let Some(measure) = self
+                    .measures
+                    .iter_mut()
+                    .find(|measure| measure.index >= index)
+                {
+                    measure.changes.push(notation.clone());
+                }
+            }
+            self.measures
+                .iter_mut()
+                .filter(|measure| match notation.is_global() {
+                    true => measure.index == index,
+                    false => measure.index >= index,
+                })
+                .for_each(|measure| measure.apply_notation(&notation));
+        }
+    }
+    /// Place tempo points of the project to measures.
+    ///
+    /// Tempo in force at the start of TimeMap is written at its first
+    /// measure. Metronome mark is written only when tempo changes.
+    /// Point with ramp, followed by another tempo, starts rit. or
+    /// accel., which lasts until the next point.
+    pub fn apply_tempo(
+        &mut self,
+        points: impl IntoIterator<Item = TempoPoint>,
+    ) {
+        let mut points = points.into_iter().collect::<Vec<_>>();
+        points.sort_by(|a, b| {
+            a.position
+                .partial_cmp(&b.position)
+                .expect("can not compare tempo positions")
+        });
+        let start = self.get_absolute_position_of_measure(self.begin);
+        let in_force = points
+            .iter()
+            .rposition(|point| point.position <= start)
+            .unwrap_or(0);
+        let mut current: Option<f64> = None;
+        let mut ramp_running = false;
+        for (idx, point) in points.iter().enumerate().skip(in_force) {
+            let position = match point.position < start {
+                true => start.clone(),
+                false => point.position.clone(),
+            };
+            let relative = match self.pos_relative_from_absolute(&position) {
+                Some(relative) => relative,
+                None => break,
+            };
+            let mut mark = TempoMark::new(relative.position());
+            mark.ramp_end = ramp_running;
+            if current != Some(point.bpm) {
+                mark.bpm = Some(point.bpm);
+                current = Some(point.bpm);
+            }
+            mark.ramp = match points.get(idx + 1) {
+                Some(next) if point.ramp && next.bpm > point.bpm => {
+                    Some(Ramp::Accelerando)
+                }
+                Some(next) if point.ramp && next.bpm < point.bpm => {
+                    Some(Ramp::Ritardando)
+                }
+                _ => None,
+            };
+            ramp_running = mark.ramp.is_some();
+            if mark.bpm.is_none() && mark.ramp.is_none() && !mark.ramp_end {
+                continue;
+            }
+            if let Some(idx) = self.index_in_map(relative.get_measure_index())
+            {
+                self.measures[idx].push_tempo(mark);
+            }
+        }
     }
     pub fn begin_measure(&self) -> u32 {
         self.begin
@@ -144,11 +356,28 @@ impl TimeMap {
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct MeasureInfo {
     pub index: u32,
     pub time_signature: TimeSignature,
+    /// Written length of the measure. Differs from the length of
+    /// time signature for partial measures.
     pub length: Length,
+    /// Key in force at the measure. If None — key from
+    /// [crate::lilypond_render::RenderSettings] is used.
+    pub key: Option<KeySignature>,
+    /// Beat groups in units of time signature denominator. If None —
+    /// every beat is a group.
+    pub beat_grouping: Option<Vec<u8>>,
+    /// Rules of splitting events by beats.
+    pub meter_rules: MeterRules,
+    /// Tempo changes inside the measure, sorted by position.
+    pub tempo: Vec<TempoMark>,
+    /// Rehearsal marks, sections and breaks at the measure start.
+    pub marks: MeasureMarks,
+    /// Key, beat grouping and meter changes, placed at this measure.
+    /// Let parts merge them with track notations by position.
+    pub changes: Vec<MeasureNotations>,
 }
 impl MeasureInfo {
     pub fn new(index: u32, time_signature: TimeSignature) -> Self {
@@ -157,8 +386,152 @@ impl MeasureInfo {
             index,
             time_signature,
             length,
+            key: None,
+            beat_grouping: None,
+            meter_rules: MeterRules::default(),
+            tempo: Vec::new(),
+            marks: MeasureMarks::default(),
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn apply_notation(&mut self, notation: &MeasureNotations) {
+        if apply_part_notation(
+            notation,
+            &self.time_signature,
+            &mut self.key,
+            &mut self.beat_grouping,
+            &mut self.meter_rules,
+        ) {
+            return;
+        }
+        match notation {
+            // applied above.
+            MeasureNotations::Key(_)
+            | MeasureNotations::BeatGrouping(_)
+            | MeasureNotations::Meter(_) => (),
+            MeasureNotations::Partial(length) => {
+                if *length <= self.timeline_length().get() {
+                    self.length = Length::from(*length);
+                }
+            }
+            MeasureNotations::Tempo(text) => {
+                let mut mark = TempoMark::new(Fraction::from(0));
+                mark.text = Some(text.clone());
+                self.push_tempo(mark);
+            }
+            MeasureNotations::Rehearsal(name) => {
+                self.marks.rehearsal = Some(name.clone())
+            }
+            MeasureNotations::Section(name) => {
+                self.marks.section = Some(name.clone())
+            }
+            MeasureNotations::Barline(barline) => {
+                self.marks.barline = Some(*barline)
+            }
+            MeasureNotations::EndBarline(barline) => {
+                self.marks.end_barline = Some(*barline)
+            }
+            MeasureNotations::Break => self.marks.line_break = true,
+            MeasureNotations::RepeatStart => self.marks.repeat_start = true,
+            MeasureNotations::RepeatEnd(times) => {
+                self.marks.repeat_end = Some(*times)
+            }
+            MeasureNotations::Volta(number, length) => {
+                self.marks.volta = Some((*number, *length))
+            }
+            MeasureNotations::Jump(jump) => {
+                if !self.marks.jumps.contains(jump) {
+                    self.marks.jumps.push(*jump)
+                }
+            }
         }
     }
+
+    /// Add tempo mark, merging it with the mark at the same position.
+    pub fn push_tempo(&mut self, mark: TempoMark) {
+        match self
+            .tempo
+            .iter_mut()
+            .find(|existing| existing.position == mark.position)
+        {
+            Some(existing) => {
+                existing.bpm = mark.bpm.or(existing.bpm);
+                existing.text = mark.text.or(existing.text.take());
+                existing.ramp = mark.ramp.or(existing.ramp);
+                existing.ramp_end |= mark.ramp_end;
+            }
+            None => {
+                self.tempo.push(mark);
+                self.tempo.sort_by_key(|mark| mark.position);
+            }
+        }
+    }
+
+    /// Length of the measure on the timeline, as time signature
+    /// defines it.
+    pub fn timeline_length(&self) -> Length {
+        Length::from(&self.time_signature)
+    }
+    /// True, if measure is shorter, than its time signature.
+    pub fn is_partial(&self) -> bool {
+        self.length.get() < self.timeline_length().get()
+    }
+    /// Distance from the start of measure on the timeline to the start
+    /// of written part. Partial measure holds the end of the measure
+    /// on the timeline, as pickup does.
+    pub fn offset(&self) -> Fraction {
+        self.timeline_length().get() - self.length.get()
+    }
+}
+
+/// Err, if position, measured from the notated start of measure, is
+/// in the cut-off head of partial measure.
+fn notated_position(
+    measure: &MeasureInfo,
+    position: Fraction,
+) -> Result<Fraction, String> {
+    match position < Fraction::from(0) {
+        true => Err(format!(
+            "Position {} is before the start of partial measure {}",
+            position + measure.offset(),
+            measure.index
+        )),
+        false => Ok(position),
+    }
+}
+
+/// Apply notation, that every part keeps on its own:
+/// key, beat grouping or meter rules.
+///
+/// Returns false, if notation belongs to the timeline.
+pub(crate) fn apply_part_notation(
+    notation: &MeasureNotations,
+    time_signature: &TimeSignature,
+    key: &mut Option<KeySignature>,
+    beat_grouping: &mut Option<Vec<u8>>,
+    meter_rules: &mut MeterRules,
+) -> bool {
+    match notation {
+        MeasureNotations::Key(new_key) => *key = Some(*new_key),
+        MeasureNotations::BeatGrouping(groups) => {
+            if fits_time_signature(groups, time_signature) {
+                *beat_grouping = Some(groups.clone());
+            }
+        }
+        MeasureNotations::Meter(rules) => *meter_rules = *rules,
+        _ => return false,
+    }
+    true
+}
+
+/// True, if sum of beat groups is the numerator of time signature.
+pub fn fits_time_signature(
+    groups: &[u8],
+    time_signature: &TimeSignature,
+) -> bool {
+    groups.iter().map(|group| *group as u32).sum::<u32>()
+        == time_signature.numerator
 }
 
 #[cfg(test)]
@@ -166,9 +539,12 @@ mod tests {
     use fraction::Fraction;
     use rea_rs::TimeSignature;
 
-    use crate::primitives::{
-        position::{AbsolutePosition, RelativePosition},
-        Length,
+    use crate::{
+        notation::measure_notations::MeasureNotations,
+        primitives::{
+            position::{AbsolutePosition, RelativePosition},
+            Length, MeasureMarks, MeterRules, Ramp, TempoPoint,
+        },
     };
 
     use super::{MeasureInfo, TimeMap, TimeMapMeasures};
@@ -183,6 +559,12 @@ mod tests {
                 index: idx,
                 time_signature,
                 length,
+                key: None,
+                beat_grouping: None,
+                meter_rules: MeterRules::default(),
+                tempo: Vec::new(),
+                marks: MeasureMarks::default(),
+                changes: Vec::new(),
             });
         }
         measures
@@ -224,7 +606,13 @@ mod tests {
             MeasureInfo {
                 index: 2,
                 length: Length::from(&time_signature),
-                time_signature
+                time_signature,
+                key: None,
+                beat_grouping: None,
+                meter_rules: MeterRules::default(),
+                tempo: Vec::new(),
+                marks: MeasureMarks::default(),
+                changes: Vec::new(),
             }
         );
         let time_signature = TimeSignature::new(7, 8);
@@ -233,7 +621,13 @@ mod tests {
             MeasureInfo {
                 index: 4,
                 length: Length::from(&time_signature),
-                time_signature
+                time_signature,
+                key: None,
+                beat_grouping: None,
+                meter_rules: MeterRules::default(),
+                tempo: Vec::new(),
+                marks: MeasureMarks::default(),
+                changes: Vec::new(),
             }
         );
         let time_signature = TimeSignature::new(9, 8);
@@ -242,7 +636,13 @@ mod tests {
             MeasureInfo {
                 index: 5,
                 length: Length::from(&time_signature),
-                time_signature
+                time_signature,
+                key: None,
+                beat_grouping: None,
+                meter_rules: MeterRules::default(),
+                tempo: Vec::new(),
+                marks: MeasureMarks::default(),
+                changes: Vec::new(),
             }
         );
     }
@@ -323,4 +723,81 @@ mod tests {
             &relative
         );
     }
+
+    #[test]
+    fn test_partial_measure() -> Result<(), String> {
+        let mut time_map = time_map_1();
+        let quarter = Fraction::new(1u64, 4u64);
+        time_map.apply_measure_notations([(
+            1,
+            MeasureNotations::Partial(quarter),
+        )]);
+        assert!(time_map.get_measure_info(1).is_partial());
+        assert!(!time_map.get_measure_info(2).is_partial());
+        assert_eq!(
+            time_map.get_measure_info(1).length,
+            quarter.into()
+        );
+        // pickup is the last beat of the first measure on the timeline
+        assert_eq!(
+            time_map.get_absolute_position_of_measure(1),
+            AbsolutePosition::from(0.75)
+        );
+        assert_eq!(
+            time_map.get_absolute_position_of_measure(2),
+            AbsolutePosition::from(1.0)
+        );
+        assert_eq!(
+            time_map
+                .pos_relative_from_absolute(&AbsolutePosition::from(
+                    0.875
+                ))
+                .unwrap(),
+            RelativePosition::new(1, Fraction::new(1u64, 8u64))
+        );
+        assert_eq!(
+            time_map.notated_from_timeline(&RelativePosition::new(
+                1,
+                Fraction::new(7u64, 8u64)
+            ))?,
+            RelativePosition::new(1, Fraction::new(1u64, 8u64))
+        );
+        assert_eq!(
+            time_map
+                .notated_from_timeline(&RelativePosition::new(2, quarter))?,
+            RelativePosition::new(2, quarter)
+        );
+        // the cut-off head of pickup
+        assert!(time_map
+            .notated_from_timeline(&RelativePosition::new(1, quarter))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_tempo() {
+        let mut time_map = time_map_1();
+        time_map.apply_measure_notations([(
+            1,
+            MeasureNotations::Tempo("Allegro".to_string()),
+        )]);
+        time_map.apply_tempo([
+            TempoPoint::new(0.0.into(), 120.0, false),
+            TempoPoint::new(1.0.into(), 120.0, true),
+            TempoPoint::new(2.5.into(), 90.0, false),
+        ]);
+        let first = &time_map.get_measure_info(1).tempo;
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].bpm, Some(120.0));
+        assert_eq!(first[0].text, Some("Allegro".to_string()));
+        // the same tempo is not repeated, but ramp starts
+        let second = &time_map.get_measure_info(2).tempo;
+        assert_eq!(second[0].bpm, None);
+        assert_eq!(second[0].ramp, Some(Ramp::Ritardando));
+        let third = &time_map.get_measure_info(3).tempo;
+        assert_eq!(third[0].position, Fraction::new(1u64, 2u64));
+        assert_eq!(third[0].bpm, Some(90.0));
+        assert!(third[0].ramp_end);
+        assert!(time_map.get_measure_info(4).tempo.is_empty());
+    }
 }
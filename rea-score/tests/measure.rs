@@ -1,7 +1,7 @@
 use itertools::Itertools;
 use rea_rs::TimeSignature;
 use rea_score::primitives::{
-    EventInfo, Measure, Note, Pitch, RelativePosition,
+    EventInfo, Measure, MeterRules, Note, Pitch, RelativePosition,
 };
 
 #[test]
@@ -97,6 +97,103 @@ fn test_events_normalized_whole() {
         .count();
 }
 
+#[test]
+fn test_events_normalized_compound() {
+    let c3 = Note::new(Pitch::from_midi(60, None, None));
+    let mut measure = Measure::new(1, TimeSignature::new(6, 8));
+    measure
+        .insert(EventInfo::new(
+            RelativePosition::new(1, 0.0.into()),
+            0.75.into(),
+            rea_score::primitives::EventType::Note(c3.clone()),
+        ))
+        .expect("Can not insert event");
+    let events = vec![EventInfo::new(
+        RelativePosition::new(1, 0.0.into()),
+        0.75.into(),
+        rea_score::primitives::EventType::Note(c3.clone()),
+    )];
+    measure
+        .get_events_normalized()
+        .expect("Can not get normalized events")
+        .into_iter()
+        .zip_eq(events)
+        .map(|(a, b)| assert_eq!(a, b))
+        .count();
+}
+
+#[test]
+fn test_events_normalized_by_meter_rules() {
+    let c3 = Note::new(Pitch::from_midi(60, None, None));
+    let mut c3_tied = c3.clone();
+    c3_tied.set_tie(true);
+    let mut measure = Measure::new(1, TimeSignature::new(6, 8));
+    measure
+        .insert(EventInfo::new(
+            RelativePosition::new(1, 0.25.into()),
+            0.25.into(),
+            rea_score::primitives::EventType::Note(c3.clone()),
+        ))
+        .expect("Can not insert event");
+    // quarter, crossing the middle of 6/8, is split
+    let events = vec![
+        EventInfo::new(
+            RelativePosition::new(1, 0.0.into()),
+            0.25.into(),
+            rea_score::primitives::EventType::Rest,
+        ),
+        EventInfo::new(
+            RelativePosition::new(1, 0.25.into()),
+            0.125.into(),
+            rea_score::primitives::EventType::Note(c3_tied.clone()),
+        ),
+        EventInfo::new(
+            RelativePosition::new(1, 0.375.into()),
+            0.125.into(),
+            rea_score::primitives::EventType::Note(c3.clone()),
+        ),
+        EventInfo::new(
+            RelativePosition::new(1, 0.5.into()),
+            0.25.into(),
+            rea_score::primitives::EventType::Rest,
+        ),
+    ];
+    measure
+        .get_events_normalized()
+        .expect("Can not get normalized events")
+        .into_iter()
+        .zip_eq(events)
+        .map(|(a, b)| assert_eq!(a, b))
+        .count();
+
+    // every eighth is a beat
+    measure.set_meter_rules(MeterRules::Beats);
+    let events = vec![
+        EventInfo::new(
+            RelativePosition::new(1, 0.0.into()),
+            0.25.into(),
+            rea_score::primitives::EventType::Rest,
+        ),
+        EventInfo::new(
+            RelativePosition::new(1, 0.25.into()),
+            0.25.into(),
+            rea_score::primitives::EventType::Note(c3.clone()),
+        ),
+        EventInfo::new(
+            RelativePosition::new(1, 0.5.into()),
+            0.25.into(),
+            rea_score::primitives::EventType::Rest,
+        ),
+    ];
+    measure
+        .get_events_normalized()
+        .expect("Can not get normalized events")
+        .into_iter()
+        .zip_eq(events)
+        .map(|(a, b)| assert_eq!(a, b))
+        .count();
+}
+
 // #[test]
 // fn test_events_normalized_3_8_as_dotted() {
 //     let c3 = Note::new(Pitch::from_midi(60, None, None));
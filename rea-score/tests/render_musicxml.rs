@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use fraction::Fraction;
+use musical_note::Key;
+use rea_rs::TimeSignature;
+use rea_score::{
+    dom::{Part, Staff, Voice},
+    lilypond_render::RenderSettings,
+    musicxml_render::{MusicXmlContext, RendersToMusicXml},
+    notation::{
+        note_notations::{NoteHead, NoteNotations},
+        NotationType,
+    },
+    primitives::{
+        AbsolutePosition, EventInfo, EventType, MeasureInfo, Note, Pitch,
+        RelativePosition, TimeMap,
+    },
+};
+
+#[test]
+fn part() {
+    let time_map = Arc::new(TimeMap::new(
+        vec![
+            MeasureInfo::new(1, TimeSignature::new(4, 4)),
+            MeasureInfo::new(2, TimeSignature::new(3, 4)),
+        ],
+        AbsolutePosition::from(0.0),
+    ));
+    let mut voice = Voice::from(time_map.clone());
+    voice.index = 1;
+    let mut note = Note::new(Pitch::from_midi(61, None, None));
+    note.apply_notation(NotationType::Note(NoteNotations::NoteHead(
+        NoteHead::Cross,
+    )))
+    .expect("can not apply notation");
+    voice
+        .insert_event(EventInfo::new(
+            RelativePosition::new(1, Fraction::new(3_u64, 4_u64)),
+            Fraction::new(1_u64, 2_u64).into(),
+            EventType::Note(note),
+        ))
+        .expect("can not insert event");
+    let part = Part::new(
+        time_map.clone(),
+        vec![Staff::new(time_map, 1, vec![voice])],
+    );
+    let mut ctx = MusicXmlContext::new(RenderSettings::new(Key::new(
+        musical_note::NoteName::C,
+        musical_note::Accidental::Sharp,
+        musical_note::Scale::Major,
+    )));
+    let xml = part.render_musicxml(&mut ctx).expect("can not render part");
+    assert!(xml.contains(r#"<score-partwise version="4.0">"#));
+    assert!(xml.contains("<divisions>3360</divisions>"));
+    assert!(xml.contains("<beats>4</beats><beat-type>4</beat-type>"));
+    assert!(xml.contains("<beats>3</beats><beat-type>4</beat-type>"));
+    assert!(xml.contains(
+        "<pitch><step>C</step><alter>1</alter><octave>4</octave></pitch>"
+    ));
+    assert!(xml.contains(r#"<tie type="start"/>"#));
+    assert!(xml.contains(r#"<tie type="stop"/>"#));
+    assert!(xml.contains("<notehead>x</notehead>"));
+    assert_eq!(xml.matches("<measure number=").count(), 2);
+}
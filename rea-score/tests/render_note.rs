@@ -1,3 +1,4 @@
+use fraction::Fraction;
 use musical_note::Key;
 
 use rea_score::{
@@ -88,3 +89,25 @@ fn test() {
         r"\override NoteHead.style = #'cross es''\f~"
     );
 }
+
+#[test]
+fn test_tuplet_notation_on_note() {
+    let mut note = Note::new(Pitch::from_midi(60, None, None));
+    note.apply_notation(NotationType::Chord(ChordNotations::TupletRate(
+        vec![Fraction::new(3_u8, 2_u8)],
+    )))
+    .expect("can not apply notation");
+    note.apply_notation(NotationType::Chord(ChordNotations::TupletEnd(1)))
+        .expect("can not apply notation");
+    assert_eq!(
+        &note.render_lilypond(
+            "8".to_string(),
+            &RenderSettings::new(Key::new(
+                musical_note::NoteName::C,
+                musical_note::Accidental::White,
+                musical_note::Scale::Major,
+            ))
+        ),
+        "c'8"
+    );
+}
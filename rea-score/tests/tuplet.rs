@@ -1,9 +1,15 @@
+use std::sync::Arc;
+
 use fraction::Fraction;
+use itertools::Itertools;
+use rea_rs::TimeSignature;
 use rea_score::{
+    dom::{Voice, VoiceRenderOptions},
     lilypond_render::RendersToLilypond,
+    notation::{chord_notations::ChordNotations, NotationType},
     primitives::{
-        event::Tuplet, EventInfo, Length, Note, Pitch,
-        RelativePosition,
+        event::Tuplet, AbsolutePosition, EventInfo, Length, Measure,
+        MeasureInfo, Note, Pitch, RelativePosition, TimeMap,
     },
 };
 
@@ -75,3 +81,105 @@ fn triplet() {
         r"\tuplet 3/2 { c'8 r8 < c' d' >8 }"
     );
 }
+
+#[test]
+fn nested_tuplet() {
+    let mut measure = Measure::new(1, TimeSignature::new(4, 4));
+    let note = |midi: u8, position: Fraction, length: Fraction| {
+        EventInfo::new(
+            RelativePosition::new(1, position),
+            Length::from(length),
+            rea_score::primitives::EventType::Note(Note::new(
+                Pitch::from_midi(midi, None, None),
+            )),
+        )
+    };
+    // triplet of quintuplet sixteenths inside the quintuplet
+    let (thirtieth, twentieth) =
+        (Fraction::new(1_u8, 30_u8), Fraction::new(1_u8, 20_u8));
+    let mut events = vec![
+        note(60, Fraction::from(0), thirtieth),
+        note(62, thirtieth, thirtieth),
+        note(64, thirtieth * 2, thirtieth),
+        note(65, twentieth * 2, twentieth),
+        note(67, twentieth * 3, twentieth),
+        note(69, twentieth * 4, twentieth),
+    ];
+    events[0]
+        .push_notation(NotationType::Chord(
+            ChordNotations::TupletRate(vec![
+                Fraction::new(5_u8, 4_u8),
+                Fraction::new(3_u8, 2_u8),
+            ]),
+        ))
+        .unwrap();
+    events[2]
+        .push_notation(NotationType::Chord(
+            ChordNotations::TupletEnd(1),
+        ))
+        .unwrap();
+    events[5]
+        .push_notation(NotationType::Chord(
+            ChordNotations::TupletEnd(1),
+        ))
+        .unwrap();
+    for event in events {
+        measure.insert(event).unwrap();
+    }
+    let rendered = measure
+        .get_events_normalized()
+        .unwrap()
+        .iter()
+        .map(|ev| ev.render_lilypond())
+        .join(" ");
+    assert!(rendered.starts_with(
+        r"\tuplet 5/4 { \tuplet 3/2 { c'16 d'16 e'16 } f'16 g'16 a'16 }"
+    ));
+}
+
+#[test]
+fn tuplet_across_barline() {
+    let time_map = Arc::new(TimeMap::new(
+        vec![
+            MeasureInfo::new(1, TimeSignature::new(4, 4)),
+            MeasureInfo::new(2, TimeSignature::new(4, 4)),
+        ],
+        AbsolutePosition::from(0.0),
+    ));
+    let mut voice = Voice::from(time_map);
+    let sixth = Fraction::new(1_u8, 6_u8);
+    let note = |midi: u8, measure: u32, position: Fraction| {
+        EventInfo::new(
+            RelativePosition::new(measure, position),
+            Length::from(sixth),
+            rea_score::primitives::EventType::Note(Note::new(
+                Pitch::from_midi(midi, None, None),
+            )),
+        )
+    };
+    // quarter triplets from the last beat, the second one crosses
+    // the barline
+    let mut events = vec![
+        note(60, 1, Fraction::new(3_u8, 4_u8)),
+        note(62, 1, Fraction::new(11_u8, 12_u8)),
+        note(64, 2, Fraction::new(1_u8, 12_u8)),
+    ];
+    events[0]
+        .push_notation(NotationType::Chord(
+            ChordNotations::TupletRate(vec![Fraction::new(
+                3_u8, 2_u8,
+            )]),
+        ))
+        .unwrap();
+    events[2]
+        .push_notation(NotationType::Chord(
+            ChordNotations::TupletEnd(1),
+        ))
+        .unwrap();
+    for event in events {
+        voice.insert_event(event).unwrap();
+    }
+    let rendered = voice.render_measures(VoiceRenderOptions::default());
+    assert!(rendered.contains(r"\tuplet 3/2 { c'4 d'8~ } |"));
+    assert!(rendered.contains(r"\tuplet 3/2 { d'8 e'4 }"));
+}